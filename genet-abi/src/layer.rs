@@ -179,6 +179,11 @@ impl Layer {
         self.class.id()
     }
 
+    /// Returns the class of self.
+    pub fn class(&self) -> Fixed<LayerClass> {
+        self.class.clone()
+    }
+
     /// Returns the type of self.
     pub fn data(&self) -> ByteSlice {
         self.class.data(self)
@@ -1,5 +1,10 @@
-use context::Context;
-use genet_abi::{token::Token, variant::Variant};
+use context::{Context, Field};
+use function::Function;
+use genet_abi::{attr::Attr, layer::Layer, token::Token, variant::Variant};
+use pattern::Pattern;
+use prefix::Prefix;
+use set::Set;
+use std::collections::HashSet;
 use variant::VariantExt;
 
 #[derive(PartialEq, Clone, Debug)]
@@ -7,6 +12,7 @@ pub enum Expr {
     Literal(Variant),
     Token(Token),
     Macro(String),
+    Prefix(Prefix),
     CmpEq(Box<Expr>, Box<Expr>),
     CmpNotEq(Box<Expr>, Box<Expr>),
     CmpLt(Box<Expr>, Box<Expr>),
@@ -18,14 +24,36 @@ pub enum Expr {
     LogicalNegation(Box<Expr>),
     UnaryPlus(Box<Expr>),
     UnaryNegation(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    Rem(Box<Expr>, Box<Expr>),
+    BitwiseAnd(Box<Expr>, Box<Expr>),
+    BitwiseOr(Box<Expr>, Box<Expr>),
+    BitwiseXor(Box<Expr>, Box<Expr>),
+    BitwiseNot(Box<Expr>),
+    ShiftLeft(Box<Expr>, Box<Expr>),
+    ShiftRight(Box<Expr>, Box<Expr>),
+    Set(Set),
+    In(Box<Expr>, Box<Expr>),
+    Pattern(Pattern),
+    Contains(Box<Expr>, Box<Expr>),
+    StartsWith(Box<Expr>, Box<Expr>),
+    Matches(Box<Expr>, Box<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+    Slice(Box<Expr>, Option<Box<Expr>>, Option<Box<Expr>>),
+    Call(Function, Vec<Expr>),
+    Occurrence(Token, i64, Token),
+    Frame(Field),
 }
 
 impl Expr {
     pub fn eval(&self, ctx: &Context) -> Variant {
         match self {
             Expr::Literal(v) => v.clone(),
-            Expr::CmpEq(l, r) => Variant::Bool(l.eval(ctx).op_eq(&r.eval(ctx))),
-            Expr::CmpNotEq(l, r) => Variant::Bool(!l.eval(ctx).op_eq(&r.eval(ctx))),
+            Expr::CmpEq(l, r) => Variant::Bool(Self::eval_eq(l, r, ctx)),
+            Expr::CmpNotEq(l, r) => Variant::Bool(!Self::eval_eq(l, r, ctx)),
             Expr::CmpLt(l, r) => Variant::Bool(l.eval(ctx).op_lt(&r.eval(ctx))),
             Expr::CmpGt(l, r) => Variant::Bool(l.eval(ctx).op_gt(&r.eval(ctx))),
             Expr::CmpLte(l, r) => Variant::Bool(l.eval(ctx).op_lte(&r.eval(ctx))),
@@ -39,25 +67,532 @@ impl Expr {
             Expr::LogicalNegation(v) => Variant::Bool(!v.eval(ctx).is_truthy()),
             Expr::UnaryPlus(v) => v.eval(ctx).op_unary_plus(),
             Expr::UnaryNegation(v) => v.eval(ctx).op_unary_negation(),
-            Expr::Token(t) => {
-                for layer in ctx.layers().iter().rev() {
-                    if layer.id() == *t {
-                        return Variant::Bool(true);
-                    }
-                    if let Some(attr) = layer
-                        .headers()
-                        .iter()
-                        .chain(layer.attrs().iter())
-                        .find(|a| a.id() == *t)
-                    {
-                        if let Ok(val) = attr.try_get(layer) {
-                            return val;
-                        }
-                    }
+            Expr::Add(l, r) => l.eval(ctx).op_add(&r.eval(ctx)),
+            Expr::Sub(l, r) => l.eval(ctx).op_sub(&r.eval(ctx)),
+            Expr::Mul(l, r) => l.eval(ctx).op_mul(&r.eval(ctx)),
+            Expr::Div(l, r) => l.eval(ctx).op_div(&r.eval(ctx)),
+            Expr::Rem(l, r) => l.eval(ctx).op_rem(&r.eval(ctx)),
+            Expr::BitwiseAnd(l, r) => l.eval(ctx).op_bitwise_and(&r.eval(ctx)),
+            Expr::BitwiseOr(l, r) => l.eval(ctx).op_bitwise_or(&r.eval(ctx)),
+            Expr::BitwiseXor(l, r) => l.eval(ctx).op_bitwise_xor(&r.eval(ctx)),
+            Expr::BitwiseNot(v) => v.eval(ctx).op_bitwise_not(),
+            Expr::ShiftLeft(l, r) => l.eval(ctx).op_shl(&r.eval(ctx)),
+            Expr::ShiftRight(l, r) => l.eval(ctx).op_shr(&r.eval(ctx)),
+            Expr::Set(_) => Variant::Nil,
+            Expr::In(l, r) => match r.as_ref() {
+                Expr::Set(set) => {
+                    let (value, typ) = l.eval_typed(ctx);
+                    Variant::Bool(set.contains_typed(&value, typ))
                 }
-                Variant::Nil
-            }
+                _ => Variant::Bool(false),
+            },
+            Expr::Pattern(_) => Variant::Nil,
+            Expr::Contains(l, r) => Variant::Bool(l.eval(ctx).op_contains(&r.eval(ctx))),
+            Expr::StartsWith(l, r) => Variant::Bool(l.eval(ctx).op_starts_with(&r.eval(ctx))),
+            Expr::Matches(l, r) => match r.as_ref() {
+                Expr::Pattern(p) => Variant::Bool(p.is_match(&l.eval(ctx))),
+                _ => Variant::Bool(false),
+            },
+            Expr::Index(v, i) => v.eval(ctx).op_index(&i.eval(ctx)),
+            Expr::Slice(v, start, end) => v.eval(ctx).op_slice(
+                start.as_ref().map(|s| s.eval(ctx)).as_ref(),
+                end.as_ref().map(|e| e.eval(ctx)).as_ref(),
+            ),
+            Expr::Call(f, args) => f.call(ctx, args),
+            Expr::Token(t) => Self::lookup(ctx, *t),
+            Expr::Occurrence(id, index, t) => Self::occurrence(ctx, *id, *index)
+                .and_then(|layer| Self::eval_token(layer, *t))
+                .unwrap_or(Variant::Nil),
+            Expr::Frame(field) => ctx.metadata().map_or(Variant::Nil, |m| m.get(*field)),
             Expr::Macro(_) => Variant::Nil,
+            Expr::Prefix(p) => Variant::Buffer(p.network().into_boxed_slice()),
+        }
+    }
+
+    /// Returns the tokens in the expression, including the layer ids of occurrences.
+    pub fn tokens(&self) -> HashSet<Token> {
+        let mut tokens = HashSet::new();
+        self.visit(&mut |e| match e {
+            Expr::Token(id) | Expr::Occurrence(id, ..) => {
+                tokens.insert(*id);
+            }
+            _ => {}
+        });
+        tokens
+    }
+
+    /// Calls `f` for the expression and all of its descendants.
+    pub fn visit<F: FnMut(&Expr)>(&self, f: &mut F) {
+        f(self);
+        match self {
+            Expr::Literal(_)
+            | Expr::Token(_)
+            | Expr::Macro(_)
+            | Expr::Prefix(_)
+            | Expr::Set(_)
+            | Expr::Pattern(_)
+            | Expr::Occurrence(..)
+            | Expr::Frame(_) => {}
+            Expr::LogicalNegation(v)
+            | Expr::UnaryPlus(v)
+            | Expr::UnaryNegation(v)
+            | Expr::BitwiseNot(v) => v.visit(f),
+            Expr::CmpEq(l, r)
+            | Expr::CmpNotEq(l, r)
+            | Expr::CmpLt(l, r)
+            | Expr::CmpGt(l, r)
+            | Expr::CmpLte(l, r)
+            | Expr::CmpGte(l, r)
+            | Expr::LogicalAnd(l, r)
+            | Expr::LogicalOr(l, r)
+            | Expr::Add(l, r)
+            | Expr::Sub(l, r)
+            | Expr::Mul(l, r)
+            | Expr::Div(l, r)
+            | Expr::Rem(l, r)
+            | Expr::BitwiseAnd(l, r)
+            | Expr::BitwiseOr(l, r)
+            | Expr::BitwiseXor(l, r)
+            | Expr::ShiftLeft(l, r)
+            | Expr::ShiftRight(l, r)
+            | Expr::In(l, r)
+            | Expr::Contains(l, r)
+            | Expr::StartsWith(l, r)
+            | Expr::Matches(l, r)
+            | Expr::Index(l, r) => {
+                l.visit(f);
+                r.visit(f);
+            }
+            Expr::Slice(v, start, end) => {
+                v.visit(f);
+                for bound in start.iter().chain(end.iter()) {
+                    bound.visit(f);
+                }
+            }
+            Expr::Call(_, args) => {
+                for arg in args {
+                    arg.visit(f);
+                }
+            }
+        }
+    }
+
+    /// Resolves a token in the focused layer if any, otherwise from the innermost layer.
+    pub(crate) fn lookup(ctx: &Context, id: Token) -> Variant {
+        Self::lookup_typed(ctx, id).0
+    }
+
+    /// Resolves a token like `lookup`, along with the type of the attribute.
+    ///
+    /// The type is `None` if the token is not an attribute.
+    pub(crate) fn lookup_typed(ctx: &Context, id: Token) -> (Variant, Option<Token>) {
+        if let Some(layer) = ctx.focused_layer() {
+            return Self::eval_token_typed(layer, id).unwrap_or((Variant::Nil, None));
+        }
+        for layer in ctx.layers().iter().rev() {
+            if let Some(val) = Self::eval_token_typed(layer, id) {
+                return val;
+            }
+        }
+        (Variant::Nil, None)
+    }
+
+    /// Evaluates an operand along with the type of the attribute it refers to, if any.
+    fn eval_typed(&self, ctx: &Context) -> (Variant, Option<Token>) {
+        match self {
+            Expr::Token(t) => Self::lookup_typed(ctx, *t),
+            Expr::Occurrence(id, index, t) => Self::occurrence(ctx, *id, *index)
+                .and_then(|layer| Self::eval_token_typed(layer, *t))
+                .unwrap_or((Variant::Nil, None)),
+            _ => (self.eval(ctx), None),
+        }
+    }
+
+    /// Finds the layer or the attribute a token or an occurrence refers to,
+    /// regardless of its value.
+    ///
+    /// Returns the layer and, unless the token is the layer id itself, the attribute.
+    pub(crate) fn resolve<'a>(&self, ctx: &Context<'a>) -> Option<(&'a Layer, Option<&'a Attr>)> {
+        match self {
+            Expr::Token(t) => match ctx.focused_layer() {
+                Some(layer) => Self::find_token(layer, *t),
+                None => ctx
+                    .layers()
+                    .iter()
+                    .rev()
+                    .filter_map(|layer| Self::find_token(layer, *t))
+                    .next(),
+            },
+            Expr::Occurrence(id, index, t) => {
+                Self::occurrence(ctx, *id, *index).and_then(|layer| Self::find_token(layer, *t))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true if the layer has any of the tokens as its id or as an attribute id.
+    pub(crate) fn has_any(layer: &Layer, tokens: &HashSet<Token>) -> bool {
+        tokens.contains(&layer.id())
+            || layer
+                .headers()
+                .iter()
+                .chain(layer.attrs().iter())
+                .any(|attr| tokens.contains(&attr.id()))
+    }
+
+    fn occurrence<'a>(ctx: &Context<'a>, id: Token, index: i64) -> Option<&'a Layer> {
+        let layers = ctx
+            .layers()
+            .iter()
+            .filter(|layer| layer.id() == id)
+            .collect::<Vec<_>>();
+        let index = if index < 0 {
+            layers.len() as i64 + index
+        } else {
+            index
+        };
+        if index < 0 {
+            return None;
+        }
+        layers.get(index as usize).map(|layer| &***layer)
+    }
+
+    fn find_token(layer: &Layer, id: Token) -> Option<(&Layer, Option<&Attr>)> {
+        if layer.id() == id {
+            return Some((layer, None));
         }
+        layer
+            .headers()
+            .iter()
+            .chain(layer.attrs().iter())
+            .find(|a| a.id() == id)
+            .map(|attr| (layer, Some(&**attr)))
+    }
+
+    fn eval_token(layer: &Layer, id: Token) -> Option<Variant> {
+        Self::eval_token_typed(layer, id).map(|(val, _)| val)
+    }
+
+    fn eval_token_typed(layer: &Layer, id: Token) -> Option<(Variant, Option<Token>)> {
+        if layer.id() == id {
+            return Some((Variant::Bool(true), None));
+        }
+        layer
+            .headers()
+            .iter()
+            .chain(layer.attrs().iter())
+            .find(|a| a.id() == id)
+            .and_then(|attr| attr.try_get(layer).ok().map(|val| (val, Some(attr.typ()))))
+    }
+
+    fn eval_eq(lhs: &Expr, rhs: &Expr, ctx: &Context) -> bool {
+        match (lhs, rhs) {
+            (Expr::Prefix(p), v) | (v, Expr::Prefix(p)) => match v.eval_typed(ctx) {
+                (value, Some(typ)) => p.contains_typed(&value, typ),
+                (value, None) => p.contains(&value),
+            },
+            _ => lhs.eval(ctx).op_eq(&rhs.eval(ctx)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use context::Context;
+    use genet_abi::variant::Variant;
+    use parser::parse;
+
+    fn eval(filter: &str) -> Variant {
+        parse(filter).unwrap().eval(&Context::new(&[]))
+    }
+
+    #[test]
+    fn prefix() {
+        assert_eq!(eval("@10.1.2.3 == @10.0.0.0/8"), Variant::Bool(true));
+        assert_eq!(eval("@10.0.0.0/8 == @10.1.2.3"), Variant::Bool(true));
+        assert_eq!(eval("@11.1.2.3 == @10.0.0.0/8"), Variant::Bool(false));
+        assert_eq!(eval("@11.1.2.3 != @10.0.0.0/8"), Variant::Bool(true));
+        assert_eq!(eval("@fe80::1 == @10.0.0.0/8"), Variant::Bool(false));
+        assert_eq!(eval("@fe80::1 == @fe80::/10"), Variant::Bool(true));
+        assert_eq!(
+            eval("@2001:db8::1 in {@10.0.0.0/8, @2001:db8::/32}"),
+            Variant::Bool(true)
+        );
+    }
+
+    #[test]
+    fn prefix_type() {
+        use genet_abi::{
+            attr::{Attr, AttrClass},
+            fixed::{Fixed, MutFixed},
+            layer::{Layer, LayerClass},
+            slice::ByteSlice,
+            token::Token,
+        };
+
+        let class = Fixed::new(LayerClass::builder(Token::from("ipv4")).build());
+        let mut layer = Layer::new(class, ByteSlice::new());
+        for (id, typ) in &[("ipv4.src", "@ipv4:addr"), ("ipv4.id", "")] {
+            let attr = AttrClass::builder(*id)
+                .typ(*typ)
+                .value(Variant::Buffer(vec![10, 0, 0, 1].into_boxed_slice()))
+                .build();
+            layer.add_attr(Attr::builder(Fixed::new(attr)).build());
+        }
+        let layers = vec![MutFixed::new(layer)];
+        let ctx = Context::new(&layers);
+        let eval = |filter: &str| parse(filter).unwrap().eval(&ctx);
+
+        assert_eq!(eval("ipv4.src == @10.0.0.0/8"), Variant::Bool(true));
+        assert_eq!(eval("ipv4[0].src == @10.0.0.0/8"), Variant::Bool(true));
+        assert_eq!(eval("ipv4.src in {@10.0.0.0/8}"), Variant::Bool(true));
+        assert_eq!(eval("ipv4.id == @10.0.0.0/8"), Variant::Bool(false));
+        assert_eq!(eval("ipv4.id in {@10.0.0.0/8}"), Variant::Bool(false));
+        assert_eq!(eval("ipv4.src == @::/0"), Variant::Bool(false));
+    }
+
+    #[test]
+    fn bytes() {
+        assert_eq!(
+            eval(r#""example.com" contains "ample""#),
+            Variant::Bool(true)
+        );
+        assert_eq!(
+            eval(r#""example.com" startsWith "ample""#),
+            Variant::Bool(false)
+        );
+        assert_eq!(eval(r#"x"474554" startsWith 0x4745"#), Variant::Bool(true));
+        assert_eq!(eval(r#"x"474554" startsWith 0x0047"#), Variant::Bool(false));
+        assert_eq!(eval(r#"x"004745" startsWith 0x0047"#), Variant::Bool(true));
+        assert_eq!(
+            eval(r#""ns.corp" matches "^.*\\.corp$""#),
+            Variant::Bool(true)
+        );
+        assert_eq!(
+            eval(r#""ns.corp.com" matches "^.*\\.corp$""#),
+            Variant::Bool(false)
+        );
+        assert_eq!(eval(r#"x"474554" matches "^GE""#), Variant::Bool(true));
+        assert_eq!(eval(r#"1 matches "1""#), Variant::Bool(false));
+    }
+
+    #[test]
+    fn index() {
+        assert_eq!(eval(r#"x"0a0b0c0d"[0]"#), Variant::UInt64(0x0a));
+        assert_eq!(eval(r#"x"0a0b0c0d"[-1]"#), Variant::UInt64(0x0d));
+        assert_eq!(eval(r#"x"0a0b0c0d"[4]"#), Variant::Nil);
+        assert_eq!(eval(r#"x"0a0b0c0d"[-5]"#), Variant::Nil);
+        assert_eq!(
+            eval(r#""abc"[1]"#),
+            Variant::String("b".to_string().into_boxed_str())
+        );
+        assert_eq!(eval(r#"@192.168.0.1[1] == 168"#), Variant::Bool(true));
+        assert_eq!(eval("10[0]"), Variant::Nil);
+    }
+
+    #[test]
+    fn call() {
+        assert_eq!(eval(r#"len("h\u00e9llo")"#), Variant::UInt64(5));
+        assert_eq!(eval(r#"len(x"474554")"#), Variant::UInt64(3));
+        assert_eq!(eval("len(1)"), Variant::Nil);
+        assert_eq!(
+            eval(r#"lower("Example.COM") == "example.com""#),
+            Variant::Bool(true)
+        );
+        assert_eq!(
+            eval(r#"upper(x"676574")"#),
+            Variant::Buffer(b"GET".to_vec().into_boxed_slice())
+        );
+        assert_eq!(eval("count(ipv4)"), Variant::UInt64(0));
+        assert_eq!(eval("abs(-5)"), Variant::UInt64(5));
+        assert_eq!(eval("abs(-1.5)"), Variant::Float64(1.5));
+        assert_eq!(eval(r#"hex(x"0aff")"#), eval(r#""0aff""#));
+        assert_eq!(eval("hex(255)"), eval(r#""ff""#));
+        assert_eq!(eval(r#"int("0x10") + int("-3")"#), Variant::UInt64(13));
+        assert_eq!(eval(r#"int(x"0100")"#), Variant::UInt64(256));
+        assert_eq!(eval("int(2.9)"), Variant::UInt64(2));
+        assert_eq!(eval(r#"int("--1")"#), Variant::Nil);
+    }
+
+    #[test]
+    fn slice() {
+        assert_eq!(
+            eval(r#"x"0a0b0c0d"[1:3]"#),
+            Variant::Buffer(vec![0x0b, 0x0c].into_boxed_slice())
+        );
+        assert_eq!(
+            eval(r#"x"0a0b0c0d"[:2]"#),
+            Variant::Buffer(vec![0x0a, 0x0b].into_boxed_slice())
+        );
+        assert_eq!(
+            eval(r#"x"0a0b0c0d"[-2:]"#),
+            Variant::Buffer(vec![0x0c, 0x0d].into_boxed_slice())
+        );
+        assert_eq!(
+            eval(r#"x"0a0b0c0d"[2:100]"#),
+            Variant::Buffer(vec![0x0c, 0x0d].into_boxed_slice())
+        );
+        assert_eq!(
+            eval(r#"x"0a0b0c0d"[3:1]"#),
+            Variant::Buffer(Vec::new().into_boxed_slice())
+        );
+        assert_eq!(
+            eval(r#""h\u00e9llo"[1:-1]"#),
+            Variant::String("\u{e9}ll".to_string().into_boxed_str())
+        );
+        assert_eq!(eval(r#"x"474554"[:]"#), eval(r#"x"474554""#));
+    }
+
+    #[test]
+    fn occurrence() {
+        use genet_abi::{
+            attr::{Attr, AttrClass},
+            fixed::{Fixed, MutFixed},
+            layer::{Layer, LayerClass},
+            slice::ByteSlice,
+            token::Token,
+        };
+
+        fn ipv4(attrs: &[(&str, u64)]) -> MutFixed<Layer> {
+            let class = Fixed::new(LayerClass::builder(Token::from("ipv4")).build());
+            let mut layer = Layer::new(class, ByteSlice::new());
+            for (id, value) in attrs {
+                let attr = Fixed::new(AttrClass::builder(*id).value(*value).build());
+                layer.add_attr(Attr::builder(attr).build());
+            }
+            MutFixed::new(layer)
+        }
+        let eth = Fixed::new(LayerClass::builder(Token::from("eth")).build());
+        let layers = vec![
+            MutFixed::new(Layer::new(eth, ByteSlice::new())),
+            ipv4(&[("ipv4.src", 1)]),
+            ipv4(&[("ipv4.src", 2)]),
+        ];
+        let ctx = Context::new(&layers);
+        let eval = |filter: &str| parse(filter).unwrap().eval(&ctx);
+
+        assert_eq!(eval("ipv4.src"), Variant::UInt64(2));
+        assert_eq!(eval("ipv4[0].src"), Variant::UInt64(1));
+        assert_eq!(eval("ipv4[1].src"), Variant::UInt64(2));
+        assert_eq!(eval("ipv4[-1].src"), Variant::UInt64(2));
+        assert_eq!(eval("ipv4[-2].src"), Variant::UInt64(1));
+        assert_eq!(eval("ipv4[2].src"), Variant::Nil);
+        assert_eq!(eval("ipv4[-3].src"), Variant::Nil);
+        assert_eq!(eval("eth[0].src"), Variant::Nil);
+
+        assert_eq!(eval("any(ipv4.src == 1)"), Variant::Bool(true));
+        assert_eq!(eval("any(ipv4.src == 3)"), Variant::Bool(false));
+        assert_eq!(eval("all(ipv4.src > 0)"), Variant::Bool(true));
+        assert_eq!(eval("all(ipv4.src == 1)"), Variant::Bool(false));
+        assert_eq!(eval("all(tcp.dst == 80)"), Variant::Bool(false));
+        assert_eq!(eval("any(ipv4.src == 1 && eth)"), Variant::Bool(false));
+
+        let layers = vec![
+            ipv4(&[("ipv4.src", 1), ("ipv4.dst", 2)]),
+            ipv4(&[("ipv4.dst", 3)]),
+        ];
+        let ctx = Context::new(&layers);
+        let eval = |filter: &str| parse(filter).unwrap().eval(&ctx);
+
+        assert_eq!(
+            eval("any(ipv4.src == 1 && ipv4.dst == 3)"),
+            Variant::Bool(false)
+        );
+        assert_eq!(eval("all(ipv4.dst > 2)"), Variant::Bool(false));
+        assert_eq!(eval("all(ipv4.src == 1)"), Variant::Bool(true));
+        assert_eq!(
+            eval("any(ipv4.src == 1 && !has(ipv4.dst))"),
+            Variant::Bool(false)
+        );
+    }
+
+    #[test]
+    fn frame() {
+        use context::Metadata;
+
+        let metadata = Metadata {
+            index: 1200,
+            timestamp: 1_704_103_200.5,
+            relative: 3.0,
+            delta: 0.02,
+            length: 1514,
+            captured_length: 128,
+            input: Some(2),
+        };
+        let ctx = Context::with_metadata(&[], metadata);
+        let eval = |filter: &str| parse(filter).unwrap().eval(&ctx);
+
+        assert_eq!(eval("frame.index > 1000"), Variant::Bool(true));
+        assert_eq!(
+            eval("frame.time >= t\"2024-01-01T10:00:00Z\""),
+            Variant::Bool(true)
+        );
+        assert_eq!(eval("frame.delta > 10ms"), Variant::Bool(true));
+        assert_eq!(eval("frame.timeRelative < 2s"), Variant::Bool(false));
+        assert_eq!(
+            eval("frame.length - frame.capturedLength"),
+            Variant::UInt64(1386)
+        );
+        assert_eq!(eval("frame.input == 2"), Variant::Bool(true));
+        assert_eq!(
+            parse("frame.index").unwrap().eval(&Context::new(&[])),
+            Variant::Nil
+        );
+    }
+
+    #[test]
+    fn has() {
+        use genet_abi::{
+            attr::{Attr, AttrClass},
+            cast::Cast,
+            fixed::{Fixed, MutFixed},
+            layer::{Layer, LayerClass},
+            slice::ByteSlice,
+            token::Token,
+        };
+        use std::io::{Error, ErrorKind, Result};
+
+        #[derive(Clone)]
+        struct Malformed;
+
+        impl Cast for Malformed {
+            fn cast(&self, _attr: &Attr, _data: &ByteSlice) -> Result<Variant> {
+                Err(Error::new(ErrorKind::Other, "malformed"))
+            }
+        }
+
+        let class = Fixed::new(LayerClass::builder(Token::from("tcp")).build());
+        let mut tcp = Layer::new(class, ByteSlice::new());
+        let attrs = vec![
+            AttrClass::builder("tcp.dst").value(0u64).build(),
+            AttrClass::builder("tcp.flags.fin").value(false).build(),
+            AttrClass::builder("tcp.options.mss")
+                .cast(Malformed)
+                .build(),
+        ];
+        for class in attrs {
+            tcp.add_attr(Attr::builder(Fixed::new(class)).build());
+        }
+        let layers = vec![MutFixed::new(tcp)];
+        let ctx = Context::new(&layers);
+        let eval = |filter: &str| parse(filter).unwrap().eval(&ctx);
+
+        assert_eq!(eval("has(tcp)"), Variant::Bool(true));
+        assert_eq!(eval("has(udp)"), Variant::Bool(false));
+        assert_eq!(eval("has(tcp.dst)"), Variant::Bool(true));
+        assert_eq!(eval("has(tcp.flags.fin)"), Variant::Bool(true));
+        assert_eq!(eval("tcp.flags.fin"), Variant::Bool(false));
+        assert_eq!(eval("exists(tcp.options.mss)"), Variant::Bool(true));
+        assert_eq!(eval("tcp.options.mss"), Variant::Nil);
+        assert_eq!(eval("has(tcp.src)"), Variant::Bool(false));
+        assert_eq!(eval("has(tcp[0].dst)"), Variant::Bool(true));
+        assert_eq!(eval("has(tcp[1].dst)"), Variant::Bool(false));
+        assert_eq!(eval("has(1)"), Variant::Bool(false));
+
+        assert_eq!(eval("error(tcp.options.mss)"), Variant::Bool(true));
+        assert_eq!(eval("error(tcp.flags.fin)"), Variant::Bool(false));
+        assert_eq!(eval("error(tcp.src)"), Variant::Bool(false));
+        assert_eq!(eval("error(tcp)"), Variant::Bool(false));
+        assert_eq!(eval("any(error(tcp.options.mss))"), Variant::Bool(true));
     }
 }
@@ -0,0 +1,1022 @@
+use ast::Expr;
+use context::Context;
+use genet_abi::variant::Variant;
+use optimizer::optimize;
+use prefix::Prefix;
+use set::SetElement;
+use std::{error, fmt, u32};
+use unparser::unparse;
+use variant::VariantExt;
+
+/// The link type of Ethernet devices (`DLT_EN10MB`).
+pub const LINKTYPE_ETHERNET: u32 = 1;
+
+const LD: u16 = 0x00;
+const LDX: u16 = 0x01;
+const ALU: u16 = 0x04;
+const JMP: u16 = 0x05;
+const RET: u16 = 0x06;
+
+const W: u16 = 0x00;
+const H: u16 = 0x08;
+const B: u16 = 0x10;
+
+const ABS: u16 = 0x20;
+const IND: u16 = 0x40;
+const MSH: u16 = 0xa0;
+
+const AND: u16 = 0x50;
+const RSH: u16 = 0x70;
+
+const JA: u16 = 0x00;
+const JEQ: u16 = 0x10;
+const JGT: u16 = 0x20;
+const JGE: u16 = 0x30;
+const JSET: u16 = 0x40;
+
+/// The number of bytes captured from an accepted packet.
+const SNAPLEN: u32 = 0x0004_0000;
+
+/// A classic BPF instruction, laid out as `struct bpf_insn`.
+#[repr(C)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Instruction {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+impl Instruction {
+    fn stmt(code: u16, k: u32) -> Instruction {
+        Instruction {
+            code,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+}
+
+/// An error returned for a filter that cannot run in the kernel.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Error {
+    message: String,
+}
+
+impl Error {
+    fn new(message: &str) -> Error {
+        Error {
+            message: message.to_string(),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Translates an expression into a classic BPF program for the link type.
+///
+/// Only attributes at fixed offsets from the outermost Ethernet, ARP, IPv4,
+/// IPv6, TCP and UDP headers are supported, compared against constants.
+/// IPv6 extension headers and IPv4 fragments are not followed.
+pub fn compile(expr: &Expr, link: u32) -> Result<Vec<Instruction>, Error> {
+    if link != LINKTYPE_ETHERNET {
+        return Err(Error::new(&format!(
+            "link type {} is not supported by capture filters",
+            link
+        )));
+    }
+    let expr = optimize(expr.clone());
+    let mut gen = Generator::new();
+    let accept = gen.label();
+    let reject = gen.label();
+    gen.branch(&expr, accept, reject)?;
+    gen.place(accept);
+    gen.stmt(RET, SNAPLEN);
+    gen.place(reject);
+    gen.stmt(RET, 0);
+    gen.finish()
+}
+
+/// A test on the packet, `(load & mask) op value`.
+struct Check {
+    offset: u32,
+    size: u16,
+    mask: u32,
+    op: u16,
+    value: u32,
+}
+
+/// A way of locating a layer header.
+///
+/// If `indirect` is set, the header follows an IPv4 header of variable length
+/// and `offset` is relative to the IPv4 header.
+struct Path {
+    checks: &'static [Check],
+    offset: u32,
+    indirect: bool,
+}
+
+const ETHERTYPE: Check = Check {
+    offset: 12,
+    size: H,
+    mask: u32::MAX,
+    op: JGT,
+    value: 1500,
+};
+
+const ETHERTYPE_IPV4: Check = Check {
+    offset: 12,
+    size: H,
+    mask: u32::MAX,
+    op: JEQ,
+    value: 0x0800,
+};
+
+const ETHERTYPE_IPV6: Check = Check {
+    offset: 12,
+    size: H,
+    mask: u32::MAX,
+    op: JEQ,
+    value: 0x86dd,
+};
+
+const ETHERTYPE_ARP: Check = Check {
+    offset: 12,
+    size: H,
+    mask: u32::MAX,
+    op: JEQ,
+    value: 0x0806,
+};
+
+const IPV4_FIRST_FRAGMENT: Check = Check {
+    offset: 20,
+    size: H,
+    mask: 0x1fff,
+    op: JEQ,
+    value: 0,
+};
+
+const IPV4_TCP: Check = Check {
+    offset: 23,
+    size: B,
+    mask: u32::MAX,
+    op: JEQ,
+    value: 6,
+};
+
+const IPV4_UDP: Check = Check {
+    offset: 23,
+    size: B,
+    mask: u32::MAX,
+    op: JEQ,
+    value: 17,
+};
+
+const IPV6_TCP: Check = Check {
+    offset: 20,
+    size: B,
+    mask: u32::MAX,
+    op: JEQ,
+    value: 6,
+};
+
+const IPV6_UDP: Check = Check {
+    offset: 20,
+    size: B,
+    mask: u32::MAX,
+    op: JEQ,
+    value: 17,
+};
+
+const ETH: &[Path] = &[Path {
+    checks: &[],
+    offset: 0,
+    indirect: false,
+}];
+
+const ETH_TYPE: &[Path] = &[Path {
+    checks: &[ETHERTYPE],
+    offset: 0,
+    indirect: false,
+}];
+
+const ARP: &[Path] = &[Path {
+    checks: &[ETHERTYPE_ARP],
+    offset: 14,
+    indirect: false,
+}];
+
+const IPV4: &[Path] = &[Path {
+    checks: &[ETHERTYPE_IPV4],
+    offset: 14,
+    indirect: false,
+}];
+
+const IPV6: &[Path] = &[Path {
+    checks: &[ETHERTYPE_IPV6],
+    offset: 14,
+    indirect: false,
+}];
+
+const TCP: &[Path] = &[
+    Path {
+        checks: &[ETHERTYPE_IPV4, IPV4_TCP, IPV4_FIRST_FRAGMENT],
+        offset: 14,
+        indirect: true,
+    },
+    Path {
+        checks: &[ETHERTYPE_IPV6, IPV6_TCP],
+        offset: 54,
+        indirect: false,
+    },
+];
+
+const UDP: &[Path] = &[
+    Path {
+        checks: &[ETHERTYPE_IPV4, IPV4_UDP, IPV4_FIRST_FRAGMENT],
+        offset: 14,
+        indirect: true,
+    },
+    Path {
+        checks: &[ETHERTYPE_IPV6, IPV6_UDP],
+        offset: 54,
+        indirect: false,
+    },
+];
+
+fn layer(id: &str) -> Option<&'static [Path]> {
+    match id {
+        "eth" => Some(ETH),
+        "arp" => Some(ARP),
+        "ipv4" => Some(IPV4),
+        "ipv6" => Some(IPV6),
+        "tcp" => Some(TCP),
+        "udp" => Some(UDP),
+        _ => None,
+    }
+}
+
+/// How an attribute value is read from its header.
+#[derive(Clone, Copy)]
+enum Kind {
+    /// An unsigned integer, `(load >> shift) & mask`.
+    Int { size: u16, shift: u32, mask: u32 },
+    /// An address of the given length.
+    Bytes(u32),
+    /// A boolean, true if any bit in the mask is set.
+    Flag { size: u16, mask: u32 },
+}
+
+fn int(size: u16) -> Kind {
+    Kind::Int {
+        size,
+        shift: 0,
+        mask: u32::MAX,
+    }
+}
+
+fn bits(size: u16, shift: u32, mask: u32) -> Kind {
+    Kind::Int { size, shift, mask }
+}
+
+fn flag(size: u16, mask: u32) -> Kind {
+    Kind::Flag { size, mask }
+}
+
+/// Returns the paths to the header of an attribute, its offset and its kind.
+fn field(id: &str) -> Option<(&'static [Path], u32, Kind)> {
+    let (paths, offset, kind) = match id {
+        "eth.dst" => (ETH, 0, Kind::Bytes(6)),
+        "eth.src" => (ETH, 6, Kind::Bytes(6)),
+        "eth.type" => (ETH_TYPE, 12, int(H)),
+        "arp.hwtype" => (ARP, 0, int(H)),
+        "arp.protocol" => (ARP, 2, int(H)),
+        "arp.hlen" => (ARP, 4, int(B)),
+        "arp.plen" => (ARP, 5, int(B)),
+        "arp.op" => (ARP, 6, int(H)),
+        "ipv4.version" => (IPV4, 0, bits(B, 4, u32::MAX)),
+        "ipv4.headerLength" => (IPV4, 0, bits(B, 0, 0x0f)),
+        "ipv4.tos" => (IPV4, 1, int(B)),
+        "ipv4.totalLength" => (IPV4, 2, int(H)),
+        "ipv4.id" => (IPV4, 4, int(H)),
+        "ipv4.flags" => (IPV4, 6, bits(B, 5, 0x07)),
+        "ipv4.flags.reserved" => (IPV4, 6, flag(B, 0x80)),
+        "ipv4.flags.dontFragment" => (IPV4, 6, flag(B, 0x40)),
+        "ipv4.flags.moreFragments" => (IPV4, 6, flag(B, 0x20)),
+        "ipv4.fragmentOffset" => (IPV4, 6, bits(H, 0, 0x1fff)),
+        "ipv4.ttl" => (IPV4, 8, int(B)),
+        "ipv4.protocol" => (IPV4, 9, int(B)),
+        "ipv4.checksum" => (IPV4, 10, int(H)),
+        "ipv4.src" => (IPV4, 12, Kind::Bytes(4)),
+        "ipv4.dst" => (IPV4, 16, Kind::Bytes(4)),
+        "ipv6.version" => (IPV6, 0, bits(B, 4, u32::MAX)),
+        "ipv6.trafficClass" => (IPV6, 0, bits(H, 4, 0xff)),
+        "ipv6.nextHeader" => (IPV6, 6, int(B)),
+        "ipv6.hopLimit" => (IPV6, 7, int(B)),
+        "ipv6.src" => (IPV6, 8, Kind::Bytes(16)),
+        "ipv6.dst" => (IPV6, 24, Kind::Bytes(16)),
+        "tcp.src" => (TCP, 0, int(H)),
+        "tcp.dst" => (TCP, 2, int(H)),
+        "tcp.seq" => (TCP, 4, int(W)),
+        "tcp.ack" => (TCP, 8, int(W)),
+        "tcp.dataOffset" => (TCP, 12, bits(B, 4, u32::MAX)),
+        "tcp.flags" => (TCP, 12, bits(H, 0, 0x0fff)),
+        "tcp.flags.ns" => (TCP, 12, flag(B, 0x01)),
+        "tcp.flags.cwr" => (TCP, 13, flag(B, 0x80)),
+        "tcp.flags.ece" => (TCP, 13, flag(B, 0x40)),
+        "tcp.flags.urg" => (TCP, 13, flag(B, 0x20)),
+        "tcp.flags.ack" => (TCP, 13, flag(B, 0x10)),
+        "tcp.flags.psh" => (TCP, 13, flag(B, 0x08)),
+        "tcp.flags.rst" => (TCP, 13, flag(B, 0x04)),
+        "tcp.flags.syn" => (TCP, 13, flag(B, 0x02)),
+        "tcp.flags.fin" => (TCP, 13, flag(B, 0x01)),
+        "tcp.window" => (TCP, 14, int(H)),
+        "tcp.checksum" => (TCP, 16, int(H)),
+        "tcp.urgent" => (TCP, 18, int(H)),
+        "udp.src" => (UDP, 0, int(H)),
+        "udp.dst" => (UDP, 2, int(H)),
+        "udp.length" => (UDP, 4, int(H)),
+        "udp.checksum" => (UDP, 6, int(H)),
+        _ => return None,
+    };
+    Some((paths, offset, kind))
+}
+
+/// A test on an attribute value.
+enum Test {
+    Truthy,
+    Eq(Variant),
+    Lt(Variant),
+    Lte(Variant),
+    Gt(Variant),
+    Gte(Variant),
+    Range(Variant, Variant),
+    Prefix(Prefix),
+}
+
+type Label = usize;
+
+enum Op {
+    Stmt(Instruction),
+    Jump(u16, u32, Label, Label),
+    Goto(Label),
+    Place(Label),
+}
+
+struct Generator {
+    ops: Vec<Op>,
+    labels: usize,
+}
+
+impl Generator {
+    fn new() -> Generator {
+        Generator {
+            ops: Vec::new(),
+            labels: 0,
+        }
+    }
+
+    fn label(&mut self) -> Label {
+        self.labels += 1;
+        self.labels - 1
+    }
+
+    fn place(&mut self, label: Label) {
+        self.ops.push(Op::Place(label));
+    }
+
+    fn stmt(&mut self, code: u16, k: u32) {
+        self.ops.push(Op::Stmt(Instruction::stmt(code, k)));
+    }
+
+    fn jump(&mut self, op: u16, k: u32, t: Label, f: Label) {
+        self.ops.push(Op::Jump(JMP | op, k, t, f));
+    }
+
+    fn goto(&mut self, label: Label) {
+        self.ops.push(Op::Goto(label));
+    }
+
+    /// Emits code that jumps to `t` if the expression holds, or to `f` otherwise.
+    fn branch(&mut self, expr: &Expr, t: Label, f: Label) -> Result<(), Error> {
+        match expr {
+            Expr::Literal(v) => {
+                self.goto(if v.is_truthy() { t } else { f });
+                Ok(())
+            }
+            Expr::LogicalAnd(l, r) => {
+                let next = self.label();
+                self.branch(l, next, f)?;
+                self.place(next);
+                self.branch(r, t, f)
+            }
+            Expr::LogicalOr(l, r) => {
+                let next = self.label();
+                self.branch(l, t, next)?;
+                self.place(next);
+                self.branch(r, t, f)
+            }
+            Expr::LogicalNegation(v) => self.branch(v, f, t),
+            Expr::Token(_) => self.compare(expr, expr, Test::Truthy, t, f),
+            Expr::CmpEq(l, r) => match (&**l, &**r) {
+                (Expr::Prefix(p), v) | (v, Expr::Prefix(p)) => {
+                    self.compare(expr, v, Test::Prefix(p.clone()), t, f)
+                }
+                (v, c) | (c, v) if is_constant(c) => {
+                    let c = c.eval(&Context::new(&[]));
+                    self.compare(expr, v, Test::Eq(c), t, f)
+                }
+                _ => Err(unsupported(expr)),
+            },
+            Expr::CmpNotEq(l, r) => self.branch(&Expr::CmpEq(l.clone(), r.clone()), f, t),
+            Expr::CmpLt(l, r) => {
+                let (v, test) =
+                    ordering(l, r, Test::Lt, Test::Gt).ok_or_else(|| unsupported(expr))?;
+                self.compare(expr, v, test, t, f)
+            }
+            Expr::CmpLte(l, r) => {
+                let (v, test) =
+                    ordering(l, r, Test::Lte, Test::Gte).ok_or_else(|| unsupported(expr))?;
+                self.compare(expr, v, test, t, f)
+            }
+            Expr::CmpGt(l, r) => {
+                let (v, test) =
+                    ordering(l, r, Test::Gt, Test::Lt).ok_or_else(|| unsupported(expr))?;
+                self.compare(expr, v, test, t, f)
+            }
+            Expr::CmpGte(l, r) => {
+                let (v, test) =
+                    ordering(l, r, Test::Gte, Test::Lte).ok_or_else(|| unsupported(expr))?;
+                self.compare(expr, v, test, t, f)
+            }
+            Expr::In(l, r) => {
+                let set = match &**r {
+                    Expr::Set(set) => set,
+                    _ => return Err(unsupported(expr)),
+                };
+                let elements = set.elements();
+                if elements.is_empty() {
+                    self.goto(f);
+                }
+                for (i, elem) in elements.iter().enumerate() {
+                    let next = if i + 1 < elements.len() {
+                        self.label()
+                    } else {
+                        f
+                    };
+                    let test = match elem {
+                        SetElement::Value(Expr::Prefix(p)) => Test::Prefix(p.clone()),
+                        SetElement::Value(v) => Test::Eq(v.eval(&Context::new(&[]))),
+                        SetElement::Range(start, end) => Test::Range(
+                            start.eval(&Context::new(&[])),
+                            end.eval(&Context::new(&[])),
+                        ),
+                    };
+                    self.compare(expr, l, test, t, next)?;
+                    if next != f {
+                        self.place(next);
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(unsupported(expr)),
+        }
+    }
+
+    /// Emits a test on a layer or an attribute in each of its possible locations.
+    fn compare(
+        &mut self,
+        expr: &Expr,
+        value: &Expr,
+        test: Test,
+        t: Label,
+        f: Label,
+    ) -> Result<(), Error> {
+        let id = match value {
+            Expr::Token(id) => id.to_string(),
+            _ => return Err(unsupported(expr)),
+        };
+        let (paths, offset, kind) = if let Some(paths) = layer(&id) {
+            match test {
+                Test::Truthy => (paths, 0, None),
+                _ => {
+                    return Err(Error::new(&format!(
+                        "layer {} cannot be compared in capture filters",
+                        id
+                    )))
+                }
+            }
+        } else if let Some((paths, offset, kind)) = field(&id) {
+            validate(&id, kind, &test)?;
+            (paths, offset, Some(kind))
+        } else {
+            return Err(Error::new(&format!(
+                "{} is not at a fixed offset and cannot be used in capture filters",
+                id
+            )));
+        };
+
+        for (i, path) in paths.iter().enumerate() {
+            let next = if i + 1 < paths.len() { self.label() } else { f };
+            for check in path.checks {
+                let ok = self.label();
+                self.load(check.size, ABS, check.offset, 0, check.mask);
+                self.jump(check.op, check.value, ok, next);
+                self.place(ok);
+            }
+            let mode = if path.indirect {
+                self.stmt(LDX | B | MSH, 14);
+                IND
+            } else {
+                ABS
+            };
+            let offset = path.offset + offset;
+            match kind {
+                Some(kind) => self.field(kind, mode, offset, &test, t, next)?,
+                None => self.goto(t),
+            }
+            if next != f {
+                self.place(next);
+            }
+        }
+        Ok(())
+    }
+
+    fn field(
+        &mut self,
+        kind: Kind,
+        mode: u16,
+        offset: u32,
+        test: &Test,
+        t: Label,
+        f: Label,
+    ) -> Result<(), Error> {
+        match (kind, test) {
+            (Kind::Flag { size, mask }, Test::Truthy) => {
+                self.load(size, mode, offset, 0, u32::MAX);
+                self.jump(JSET, mask, t, f);
+            }
+            (Kind::Flag { size, mask }, Test::Eq(Variant::Bool(v))) => {
+                self.load(size, mode, offset, 0, u32::MAX);
+                if *v {
+                    self.jump(JSET, mask, t, f);
+                } else {
+                    self.jump(JSET, mask, f, t);
+                }
+            }
+            (Kind::Bytes(_), Test::Truthy) => self.goto(t),
+            (Kind::Int { size, shift, mask }, _) => {
+                let (op, k, t, f) = match test {
+                    Test::Eq(v) => match integer(v) {
+                        Ok(k) => (JEQ, k, t, f),
+                        Err(_) if is_number(v) => {
+                            self.goto(f);
+                            return Ok(());
+                        }
+                        Err(err) => return Err(err),
+                    },
+                    Test::Lt(v) => (JGE, integer(v)?, f, t),
+                    Test::Lte(v) => (JGT, integer(v)?, f, t),
+                    Test::Gt(v) => (JGT, integer(v)?, t, f),
+                    Test::Gte(v) => (JGE, integer(v)?, t, f),
+                    Test::Range(start, end) => {
+                        let (start, end) = (integer(start)?, integer(end)?);
+                        let next = self.label();
+                        self.load(size, mode, offset, shift, mask);
+                        self.jump(JGE, start, next, f);
+                        self.place(next);
+                        self.jump(JGT, end, f, t);
+                        return Ok(());
+                    }
+                    _ => unreachable!(),
+                };
+                self.load(size, mode, offset, shift, mask);
+                self.jump(op, k, t, f);
+            }
+            (Kind::Bytes(len), Test::Eq(v)) => {
+                let bytes = v.as_bytes().unwrap_or(&[]);
+                if bytes.len() != len as usize {
+                    self.goto(f);
+                    return Ok(());
+                }
+                let mut pos = 0;
+                while pos < bytes.len() {
+                    let (size, width) = match bytes.len() - pos {
+                        1 => (B, 1),
+                        2 | 3 => (H, 2),
+                        _ => (W, 4),
+                    };
+                    let k = bytes[pos..pos + width]
+                        .iter()
+                        .fold(0, |k, b| (k << 8) | u32::from(*b));
+                    pos += width;
+                    let next = if pos < bytes.len() { self.label() } else { t };
+                    self.load(size, mode, offset + pos as u32 - width as u32, 0, u32::MAX);
+                    self.jump(JEQ, k, next, f);
+                    if next != t {
+                        self.place(next);
+                    }
+                }
+            }
+            (Kind::Bytes(len), Test::Prefix(prefix)) => {
+                let network = prefix.network();
+                if network.len() != len as usize {
+                    self.goto(f);
+                    return Ok(());
+                }
+                let words = (0..network.len() / 4)
+                    .map(|i| {
+                        let bits = (prefix.len() as u32).saturating_sub(i as u32 * 32).min(32);
+                        let mask = if bits == 0 {
+                            0
+                        } else {
+                            u32::MAX << (32 - bits)
+                        };
+                        let k = network[i * 4..i * 4 + 4]
+                            .iter()
+                            .fold(0, |k, b| (k << 8) | u32::from(*b));
+                        (i as u32 * 4, mask, k)
+                    })
+                    .filter(|(_, mask, _)| *mask != 0)
+                    .collect::<Vec<_>>();
+                if words.is_empty() {
+                    self.goto(t);
+                }
+                for (i, (pos, mask, k)) in words.iter().enumerate() {
+                    let next = if i + 1 < words.len() { self.label() } else { t };
+                    self.load(W, mode, offset + pos, 0, *mask);
+                    self.jump(JEQ, *k, next, f);
+                    if next != t {
+                        self.place(next);
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, size: u16, mode: u16, offset: u32, shift: u32, mask: u32) {
+        self.stmt(LD | size | mode, offset);
+        if shift > 0 {
+            self.stmt(ALU | RSH, shift);
+        }
+        if mask != u32::MAX {
+            self.stmt(ALU | AND, mask);
+        }
+    }
+
+    /// Resolves the labels into relative jump offsets.
+    fn finish(self) -> Result<Vec<Instruction>, Error> {
+        // Drop jumps to the immediately following instruction.
+        let mut ops = Vec::new();
+        for (i, op) in self.ops.iter().enumerate() {
+            if let Op::Goto(label) = op {
+                let next = self.ops[i + 1..]
+                    .iter()
+                    .take_while(|op| match op {
+                        Op::Place(_) => true,
+                        _ => false,
+                    })
+                    .any(|op| match op {
+                        Op::Place(l) => l == label,
+                        _ => false,
+                    });
+                if next {
+                    continue;
+                }
+            }
+            ops.push(op);
+        }
+
+        let mut positions = vec![0; self.labels];
+        let mut pos = 0;
+        for op in &ops {
+            match op {
+                Op::Place(label) => positions[*label] = pos,
+                _ => pos += 1,
+            }
+        }
+
+        let mut program = Vec::new();
+        for op in &ops {
+            let pos = program.len();
+            let offset = |label: Label| positions[label] - pos - 1;
+            let insn = match op {
+                Op::Stmt(insn) => *insn,
+                Op::Goto(label) => Instruction::stmt(JMP | JA, offset(*label) as u32),
+                Op::Jump(code, k, t, f) => {
+                    let (t, f) = (offset(*t), offset(*f));
+                    if t > 0xff || f > 0xff {
+                        return Err(Error::new("filter is too large for a capture filter"));
+                    }
+                    Instruction {
+                        code: *code,
+                        jt: t as u8,
+                        jf: f as u8,
+                        k: *k,
+                    }
+                }
+                Op::Place(_) => continue,
+            };
+            program.push(insn);
+        }
+        Ok(program)
+    }
+}
+
+/// Returns the non-constant operand of an ordering comparison
+/// and the test for it, swapping the operator if needed.
+fn ordering<'a, F, G>(lhs: &'a Expr, rhs: &'a Expr, test: F, swapped: G) -> Option<(&'a Expr, Test)>
+where
+    F: Fn(Variant) -> Test,
+    G: Fn(Variant) -> Test,
+{
+    let ctx = Context::new(&[]);
+    if is_constant(rhs) {
+        Some((lhs, test(rhs.eval(&ctx))))
+    } else if is_constant(lhs) {
+        Some((rhs, swapped(lhs.eval(&ctx))))
+    } else {
+        None
+    }
+}
+
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(_) => true,
+        _ => false,
+    }
+}
+
+fn is_number(value: &Variant) -> bool {
+    match value {
+        Variant::Int64(_) | Variant::UInt64(_) | Variant::BigInt(_) | Variant::Float64(_) => true,
+        _ => false,
+    }
+}
+
+/// Returns an error if the test does not apply to the kind of the attribute.
+fn validate(id: &str, kind: Kind, test: &Test) -> Result<(), Error> {
+    let valid = match (kind, test) {
+        (Kind::Int { .. }, Test::Truthy) => {
+            return Err(Error::new(&format!(
+                "{} is an integer and must be compared with a value in capture filters",
+                id
+            )))
+        }
+        (_, Test::Truthy) => true,
+        (Kind::Flag { .. }, Test::Eq(Variant::Bool(_))) => true,
+        (Kind::Flag { .. }, _) => false,
+        (Kind::Int { .. }, Test::Prefix(_)) => false,
+        (Kind::Int { .. }, _) => true,
+        (Kind::Bytes(_), Test::Eq(v)) => v.as_bytes().is_some(),
+        (Kind::Bytes(_), Test::Prefix(_)) => true,
+        (Kind::Bytes(_), _) => {
+            return Err(Error::new(&format!(
+                "{} is an address and cannot be compared by order in capture filters",
+                id
+            )))
+        }
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::new(&format!(
+            "{} cannot be compared with this value in capture filters",
+            id
+        )))
+    }
+}
+
+/// Converts a constant into a 32-bit BPF operand.
+fn integer(value: &Variant) -> Result<u32, Error> {
+    let v = match value.clone().shrink() {
+        Variant::UInt64(v) if v <= u64::from(u32::MAX) => Some(v as u32),
+        Variant::Int64(v) if v >= 0 && v <= i64::from(u32::MAX) => Some(v as u32),
+        Variant::Float64(v) if v.fract() == 0.0 && v >= 0.0 && v <= f64::from(u32::MAX) => {
+            Some(v as u32)
+        }
+        _ => None,
+    };
+    v.ok_or_else(|| {
+        Error::new(&format!(
+            "{} is not an unsigned 32-bit integer",
+            unparse(&Expr::Literal(value.clone()))
+        ))
+    })
+}
+
+fn unsupported(expr: &Expr) -> Error {
+    Error::new(&format!(
+        "{} cannot be translated into a capture filter",
+        unparse(expr)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parse;
+
+    fn bpf(filter: &str) -> Result<Vec<Instruction>, Error> {
+        compile(&parse(filter).unwrap(), LINKTYPE_ETHERNET)
+    }
+
+    /// Runs a program on a packet and returns the number of bytes to accept.
+    fn run(program: &[Instruction], packet: &[u8]) -> u32 {
+        let (mut a, mut x, mut pc) = (0u32, 0u32, 0);
+        let load = |offset: u32, size: u16| -> Option<u32> {
+            let width = match size {
+                W => 4,
+                H => 2,
+                _ => 1,
+            };
+            let offset = offset as usize;
+            packet
+                .get(offset..offset + width)
+                .map(|b| b.iter().fold(0, |v, b| (v << 8) | u32::from(*b)))
+        };
+        loop {
+            let insn = program[pc];
+            pc += 1;
+            let (class, size, mode) = (insn.code & 0x07, insn.code & 0x18, insn.code & 0xe0);
+            match class {
+                LD => {
+                    let offset = if mode == IND { x + insn.k } else { insn.k };
+                    match load(offset, size) {
+                        Some(v) => a = v,
+                        None => return 0,
+                    }
+                }
+                LDX => match load(insn.k, B) {
+                    Some(v) => x = (v & 0x0f) * 4,
+                    None => return 0,
+                },
+                ALU => match insn.code & 0xf0 {
+                    AND => a &= insn.k,
+                    RSH => a >>= insn.k,
+                    _ => unreachable!(),
+                },
+                JMP => {
+                    let cond = match insn.code & 0xf0 {
+                        JA => {
+                            pc += insn.k as usize;
+                            continue;
+                        }
+                        JEQ => a == insn.k,
+                        JGT => a > insn.k,
+                        JGE => a >= insn.k,
+                        JSET => a & insn.k != 0,
+                        _ => unreachable!(),
+                    };
+                    pc += if cond { insn.jt } else { insn.jf } as usize;
+                }
+                RET => return insn.k,
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn ipv4_tcp(src: [u8; 4], dst: [u8; 4], sport: u16, dport: u16, flags: u8) -> Vec<u8> {
+        let mut p = vec![0u8; 14 + 24 + 20];
+        p[0..6].copy_from_slice(&[0, 1, 2, 3, 4, 5]);
+        p[6..12].copy_from_slice(&[6, 7, 8, 9, 10, 11]);
+        p[12..14].copy_from_slice(&[0x08, 0x00]);
+        p[14] = 0x46;
+        p[22] = 64;
+        p[23] = 6;
+        p[26..30].copy_from_slice(&src);
+        p[30..34].copy_from_slice(&dst);
+        p[38..40].copy_from_slice(&[(sport >> 8) as u8, sport as u8]);
+        p[40..42].copy_from_slice(&[(dport >> 8) as u8, dport as u8]);
+        p[51] = flags;
+        p
+    }
+
+    fn ipv6_udp(sport: u16, dport: u16) -> Vec<u8> {
+        let mut p = vec![0u8; 14 + 40 + 8];
+        p[12..14].copy_from_slice(&[0x86, 0xdd]);
+        p[14] = 0x60;
+        p[20] = 17;
+        p[22..38].copy_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        p[54..56].copy_from_slice(&[(sport >> 8) as u8, sport as u8]);
+        p[56..58].copy_from_slice(&[(dport >> 8) as u8, dport as u8]);
+        p
+    }
+
+    #[test]
+    fn instructions() {
+        assert_eq!(
+            bpf("ipv4.protocol == 6").unwrap(),
+            vec![
+                Instruction::stmt(0x28, 12),
+                Instruction {
+                    code: 0x15,
+                    jt: 0,
+                    jf: 3,
+                    k: 0x0800,
+                },
+                Instruction::stmt(0x30, 23),
+                Instruction {
+                    code: 0x15,
+                    jt: 0,
+                    jf: 1,
+                    k: 6,
+                },
+                Instruction::stmt(0x06, SNAPLEN),
+                Instruction::stmt(0x06, 0),
+            ]
+        );
+        assert_eq!(
+            bpf("true").unwrap(),
+            vec![Instruction::stmt(0x06, SNAPLEN), Instruction::stmt(0x06, 0)]
+        );
+    }
+
+    #[test]
+    fn matching() {
+        let tcp = ipv4_tcp([10, 1, 2, 3], [192, 168, 0, 1], 50000, 80, 0x02);
+        let udp = ipv6_udp(5353, 53);
+        let matches = |filter: &str, packet: &[u8]| run(&bpf(filter).unwrap(), packet) > 0;
+
+        assert!(matches("eth.type == 0x0800", &tcp));
+        assert!(matches("eth.src == @06:07:08:09:0a:0b", &tcp));
+        assert!(!matches("eth.dst == @06:07:08:09:0a:0b", &tcp));
+        assert!(matches("ipv4 && !ipv6", &tcp));
+        assert!(matches("ipv4.src == @10.1.2.3", &tcp));
+        assert!(matches("ipv4.src == @10.0.0.0/8", &tcp));
+        assert!(!matches("ipv4.dst == @10.0.0.0/8", &tcp));
+        assert!(matches("ipv4.dst in {@10.0.0.0/8, @192.168.0.0/16}", &tcp));
+        assert!(matches("ipv4.ttl >= 64 && ipv4.headerLength == 6", &tcp));
+        assert!(matches("tcp.dst == 80", &tcp));
+        assert!(matches("80 == tcp.dst", &tcp));
+        assert!(matches("tcp.src in {1024..65535}", &tcp));
+        assert!(!matches("tcp.src < 1024", &tcp));
+        assert!(matches("tcp.dst in {443, 80}", &tcp));
+        assert!(matches("tcp.flags.syn && !tcp.flags.ack", &tcp));
+        assert!(matches("tcp.flags.syn == true", &tcp));
+        assert!(!matches("udp", &tcp));
+        assert!(matches("udp.dst != 53", &tcp));
+        assert!(!matches("tcp.dst == 0x10050", &tcp));
+
+        assert!(matches("udp.dst == 53 && udp.src == 5353", &udp));
+        assert!(matches("ipv6.src == @2001:db8::1", &udp));
+        assert!(matches("ipv6.src == @2001:db8::/32", &udp));
+        assert!(!matches("ipv6.src == @2001:db9::/32", &udp));
+        assert!(!matches("ipv4.src == @2001:db8::/32", &udp));
+        assert!(!matches("tcp.dst == 80 || ipv4", &udp));
+    }
+
+    #[test]
+    fn error() {
+        let message = |filter: &str| bpf(filter).unwrap_err().message().to_string();
+        assert_eq!(
+            message("http.method == 1"),
+            "http.method is not at a fixed offset and cannot be used in capture filters"
+        );
+        assert_eq!(
+            message("tcp.src == tcp.dst"),
+            "tcp.src == tcp.dst cannot be translated into a capture filter"
+        );
+        assert_eq!(
+            message("len(eth.src) == 6"),
+            "len(eth.src) == 6 cannot be translated into a capture filter"
+        );
+        assert_eq!(
+            message("tcp.flags.syn > 1"),
+            "tcp.flags.syn cannot be compared with this value in capture filters"
+        );
+        assert_eq!(
+            message("tcp.dst > -1"),
+            "-1 is not an unsigned 32-bit integer"
+        );
+        assert_eq!(
+            message("tcp && !tcp.dst"),
+            "tcp.dst is an integer and must be compared with a value in capture filters"
+        );
+        assert_eq!(
+            message("ipv4.src < @10.0.0.1"),
+            "ipv4.src is an address and cannot be compared by order in capture filters"
+        );
+        assert_eq!(
+            compile(&parse("tcp").unwrap(), 101).unwrap_err().message(),
+            "link type 101 is not supported by capture filters"
+        );
+    }
+}
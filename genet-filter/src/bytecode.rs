@@ -0,0 +1,611 @@
+use ast::Expr;
+use context::{Context, Field};
+use genet_abi::{token::Token, variant::Variant};
+use pattern::Pattern;
+use prefix::Prefix;
+use set::Set;
+use std::cmp::Ordering;
+use variant::VariantExt;
+
+type Reg = usize;
+
+/// A filter expression compiled into flat, register-based bytecode.
+///
+/// Each instruction writes its result into a register, and the operands of a node
+/// are evaluated into the registers following it, so the result of the whole
+/// expression ends up in register 0.
+/// Tokens are interned into slots and looked up at most once per evaluation.
+///
+/// Nodes without a dedicated instruction, such as function calls,
+/// fall back to the tree evaluator in `Expr::eval`.
+#[derive(Clone, Debug)]
+pub struct Program {
+    code: Vec<Insn>,
+    consts: Vec<Variant>,
+    tokens: Vec<Token>,
+    prefixes: Vec<Prefix>,
+    sets: Vec<Set>,
+    patterns: Vec<Pattern>,
+    exprs: Vec<Expr>,
+    registers: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Insn {
+    Const(Reg, usize),
+    Token(Reg, usize),
+    Frame(Reg, Field),
+    Eval(Reg, usize),
+    Unary(Unary, Reg),
+    Binary(Binary, Reg, Reg),
+    Compare(Cmp, Reg, Reg),
+    CompareConst(Cmp, Reg, usize),
+    CompareInt(Cmp, Reg, Int, usize),
+    Prefix(Reg, usize),
+    PrefixToken(Reg, usize, usize),
+    In(Reg, usize),
+    InToken(Reg, usize, usize),
+    Matches(Reg, usize),
+    Truthy(Reg),
+    Not(Reg),
+    Bool(Reg, bool),
+    JumpIf(Reg, usize),
+    JumpUnless(Reg, usize),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Unary {
+    Plus,
+    Negation,
+    BitwiseNot,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Binary {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    ShiftLeft,
+    ShiftRight,
+    Contains,
+    StartsWith,
+    Index,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Cmp {
+    Eq,
+    NotEq,
+    Lt,
+    Gt,
+    Lte,
+    Gte,
+}
+
+/// An integer constant inlined into a comparison.
+#[derive(Clone, Copy, Debug)]
+enum Int {
+    Signed(i64),
+    Unsigned(u64),
+}
+
+impl Cmp {
+    fn test(self, lhs: &Variant, rhs: &Variant) -> bool {
+        match self {
+            Cmp::Eq => lhs.op_eq(rhs),
+            Cmp::NotEq => !lhs.op_eq(rhs),
+            Cmp::Lt => lhs.op_lt(rhs),
+            Cmp::Gt => lhs.op_gt(rhs),
+            Cmp::Lte => lhs.op_lte(rhs),
+            Cmp::Gte => lhs.op_gte(rhs),
+        }
+    }
+
+    fn test_ord(self, ord: Option<Ordering>) -> bool {
+        match (self, ord) {
+            (Cmp::Eq, Some(Ordering::Equal)) => true,
+            (Cmp::NotEq, Some(Ordering::Equal)) => false,
+            (Cmp::NotEq, _) => true,
+            (Cmp::Lt, Some(Ordering::Less)) => true,
+            (Cmp::Gt, Some(Ordering::Greater)) => true,
+            (Cmp::Lte, Some(Ordering::Less)) | (Cmp::Lte, Some(Ordering::Equal)) => true,
+            (Cmp::Gte, Some(Ordering::Greater)) | (Cmp::Gte, Some(Ordering::Equal)) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Int {
+    fn new(value: &Variant) -> Option<Int> {
+        match value {
+            Variant::Int64(v) => Some(Int::Signed(*v)),
+            Variant::UInt64(v) => Some(Int::Unsigned(*v)),
+            _ => None,
+        }
+    }
+
+    /// Compares a numeric value with the constant without cloning it,
+    /// following the same conversions as `VariantExt::ord`.
+    fn ord(self, value: &Variant) -> Option<Option<Ordering>> {
+        let ord = match (value, self) {
+            (Variant::Int64(a), Int::Signed(b)) => a.partial_cmp(&b),
+            (Variant::Int64(a), Int::Unsigned(b)) => a.partial_cmp(&(b as i64)),
+            (Variant::UInt64(a), Int::Unsigned(b)) => a.partial_cmp(&b),
+            (Variant::UInt64(a), Int::Signed(b)) => a.partial_cmp(&(b as u64)),
+            (Variant::Float64(a), Int::Signed(b)) => a.partial_cmp(&(b as f64)),
+            (Variant::Float64(a), Int::Unsigned(b)) => a.partial_cmp(&(b as f64)),
+            _ => return None,
+        };
+        Some(ord)
+    }
+}
+
+impl Program {
+    pub fn compile(expr: &Expr) -> Program {
+        let mut program = Program {
+            code: Vec::new(),
+            consts: Vec::new(),
+            tokens: Vec::new(),
+            prefixes: Vec::new(),
+            sets: Vec::new(),
+            patterns: Vec::new(),
+            exprs: Vec::new(),
+            registers: 1,
+        };
+        program.emit(expr, 0);
+        program
+    }
+
+    /// Evaluates the program. The result is identical to `Expr::eval`.
+    pub fn run(&self, ctx: &Context) -> Variant {
+        let mut regs = vec![Variant::Nil; self.registers];
+        let mut slots: Vec<Option<(Variant, Option<Token>)>> = vec![None; self.tokens.len()];
+        let mut pc = 0;
+        while pc < self.code.len() {
+            match self.code[pc] {
+                Insn::Const(dst, idx) => regs[dst] = self.consts[idx].clone(),
+                Insn::Token(dst, slot) => {
+                    regs[dst] = self.load(ctx, &mut slots, slot).0.clone();
+                }
+                Insn::Frame(dst, field) => {
+                    regs[dst] = ctx.metadata().map_or(Variant::Nil, |m| m.get(field))
+                }
+                Insn::Eval(dst, idx) => regs[dst] = self.exprs[idx].eval(ctx),
+                Insn::Unary(op, dst) => {
+                    regs[dst] = match op {
+                        Unary::Plus => regs[dst].op_unary_plus(),
+                        Unary::Negation => regs[dst].op_unary_negation(),
+                        Unary::BitwiseNot => regs[dst].op_bitwise_not(),
+                    }
+                }
+                Insn::Binary(op, dst, src) => {
+                    let (lhs, rhs) = (&regs[dst], &regs[src]);
+                    regs[dst] = match op {
+                        Binary::Add => lhs.op_add(rhs),
+                        Binary::Sub => lhs.op_sub(rhs),
+                        Binary::Mul => lhs.op_mul(rhs),
+                        Binary::Div => lhs.op_div(rhs),
+                        Binary::Rem => lhs.op_rem(rhs),
+                        Binary::BitwiseAnd => lhs.op_bitwise_and(rhs),
+                        Binary::BitwiseOr => lhs.op_bitwise_or(rhs),
+                        Binary::BitwiseXor => lhs.op_bitwise_xor(rhs),
+                        Binary::ShiftLeft => lhs.op_shl(rhs),
+                        Binary::ShiftRight => lhs.op_shr(rhs),
+                        Binary::Contains => Variant::Bool(lhs.op_contains(rhs)),
+                        Binary::StartsWith => Variant::Bool(lhs.op_starts_with(rhs)),
+                        Binary::Index => lhs.op_index(rhs),
+                    }
+                }
+                Insn::Compare(op, dst, src) => {
+                    regs[dst] = Variant::Bool(op.test(&regs[dst], &regs[src]))
+                }
+                Insn::CompareConst(op, dst, idx) => {
+                    regs[dst] = Variant::Bool(op.test(&regs[dst], &self.consts[idx]))
+                }
+                Insn::CompareInt(op, dst, int, idx) => {
+                    let result = match int.ord(&regs[dst]) {
+                        Some(ord) => op.test_ord(ord),
+                        None => op.test(&regs[dst], &self.consts[idx]),
+                    };
+                    regs[dst] = Variant::Bool(result);
+                }
+                Insn::Prefix(dst, idx) => {
+                    regs[dst] = Variant::Bool(self.prefixes[idx].contains(&regs[dst]))
+                }
+                Insn::PrefixToken(dst, slot, idx) => {
+                    regs[dst] = Variant::Bool(match self.load(ctx, &mut slots, slot) {
+                        (value, Some(typ)) => self.prefixes[idx].contains_typed(value, *typ),
+                        (value, None) => self.prefixes[idx].contains(value),
+                    })
+                }
+                Insn::In(dst, idx) => {
+                    regs[dst] = Variant::Bool(self.sets[idx].contains(&regs[dst]))
+                }
+                Insn::InToken(dst, slot, idx) => {
+                    let (value, typ) = self.load(ctx, &mut slots, slot);
+                    regs[dst] = Variant::Bool(self.sets[idx].contains_typed(value, *typ))
+                }
+                Insn::Matches(dst, idx) => {
+                    regs[dst] = Variant::Bool(self.patterns[idx].is_match(&regs[dst]))
+                }
+                Insn::Truthy(dst) => regs[dst] = Variant::Bool(regs[dst].is_truthy()),
+                Insn::Not(dst) => regs[dst] = Variant::Bool(!regs[dst].is_truthy()),
+                Insn::Bool(dst, value) => regs[dst] = Variant::Bool(value),
+                Insn::JumpIf(src, target) => {
+                    if regs[src].is_truthy() {
+                        pc = target;
+                        continue;
+                    }
+                }
+                Insn::JumpUnless(src, target) => {
+                    if !regs[src].is_truthy() {
+                        pc = target;
+                        continue;
+                    }
+                }
+            }
+            pc += 1;
+        }
+        regs.swap_remove(0)
+    }
+
+    /// Looks up the token in a slot unless it has been already.
+    fn load<'a>(
+        &self,
+        ctx: &Context,
+        slots: &'a mut [Option<(Variant, Option<Token>)>],
+        slot: usize,
+    ) -> &'a (Variant, Option<Token>) {
+        if slots[slot].is_none() {
+            slots[slot] = Some(Expr::lookup_typed(ctx, self.tokens[slot]));
+        }
+        slots[slot].as_ref().unwrap()
+    }
+
+    fn emit(&mut self, expr: &Expr, dst: Reg) {
+        if dst >= self.registers {
+            self.registers = dst + 1;
+        }
+        match expr {
+            Expr::Literal(v) => {
+                let idx = self.constant(v.clone());
+                self.code.push(Insn::Const(dst, idx));
+            }
+            Expr::Token(t) => {
+                let slot = self.slot(*t);
+                self.code.push(Insn::Token(dst, slot));
+            }
+            Expr::Frame(field) => self.code.push(Insn::Frame(dst, *field)),
+            Expr::CmpEq(l, r) => self.emit_eq(l, r, dst, false),
+            Expr::CmpNotEq(l, r) => self.emit_eq(l, r, dst, true),
+            Expr::CmpLt(l, r) => self.emit_compare(Cmp::Lt, l, r, dst),
+            Expr::CmpGt(l, r) => self.emit_compare(Cmp::Gt, l, r, dst),
+            Expr::CmpLte(l, r) => self.emit_compare(Cmp::Lte, l, r, dst),
+            Expr::CmpGte(l, r) => self.emit_compare(Cmp::Gte, l, r, dst),
+            Expr::LogicalAnd(l, r) => self.emit_logical(l, r, dst, false),
+            Expr::LogicalOr(l, r) => self.emit_logical(l, r, dst, true),
+            Expr::LogicalNegation(v) => {
+                self.emit(v, dst);
+                self.code.push(Insn::Not(dst));
+            }
+            Expr::UnaryPlus(v) => self.emit_unary(Unary::Plus, v, dst),
+            Expr::UnaryNegation(v) => self.emit_unary(Unary::Negation, v, dst),
+            Expr::BitwiseNot(v) => self.emit_unary(Unary::BitwiseNot, v, dst),
+            Expr::Add(l, r) => self.emit_binary(Binary::Add, l, r, dst),
+            Expr::Sub(l, r) => self.emit_binary(Binary::Sub, l, r, dst),
+            Expr::Mul(l, r) => self.emit_binary(Binary::Mul, l, r, dst),
+            Expr::Div(l, r) => self.emit_binary(Binary::Div, l, r, dst),
+            Expr::Rem(l, r) => self.emit_binary(Binary::Rem, l, r, dst),
+            Expr::BitwiseAnd(l, r) => self.emit_binary(Binary::BitwiseAnd, l, r, dst),
+            Expr::BitwiseOr(l, r) => self.emit_binary(Binary::BitwiseOr, l, r, dst),
+            Expr::BitwiseXor(l, r) => self.emit_binary(Binary::BitwiseXor, l, r, dst),
+            Expr::ShiftLeft(l, r) => self.emit_binary(Binary::ShiftLeft, l, r, dst),
+            Expr::ShiftRight(l, r) => self.emit_binary(Binary::ShiftRight, l, r, dst),
+            Expr::Contains(l, r) => self.emit_binary(Binary::Contains, l, r, dst),
+            Expr::StartsWith(l, r) => self.emit_binary(Binary::StartsWith, l, r, dst),
+            Expr::Index(l, r) => self.emit_binary(Binary::Index, l, r, dst),
+            Expr::In(l, r) => match r.as_ref() {
+                Expr::Set(set) => {
+                    self.sets.push(set.clone());
+                    let idx = self.sets.len() - 1;
+                    match l.as_ref() {
+                        Expr::Token(t) => {
+                            let slot = self.slot(*t);
+                            self.code.push(Insn::InToken(dst, slot, idx));
+                        }
+                        Expr::Occurrence(..) => {
+                            self.exprs.push(expr.clone());
+                            self.code.push(Insn::Eval(dst, self.exprs.len() - 1));
+                        }
+                        _ => {
+                            self.emit(l, dst);
+                            self.code.push(Insn::In(dst, idx));
+                        }
+                    }
+                }
+                _ => self.code.push(Insn::Bool(dst, false)),
+            },
+            Expr::Matches(l, r) => match r.as_ref() {
+                Expr::Pattern(pattern) => {
+                    self.emit(l, dst);
+                    self.patterns.push(pattern.clone());
+                    self.code.push(Insn::Matches(dst, self.patterns.len() - 1));
+                }
+                _ => self.code.push(Insn::Bool(dst, false)),
+            },
+            Expr::Prefix(p) => {
+                let idx = self.constant(Variant::Buffer(p.network().into_boxed_slice()));
+                self.code.push(Insn::Const(dst, idx));
+            }
+            Expr::Set(_) | Expr::Pattern(_) | Expr::Macro(_) => {
+                let idx = self.constant(Variant::Nil);
+                self.code.push(Insn::Const(dst, idx));
+            }
+            Expr::Slice(..) | Expr::Call(..) | Expr::Occurrence(..) => {
+                self.exprs.push(expr.clone());
+                self.code.push(Insn::Eval(dst, self.exprs.len() - 1));
+            }
+        }
+    }
+
+    fn emit_eq(&mut self, lhs: &Expr, rhs: &Expr, dst: Reg, negate: bool) {
+        match (lhs, rhs) {
+            (Expr::Prefix(p), v) | (v, Expr::Prefix(p)) => {
+                self.prefixes.push(p.clone());
+                let idx = self.prefixes.len() - 1;
+                match v {
+                    Expr::Token(t) => {
+                        let slot = self.slot(*t);
+                        self.code.push(Insn::PrefixToken(dst, slot, idx));
+                    }
+                    Expr::Occurrence(..) => {
+                        let eq = Expr::CmpEq(Box::new(lhs.clone()), Box::new(rhs.clone()));
+                        self.exprs.push(eq);
+                        self.code.push(Insn::Eval(dst, self.exprs.len() - 1));
+                    }
+                    _ => {
+                        self.emit(v, dst);
+                        self.code.push(Insn::Prefix(dst, idx));
+                    }
+                }
+                if negate {
+                    self.code.push(Insn::Not(dst));
+                }
+            }
+            _ => self.emit_compare(if negate { Cmp::NotEq } else { Cmp::Eq }, lhs, rhs, dst),
+        }
+    }
+
+    fn emit_compare(&mut self, op: Cmp, lhs: &Expr, rhs: &Expr, dst: Reg) {
+        self.emit(lhs, dst);
+        if let Expr::Literal(v) = rhs {
+            let idx = self.constant(v.clone());
+            match Int::new(v) {
+                Some(int) => self.code.push(Insn::CompareInt(op, dst, int, idx)),
+                None => self.code.push(Insn::CompareConst(op, dst, idx)),
+            }
+        } else {
+            self.emit(rhs, dst + 1);
+            self.code.push(Insn::Compare(op, dst, dst + 1));
+        }
+    }
+
+    fn emit_logical(&mut self, lhs: &Expr, rhs: &Expr, dst: Reg, or: bool) {
+        self.emit(lhs, dst);
+        self.code.push(Insn::Truthy(dst));
+        let jump = self.code.len();
+        self.code.push(Insn::JumpIf(dst, 0));
+        self.emit(rhs, dst);
+        self.code.push(Insn::Truthy(dst));
+        let end = self.code.len();
+        self.code[jump] = if or {
+            Insn::JumpIf(dst, end)
+        } else {
+            Insn::JumpUnless(dst, end)
+        };
+    }
+
+    fn emit_unary(&mut self, op: Unary, v: &Expr, dst: Reg) {
+        self.emit(v, dst);
+        self.code.push(Insn::Unary(op, dst));
+    }
+
+    fn emit_binary(&mut self, op: Binary, lhs: &Expr, rhs: &Expr, dst: Reg) {
+        self.emit(lhs, dst);
+        self.emit(rhs, dst + 1);
+        self.code.push(Insn::Binary(op, dst, dst + 1));
+    }
+
+    fn slot(&mut self, token: Token) -> usize {
+        match self.tokens.iter().position(|t| *t == token) {
+            Some(slot) => slot,
+            None => {
+                self.tokens.push(token);
+                self.tokens.len() - 1
+            }
+        }
+    }
+
+    fn constant(&mut self, value: Variant) -> usize {
+        match self.consts.iter().position(|v| *v == value) {
+            Some(idx) => idx,
+            None => {
+                self.consts.push(value);
+                self.consts.len() - 1
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use context::Metadata;
+    use genet_abi::{
+        attr::{Attr, AttrClass},
+        fixed::{Fixed, MutFixed},
+        layer::{Layer, LayerClass},
+        slice::ByteSlice,
+    };
+    use optimizer::optimize;
+    use parser::parse;
+
+    fn layer<T: 'static + Into<Variant> + Send + Sync + Clone>(
+        id: &str,
+        attrs: Vec<(&str, T)>,
+    ) -> MutFixed<Layer> {
+        let class = Fixed::new(LayerClass::builder(Token::from(id)).build());
+        let mut layer = Layer::new(class, ByteSlice::new());
+        for (id, value) in attrs {
+            let typ = if id == "ipv4.src" { "@ipv4:addr" } else { "" };
+            let attr = Fixed::new(AttrClass::builder(id).typ(typ).value(value).build());
+            layer.add_attr(Attr::builder(attr).build());
+        }
+        MutFixed::new(layer)
+    }
+
+    const FILTERS: &[&str] = &[
+        "nil",
+        "true && false || true",
+        "!tcp",
+        "tcp && !udp",
+        "tcp || udp",
+        "tcp.dst == 80",
+        "tcp.dst != 80",
+        "tcp.dst == 80 || tcp.dst == 443",
+        "tcp.dst > 1000 && tcp.dst <= 8080",
+        "tcp.dst >= 80.0",
+        "tcp.dst < -1",
+        "tcp.dst == -1",
+        "tcp.seq >= 18446744073709551615",
+        "tcp.seq > 100000000000000000000",
+        "tcp.offset < 0 && tcp.offset > -100",
+        "100 < tcp.dst",
+        "tcp.dst + tcp.src * 2 - 1",
+        "tcp.dst / 0",
+        "tcp.dst % 7 == 4",
+        "-tcp.dst",
+        "+tcp.offset",
+        "~tcp.dst & 0xff | 1 ^ 3",
+        "tcp.dst << 2 >> 1",
+        "tcp.dst in {80, 443, 8000..9000}",
+        "tcp.dst in {22}",
+        "ipv4.src == @10.0.0.0/8",
+        "@10.0.0.0/8 != ipv4.src",
+        "ipv4.src in {@10.0.0.0/8, @192.168.0.0/16}",
+        "ipv4[-1].src == @192.168.0.0/16",
+        "ipv4[0].src in {@10.0.0.0/8}",
+        "tcp.src in {@0.0.0.0/0, 51234}",
+        "eth.type == @0.0.0.0/0",
+        "ipv4.src[0] == 10",
+        "ipv4.src[1:3]",
+        "ipv4.src contains x\"0001\"",
+        "ipv4.src startsWith x\"0a\"",
+        "ipv4.src matches \"^\\\\x0a\"",
+        "ipv4.src == 0x0a000001",
+        "ipv4.src > 0x0a000000",
+        "http.host == \"example.com\"",
+        "http.host contains \"ample\"",
+        "http.host matches \"^ex\"",
+        "http.host < \"f\"",
+        "len(http.host) == 11",
+        "lower(http.host) == http.host",
+        "count(ipv4) > 1",
+        "ipv4[0].src == ipv4[-1].src",
+        "any(ipv4.src == @10.0.0.1)",
+        "frame.index > 1000",
+        "frame.time >= t\"2024-01-01T10:00:00Z\"",
+        "frame.delta > 10ms && frame.input == 2",
+        "frame.length - frame.capturedLength",
+        "(tcp.dst == 80) == (tcp.src == 80)",
+        "tcp.dst && tcp.src",
+        "tcp.dst || nil",
+        "1.5 + tcp.dst > tcp.src",
+    ];
+
+    #[test]
+    fn differential() {
+        let empty: Vec<MutFixed<Layer>> = Vec::new();
+        let layers = vec![
+            layer("eth", vec![("eth.type", 0x0800u64)]),
+            layer(
+                "ipv4",
+                vec![(
+                    "ipv4.src",
+                    Variant::Buffer(vec![10, 0, 0, 1].into_boxed_slice()),
+                )],
+            ),
+            layer(
+                "ipv4",
+                vec![(
+                    "ipv4.src",
+                    Variant::Buffer(vec![192, 168, 0, 2].into_boxed_slice()),
+                )],
+            ),
+            layer(
+                "tcp",
+                vec![
+                    ("tcp.src", Variant::UInt64(51234)),
+                    ("tcp.dst", Variant::UInt64(443)),
+                    ("tcp.seq", Variant::UInt64(u64::max_value())),
+                    ("tcp.offset", Variant::Int64(-20)),
+                ],
+            ),
+            layer(
+                "http",
+                vec![(
+                    "http.host",
+                    Variant::String("example.com".to_string().into_boxed_str()),
+                )],
+            ),
+        ];
+        let udp = vec![layer("udp", vec![("udp.dst", 53u64)])];
+        let metadata = Metadata {
+            index: 1200,
+            timestamp: 1_704_103_200.5,
+            relative: 3.0,
+            delta: 0.02,
+            length: 1514,
+            captured_length: 128,
+            input: Some(2),
+        };
+        let contexts = vec![
+            Context::new(&empty),
+            Context::new(&layers),
+            Context::new(&udp),
+            Context::with_metadata(&layers, metadata),
+            Context::new(&layers).focus(1),
+        ];
+
+        for filter in FILTERS {
+            let expr = parse(filter).unwrap();
+            for expr in &[expr.clone(), optimize(expr)] {
+                let program = Program::compile(expr);
+                for (i, ctx) in contexts.iter().enumerate() {
+                    assert_eq!(
+                        program.run(ctx),
+                        expr.eval(ctx),
+                        "{} in context {}",
+                        filter,
+                        i
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn registers() {
+        let program = Program::compile(&parse("tcp.dst == 80 || tcp.dst == 443").unwrap());
+        assert_eq!(program.tokens.len(), 1);
+        assert_eq!(program.registers, 1);
+
+        let program = Program::compile(&parse("(1 + 2) * (3 + tcp.dst)").unwrap());
+        assert_eq!(program.registers, 3);
+    }
+}
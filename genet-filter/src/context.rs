@@ -1,15 +1,120 @@
-use genet_abi::{fixed::MutFixed, layer::Layer};
+use genet_abi::{fixed::MutFixed, layer::Layer, variant::Variant};
 
+/// A frame pseudo-field such as `frame.index`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Field {
+    Index,
+    Time,
+    TimeRelative,
+    Delta,
+    Length,
+    CapturedLength,
+    Input,
+}
+
+const FIELDS: &[(Field, &str)] = &[
+    (Field::Index, "frame.index"),
+    (Field::Time, "frame.time"),
+    (Field::TimeRelative, "frame.timeRelative"),
+    (Field::Delta, "frame.delta"),
+    (Field::Length, "frame.length"),
+    (Field::CapturedLength, "frame.capturedLength"),
+    (Field::Input, "frame.input"),
+];
+
+impl Field {
+    pub fn from_name(name: &str) -> Option<Field> {
+        FIELDS.iter().find(|(_, n)| *n == name).map(|(f, _)| *f)
+    }
+
+    pub fn name(self) -> &'static str {
+        FIELDS.iter().find(|(f, _)| *f == self).unwrap().1
+    }
+
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        FIELDS.iter().map(|(_, n)| *n)
+    }
+}
+
+/// Per-frame metadata exposed to filters as `frame.*` pseudo-fields.
+///
+/// Timestamps are in seconds; `timestamp` is since the Unix epoch,
+/// `relative` since the first frame and `delta` since the previous frame.
+#[derive(PartialEq, Clone, Copy, Default, Debug)]
+pub struct Metadata {
+    pub index: u32,
+    pub timestamp: f64,
+    pub relative: f64,
+    pub delta: f64,
+    pub length: u64,
+    pub captured_length: u64,
+    pub input: Option<u32>,
+}
+
+impl Metadata {
+    pub fn get(&self, field: Field) -> Variant {
+        match field {
+            Field::Index => Variant::UInt64(u64::from(self.index)),
+            Field::Time => Variant::Float64(self.timestamp),
+            Field::TimeRelative => Variant::Float64(self.relative),
+            Field::Delta => Variant::Float64(self.delta),
+            Field::Length => Variant::UInt64(self.length),
+            Field::CapturedLength => Variant::UInt64(self.captured_length),
+            Field::Input => self
+                .input
+                .map_or(Variant::Nil, |id| Variant::UInt64(u64::from(id))),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Context<'a> {
     layers: &'a [MutFixed<Layer>],
+    metadata: Option<Metadata>,
+    focus: Option<usize>,
 }
 
 impl<'a> Context<'a> {
     pub fn new(layers: &'a [MutFixed<Layer>]) -> Self {
-        Context { layers }
+        Context {
+            layers,
+            metadata: None,
+            focus: None,
+        }
+    }
+
+    /// Creates a context for a frame, with its metadata.
+    pub fn with_metadata(layers: &'a [MutFixed<Layer>], metadata: Metadata) -> Self {
+        Context {
+            layers,
+            metadata: Some(metadata),
+            focus: None,
+        }
     }
 
     pub fn layers(&self) -> &'a [MutFixed<Layer>] {
         self.layers
     }
+
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    /// Returns a context in which attributes are only looked up
+    /// in the layer at `index`.
+    ///
+    /// This is used to evaluate an expression for each occurrence of a layer.
+    pub fn focus(&self, index: usize) -> Context<'a> {
+        Context {
+            layers: self.layers,
+            metadata: self.metadata,
+            focus: Some(index),
+        }
+    }
+
+    pub fn focused_layer(&self) -> Option<&'a Layer> {
+        self.focus
+            .and_then(|index| self.layers.get(index))
+            .map(|layer| &**layer)
+    }
 }
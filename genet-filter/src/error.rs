@@ -0,0 +1,202 @@
+use parser::Rule;
+use pest::error::{Error as PestError, ErrorVariant, InputLocation};
+use std::{error, fmt, ops::Range};
+
+/// A filter compile error.
+///
+/// `span` is a byte range in the filter source, suitable for highlighting.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Error {
+    message: String,
+    span: Range<usize>,
+    expected: Vec<String>,
+    suggestion: Option<String>,
+}
+
+impl Error {
+    pub fn new(message: &str, span: Range<usize>) -> Error {
+        Error {
+            message: message.to_string(),
+            span,
+            expected: Vec::new(),
+            suggestion: None,
+        }
+    }
+
+    /// Creates an error for an unknown member,
+    /// suggesting the closest of the known tokens.
+    pub fn unknown_member<'a, I>(name: &str, span: Range<usize>, tokens: I) -> Error
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        Error {
+            suggestion: suggest(name, tokens),
+            ..Error::new(&format!("unknown attribute: {}", name), span)
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// Returns the expected tokens in plain words.
+    pub fn expected(&self) -> &[String] {
+        &self.expected
+    }
+
+    pub fn suggestion(&self) -> Option<&str> {
+        self.suggestion.as_ref().map(|s| s.as_str())
+    }
+}
+
+impl From<PestError<Rule>> for Error {
+    fn from(err: PestError<Rule>) -> Error {
+        let span = match err.location {
+            InputLocation::Pos(pos) => pos..pos,
+            InputLocation::Span((start, end)) => start..end,
+        };
+        match err.variant {
+            ErrorVariant::ParsingError { positives, .. } => {
+                let mut expected = Vec::<String>::new();
+                for rule in positives {
+                    let desc = describe(rule).to_string();
+                    if !expected.contains(&desc) {
+                        expected.push(desc);
+                    }
+                }
+                let message = if expected.is_empty() {
+                    "unexpected token".to_string()
+                } else {
+                    format!("expected {}", join(&expected))
+                };
+                Error {
+                    expected,
+                    ..Error::new(&message, span)
+                }
+            }
+            ErrorVariant::CustomError { message } => Error::new(&message, span),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.message, self.span.start)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " (did you mean {}?)", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+fn describe(rule: Rule) -> &'static str {
+    match rule {
+        Rule::EOI => "end of filter",
+        Rule::member | Rule::member_occurrence => "attribute",
+        Rule::call => "function call",
+        Rule::macro_exp => "macro",
+        Rule::dec_integer
+        | Rule::hex_integer
+        | Rule::oct_integer
+        | Rule::bin_integer
+        | Rule::float => "number",
+        Rule::duration => "duration",
+        Rule::datetime => "date-time",
+        Rule::string => "string",
+        Rule::hex_string => "byte string",
+        Rule::nil => "nil",
+        Rule::boolean => "boolean",
+        Rule::set | Rule::set_value | Rule::set_range => "set",
+        Rule::op_eq
+        | Rule::op_ne
+        | Rule::op_lt
+        | Rule::op_gt
+        | Rule::op_lte
+        | Rule::op_gte
+        | Rule::op_logical_and
+        | Rule::op_logical_or
+        | Rule::op_add
+        | Rule::op_sub
+        | Rule::op_mul
+        | Rule::op_div
+        | Rule::op_rem
+        | Rule::op_bitwise_and
+        | Rule::op_bitwise_or
+        | Rule::op_bitwise_xor
+        | Rule::op_shl
+        | Rule::op_shr
+        | Rule::op_in
+        | Rule::op_contains
+        | Rule::op_starts_with
+        | Rule::op_matches => "operator",
+        Rule::postfix_index | Rule::postfix_slice => "index",
+        _ => "expression",
+    }
+}
+
+fn join(words: &[String]) -> String {
+    match words.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} or {}", rest.join(", "), last),
+        _ => words.join(""),
+    }
+}
+
+/// Returns the candidate closest to `name` by edit distance,
+/// if it is close enough to be a plausible typo.
+fn suggest<'a, I>(name: &str, candidates: I) -> Option<String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let max = (name.chars().count() / 3).max(1);
+    candidates
+        .map(|c| (distance(name, c), c))
+        .filter(|(d, _)| *d <= max)
+        .min()
+        .map(|(_, c)| c.to_string())
+}
+
+fn distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { prev } else { prev + 1 };
+            prev = row[j + 1];
+            row[j + 1] = cost.min(row[j] + 1).min(prev + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggest() {
+        let tokens = ["tcp", "tcp.src", "tcp.dst", "udp.dst", "ipv4.src"];
+        assert_eq!(
+            super::suggest("tcp.dts", tokens.iter().cloned()),
+            Some("tcp.dst".to_string())
+        );
+        assert_eq!(
+            super::suggest("ipv4.sr", tokens.iter().cloned()),
+            Some("ipv4.src".to_string())
+        );
+        assert_eq!(super::suggest("http", tokens.iter().cloned()), None);
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("", "abc"), 3);
+    }
+}
@@ -0,0 +1,301 @@
+use ast::Expr;
+use context::Context;
+use genet_abi::variant::Variant;
+use num_bigint::{BigInt, Sign};
+use num_traits::{FromPrimitive, Num, Signed};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+use variant::{self, VariantExt};
+
+/// A function callable from filter expressions.
+///
+/// Arguments are passed unevaluated, so a function can inspect
+/// tokens directly (as `count` does) or evaluate them with the context.
+#[derive(Clone)]
+pub struct Function {
+    name: String,
+    args: usize,
+    func: fn(&Context, &[Expr]) -> Variant,
+    builtin: bool,
+}
+
+impl Function {
+    pub fn new(name: &str, args: usize, func: fn(&Context, &[Expr]) -> Variant) -> Function {
+        Function {
+            name: name.to_string(),
+            args,
+            func,
+            builtin: false,
+        }
+    }
+
+    fn builtin(name: &str, args: usize, func: fn(&Context, &[Expr]) -> Variant) -> Function {
+        Function {
+            builtin: true,
+            ..Function::new(name, args, func)
+        }
+    }
+
+    /// Returns true if the function is one of the built-in functions,
+    /// which only look at the layers and attributes of their arguments.
+    pub fn is_builtin(&self) -> bool {
+        self.builtin
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the number of arguments the function takes.
+    pub fn args(&self) -> usize {
+        self.args
+    }
+
+    pub fn call(&self, ctx: &Context, args: &[Expr]) -> Variant {
+        (self.func)(ctx, args)
+    }
+}
+
+impl PartialEq for Function {
+    fn eq(&self, other: &Function) -> bool {
+        self.name == other.name && self.args == other.args
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Function({:?}, {})", self.name, self.args)
+    }
+}
+
+/// A set of functions, known tokens and named macros available to the parser.
+///
+/// `Registry::default()` contains the built-in functions.
+/// If any token is registered, members not in the registry are rejected
+/// and the closest known token is suggested.
+#[derive(Clone, Debug)]
+pub struct Registry {
+    functions: HashMap<String, Function>,
+    tokens: HashSet<String>,
+    macros: HashMap<String, String>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry {
+            functions: HashMap::new(),
+            tokens: HashSet::new(),
+            macros: HashMap::new(),
+        }
+    }
+
+    /// Registers a function, replacing any function with the same name.
+    pub fn register(&mut self, func: Function) {
+        self.functions.insert(func.name().to_string(), func);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    pub fn functions(&self) -> impl Iterator<Item = &Function> {
+        self.functions.values()
+    }
+
+    /// Registers a known layer or attribute token.
+    pub fn add_token(&mut self, id: &str) {
+        self.tokens.insert(id.to_string());
+    }
+
+    pub fn tokens(&self) -> impl Iterator<Item = &str> {
+        self.tokens.iter().map(|s| s.as_str())
+    }
+
+    /// Registers a named filter snippet referred to as `@name`.
+    pub fn add_macro(&mut self, name: &str, filter: &str) {
+        self.macros.insert(name.to_string(), filter.to_string());
+    }
+
+    /// Returns the name and the filter of a macro.
+    pub fn get_macro(&self, name: &str) -> Option<(&str, &str)> {
+        self.macros
+            .get_key_value(name)
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Returns true if the member is known, or if no token is registered.
+    pub fn is_known(&self, id: &str) -> bool {
+        self.tokens.is_empty() || self.tokens.contains(id)
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        let mut registry = Registry::new();
+        registry.register(Function::builtin("len", 1, len));
+        registry.register(Function::builtin("lower", 1, lower));
+        registry.register(Function::builtin("upper", 1, upper));
+        registry.register(Function::builtin("count", 1, count));
+        registry.register(Function::builtin("abs", 1, abs));
+        registry.register(Function::builtin("hex", 1, hex));
+        registry.register(Function::builtin("int", 1, int));
+        registry.register(Function::builtin("any", 1, any));
+        registry.register(Function::builtin("all", 1, all));
+        registry.register(Function::builtin("has", 1, has));
+        registry.register(Function::builtin("exists", 1, has));
+        registry.register(Function::builtin("error", 1, error));
+        registry
+    }
+}
+
+/// Returns the number of characters in a string or bytes in a buffer.
+fn len(ctx: &Context, args: &[Expr]) -> Variant {
+    match args[0].eval(ctx) {
+        Variant::String(s) => Variant::UInt64(s.chars().count() as u64),
+        v => v
+            .as_bytes()
+            .map_or(Variant::Nil, |b| Variant::UInt64(b.len() as u64)),
+    }
+}
+
+fn lower(ctx: &Context, args: &[Expr]) -> Variant {
+    match args[0].eval(ctx) {
+        Variant::String(s) => Variant::String(s.to_lowercase().into_boxed_str()),
+        v => v.as_bytes().map_or(Variant::Nil, |b| {
+            Variant::Buffer(b.to_ascii_lowercase().into_boxed_slice())
+        }),
+    }
+}
+
+fn upper(ctx: &Context, args: &[Expr]) -> Variant {
+    match args[0].eval(ctx) {
+        Variant::String(s) => Variant::String(s.to_uppercase().into_boxed_str()),
+        v => v.as_bytes().map_or(Variant::Nil, |b| {
+            Variant::Buffer(b.to_ascii_uppercase().into_boxed_slice())
+        }),
+    }
+}
+
+/// Returns the number of layers and attributes in the frame matching the token.
+fn count(ctx: &Context, args: &[Expr]) -> Variant {
+    let id = match &args[0] {
+        Expr::Token(id) => *id,
+        _ => return Variant::Nil,
+    };
+    let count = ctx
+        .layers()
+        .iter()
+        .map(|layer| {
+            let attrs = layer
+                .headers()
+                .iter()
+                .chain(layer.attrs().iter())
+                .filter(|a| a.id() == id)
+                .count();
+            attrs + if layer.id() == id { 1 } else { 0 }
+        })
+        .sum::<usize>();
+    Variant::UInt64(count as u64)
+}
+
+fn abs(ctx: &Context, args: &[Expr]) -> Variant {
+    match args[0].eval(ctx) {
+        Variant::Float64(v) => Variant::Float64(v.abs()),
+        v @ Variant::Int64(_) | v @ Variant::UInt64(_) | v @ Variant::BigInt(_) => {
+            variant::to_bigint(&v).map_or(Variant::Nil, |v| variant::from_bigint(v.abs()))
+        }
+        _ => Variant::Nil,
+    }
+}
+
+/// Formats bytes or an integer as a lowercase hexadecimal string.
+fn hex(ctx: &Context, args: &[Expr]) -> Variant {
+    let value = args[0].eval(ctx);
+    let hex = if let Some(b) = value.as_bytes() {
+        b.iter().map(|b| format!("{:02x}", b)).collect::<String>()
+    } else if let Some(v) = variant::to_bigint(&value) {
+        v.to_str_radix(16)
+    } else {
+        return Variant::Nil;
+    };
+    Variant::String(hex.into_boxed_str())
+}
+
+/// Converts a value into an integer.
+///
+/// Strings accept the same integer notations as filter literals,
+/// bytes are read as unsigned big-endian integers and floats are truncated.
+fn int(ctx: &Context, args: &[Expr]) -> Variant {
+    match args[0].eval(ctx) {
+        Variant::Bool(v) => Variant::UInt64(if v { 1 } else { 0 }),
+        Variant::Float64(v) => {
+            BigInt::from_f64(v.trunc()).map_or(Variant::Nil, variant::from_bigint)
+        }
+        Variant::String(s) => parse_int(s.trim()).map_or(Variant::Nil, variant::from_bigint),
+        v => variant::to_bigint(&v).map_or(Variant::Nil, variant::from_bigint),
+    }
+}
+
+/// Returns true if the expression holds for any of the layers
+/// that have a token of the expression.
+fn any(ctx: &Context, args: &[Expr]) -> Variant {
+    Variant::Bool(occurrences(ctx, &args[0]).any(|ctx| args[0].eval(&ctx).is_truthy()))
+}
+
+/// Returns true if the frame has any layer with a token of the expression,
+/// and the expression holds for all of them.
+fn all(ctx: &Context, args: &[Expr]) -> Variant {
+    let mut found = false;
+    let matched = occurrences(ctx, &args[0]).all(|ctx| {
+        found = true;
+        args[0].eval(&ctx).is_truthy()
+    });
+    Variant::Bool(found && matched)
+}
+
+/// Returns true if the layer or the attribute is present in the frame,
+/// even if its value is falsy or cannot be decoded.
+fn has(ctx: &Context, args: &[Expr]) -> Variant {
+    Variant::Bool(args[0].resolve(ctx).is_some())
+}
+
+/// Returns true if the attribute is present in the frame but cannot be decoded.
+fn error(ctx: &Context, args: &[Expr]) -> Variant {
+    Variant::Bool(match args[0].resolve(ctx) {
+        Some((layer, Some(attr))) => attr.try_get(layer).is_err(),
+        _ => false,
+    })
+}
+
+/// Returns a context focused on each layer that has any of the tokens
+/// in the expression, as its id or as an attribute.
+fn occurrences<'a>(ctx: &Context<'a>, expr: &Expr) -> impl Iterator<Item = Context<'a>> {
+    let tokens = expr.tokens();
+    let layers = ctx.layers();
+    let base = ctx.clone();
+    (0..layers.len())
+        .filter(move |i| Expr::has_any(&layers[*i], &tokens))
+        .map(move |i| base.focus(i))
+}
+
+fn parse_int(s: &str) -> Option<BigInt> {
+    let (sign, s) = if s.starts_with('-') {
+        (Sign::Minus, &s[1..])
+    } else {
+        (Sign::Plus, s)
+    };
+    let v = if s.starts_with("0x") {
+        BigInt::from_str_radix(&s[2..], 16)
+    } else if s.starts_with("0o") {
+        BigInt::from_str_radix(&s[2..], 8)
+    } else if s.starts_with("0b") {
+        BigInt::from_str_radix(&s[2..], 2)
+    } else {
+        BigInt::from_str_radix(s, 10)
+    };
+    v.ok()
+        .filter(|_| !s.starts_with('+') && !s.starts_with('-'))
+        .map(|v| if sign == Sign::Minus { -v } else { v })
+}
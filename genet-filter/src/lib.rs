@@ -3,6 +3,7 @@ extern crate hwaddr;
 extern crate num_bigint;
 extern crate num_traits;
 extern crate pest;
+extern crate regex;
 extern crate serde;
 extern crate serde_json;
 
@@ -13,48 +14,113 @@ extern crate pest_derive;
 extern crate arrayref;
 
 use ast::Expr;
+use bytecode::Program;
 use context::Context;
-use parser::parse;
-use result::Result;
-use std::fmt;
+use error::Error;
+use function::Registry;
+use genet_abi::token::Token;
+use parser::parse_with;
+use std::collections::HashSet;
 use variant::VariantExt;
 
 pub mod ast;
+pub mod bpf;
+pub mod bytecode;
 pub mod context;
+pub mod error;
+pub mod function;
+pub mod optimizer;
 pub mod parser;
+pub mod pattern;
+pub mod prefix;
 pub mod result;
+pub mod set;
+pub mod time;
 pub mod unparser;
 pub mod variant;
 
 #[derive(Clone, Debug)]
 pub struct Filter {
     expr: Expr,
+    program: Program,
+    tokens: Option<HashSet<Token>>,
 }
 
 impl Filter {
-    pub fn compile(filter: &str) -> Result<Filter> {
-        match parse(filter) {
-            Ok(expr) => Ok(Filter { expr }),
-            Err(err) => Err(Box::new(Error(format!("{}", err)))),
-        }
+    pub fn compile(filter: &str) -> Result<Filter, Error> {
+        Self::compile_with(filter, &Registry::default())
     }
 
-    pub fn test(&self, ctx: &Context) -> bool {
-        self.expr.eval(ctx).is_truthy()
+    /// Compiles a filter with the functions in the registry.
+    pub fn compile_with(filter: &str, functions: &Registry) -> Result<Filter, Error> {
+        parse_with(filter, functions).map(|expr| {
+            let expr = optimizer::optimize(expr);
+            let tokens = optimizer::tokens(&expr);
+            let program = Program::compile(&expr);
+            Filter {
+                expr,
+                program,
+                tokens,
+            }
+        })
     }
-}
 
-#[derive(Debug)]
-struct Error(String);
+    /// Returns the tokens the filter depends on, if any.
+    ///
+    /// Frames with none of these tokens as a layer id or an attribute id
+    /// never match the filter.
+    pub fn tokens(&self) -> Option<&HashSet<Token>> {
+        self.tokens.as_ref()
+    }
 
-impl std::error::Error for Error {
-    fn description(&self) -> &str {
-        &self.0
+    /// Translates the filter into a classic BPF program for capture-time filtering.
+    pub fn to_bpf(&self, link: u32) -> Result<Vec<bpf::Instruction>, bpf::Error> {
+        bpf::compile(&self.expr, link)
+    }
+
+    pub fn test(&self, ctx: &Context) -> bool {
+        if let Some(tokens) = &self.tokens {
+            if !ctx
+                .layers()
+                .iter()
+                .any(|layer| Expr::has_any(layer, tokens))
+            {
+                return false;
+            }
+        }
+        self.program.run(ctx).is_truthy()
     }
 }
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use genet_abi::{
+        attr::{Attr, AttrClass},
+        fixed::{Fixed, MutFixed},
+        layer::{Layer, LayerClass},
+        slice::ByteSlice,
+        variant::Variant,
+    };
+
+    #[test]
+    fn fast_reject() {
+        let class = Fixed::new(LayerClass::builder(Token::from("[link-1]")).build());
+        let mut link = Layer::new(class, ByteSlice::new());
+        let attr = Fixed::new(AttrClass::builder("link.timestamp").value(1.5).build());
+        link.add_attr(Attr::builder(attr).build());
+        let layers = vec![MutFixed::new(link)];
+        let ctx = Context::new(&layers);
+
+        assert!(Filter::compile("link.timestamp > 0").unwrap().test(&ctx));
+        assert!(!Filter::compile("tcp.dst == 80").unwrap().test(&ctx));
+
+        let mut functions = Registry::default();
+        functions.register(function::Function::new("link", 0, |ctx, _| {
+            Variant::Bool(!ctx.layers().is_empty())
+        }));
+        assert!(Filter::compile_with("link()", &functions)
+            .unwrap()
+            .test(&ctx));
     }
 }
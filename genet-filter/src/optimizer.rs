@@ -0,0 +1,274 @@
+use ast::Expr;
+use context::Context;
+use genet_abi::{token::Token, variant::Variant};
+use std::collections::HashSet;
+use variant::VariantExt;
+
+/// Rewrites an expression into an equivalent one that is cheaper to evaluate.
+///
+/// Constant subtrees are folded into literals, boolean identities are
+/// simplified and the operands of `&&` and `||` chains are ordered by
+/// estimated cost so that cheap tests short-circuit expensive ones.
+pub fn optimize(expr: Expr) -> Expr {
+    let expr = map_children(expr, optimize);
+    let expr = if is_foldable(&expr) {
+        Expr::Literal(expr.eval(&Context::new(&[])))
+    } else {
+        expr
+    };
+    match expr {
+        Expr::LogicalAnd(..) => optimize_chain(expr, true),
+        Expr::LogicalOr(..) => optimize_chain(expr, false),
+        Expr::LogicalNegation(v) => match *v {
+            Expr::LogicalNegation(ref inner) if is_boolean(inner) => (**inner).clone(),
+            v => Expr::LogicalNegation(Box::new(v)),
+        },
+        expr => expr,
+    }
+}
+
+/// Returns the tokens the expression depends on.
+///
+/// A frame in which none of the returned tokens appear,
+/// either as a layer id or as an attribute id, never matches.
+/// Returns `None` if the expression may match such a frame as well,
+/// or calls a function that may look at the frame beyond its arguments.
+pub fn tokens(expr: &Expr) -> Option<HashSet<Token>> {
+    let mut opaque = false;
+    expr.visit(&mut |e| match e {
+        Expr::Frame(_) => opaque = true,
+        Expr::Call(func, _) if !func.is_builtin() => opaque = true,
+        _ => {}
+    });
+    if opaque || expr.eval(&Context::new(&[])).is_truthy() {
+        return None;
+    }
+    Some(expr.tokens())
+}
+
+fn optimize_chain(expr: Expr, and: bool) -> Expr {
+    let mut operands = Vec::new();
+    flatten(expr, and, &mut operands);
+
+    // `false` absorbs an `&&` chain, `true` absorbs an `||` chain.
+    if operands
+        .iter()
+        .any(|e| is_literal(e) && e.eval(&Context::new(&[])).is_truthy() != and)
+    {
+        return Expr::Literal(Variant::Bool(!and));
+    }
+    let len = operands.len();
+    operands.retain(|e| !is_literal(e));
+    if operands.is_empty() {
+        return Expr::Literal(Variant::Bool(and));
+    }
+    if operands.len() == 1 && !is_boolean(&operands[0]) && len > 1 {
+        // Keep the chain so that the result is still converted to a bool.
+        operands.push(Expr::Literal(Variant::Bool(and)));
+    }
+
+    operands.sort_by_key(cost);
+    let mut operands = operands.into_iter();
+    let first = operands.next().unwrap();
+    operands.fold(first, |lhs, rhs| {
+        if and {
+            Expr::LogicalAnd(Box::new(lhs), Box::new(rhs))
+        } else {
+            Expr::LogicalOr(Box::new(lhs), Box::new(rhs))
+        }
+    })
+}
+
+fn flatten(expr: Expr, and: bool, operands: &mut Vec<Expr>) {
+    match expr {
+        Expr::LogicalAnd(l, r) if and => {
+            flatten(*l, and, operands);
+            flatten(*r, and, operands);
+        }
+        Expr::LogicalOr(l, r) if !and => {
+            flatten(*l, and, operands);
+            flatten(*r, and, operands);
+        }
+        expr => operands.push(expr),
+    }
+}
+
+fn is_literal(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(_) => true,
+        _ => false,
+    }
+}
+
+/// Returns true if the expression always evaluates to `Variant::Bool`.
+fn is_boolean(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(Variant::Bool(_))
+        | Expr::CmpEq(..)
+        | Expr::CmpNotEq(..)
+        | Expr::CmpLt(..)
+        | Expr::CmpGt(..)
+        | Expr::CmpLte(..)
+        | Expr::CmpGte(..)
+        | Expr::LogicalAnd(..)
+        | Expr::LogicalOr(..)
+        | Expr::LogicalNegation(..)
+        | Expr::In(..)
+        | Expr::Contains(..)
+        | Expr::StartsWith(..)
+        | Expr::Matches(..) => true,
+        _ => false,
+    }
+}
+
+/// Returns true if the expression does not depend on the frame
+/// and can be replaced by its value.
+fn is_foldable(expr: &Expr) -> bool {
+    match expr {
+        // Leaves, and operands that only make sense as a part of their parent.
+        Expr::Literal(_) | Expr::Macro(_) | Expr::Prefix(_) | Expr::Set(_) | Expr::Pattern(_) => {
+            false
+        }
+        _ => is_constant(expr),
+    }
+}
+
+fn is_constant(expr: &Expr) -> bool {
+    let mut constant = true;
+    expr.visit(&mut |e| match e {
+        Expr::Token(_) | Expr::Occurrence(..) | Expr::Call(..) | Expr::Frame(_) => constant = false,
+        _ => {}
+    });
+    constant
+}
+
+/// Returns the estimated cost of evaluating the expression.
+fn cost(expr: &Expr) -> u32 {
+    let mut cost = 0;
+    expr.visit(&mut |e| {
+        cost += match e {
+            Expr::Literal(_) | Expr::Macro(_) | Expr::Prefix(_) | Expr::Set(_) => 0,
+            Expr::Frame(_) => 2,
+            Expr::Token(_) | Expr::Occurrence(..) => 10,
+            Expr::Call(..) => 20,
+            Expr::Contains(..) | Expr::StartsWith(..) => 5,
+            Expr::Matches(..) => 30,
+            _ => 1,
+        }
+    });
+    cost
+}
+
+/// Applies `f` to the direct children of the expression.
+fn map_children<F: Fn(Expr) -> Expr>(expr: Expr, f: F) -> Expr {
+    let b = |e: Box<Expr>| Box::new(f(*e));
+    match expr {
+        Expr::LogicalNegation(v) => Expr::LogicalNegation(b(v)),
+        Expr::UnaryPlus(v) => Expr::UnaryPlus(b(v)),
+        Expr::UnaryNegation(v) => Expr::UnaryNegation(b(v)),
+        Expr::BitwiseNot(v) => Expr::BitwiseNot(b(v)),
+        Expr::CmpEq(l, r) => Expr::CmpEq(b(l), b(r)),
+        Expr::CmpNotEq(l, r) => Expr::CmpNotEq(b(l), b(r)),
+        Expr::CmpLt(l, r) => Expr::CmpLt(b(l), b(r)),
+        Expr::CmpGt(l, r) => Expr::CmpGt(b(l), b(r)),
+        Expr::CmpLte(l, r) => Expr::CmpLte(b(l), b(r)),
+        Expr::CmpGte(l, r) => Expr::CmpGte(b(l), b(r)),
+        Expr::LogicalAnd(l, r) => Expr::LogicalAnd(b(l), b(r)),
+        Expr::LogicalOr(l, r) => Expr::LogicalOr(b(l), b(r)),
+        Expr::Add(l, r) => Expr::Add(b(l), b(r)),
+        Expr::Sub(l, r) => Expr::Sub(b(l), b(r)),
+        Expr::Mul(l, r) => Expr::Mul(b(l), b(r)),
+        Expr::Div(l, r) => Expr::Div(b(l), b(r)),
+        Expr::Rem(l, r) => Expr::Rem(b(l), b(r)),
+        Expr::BitwiseAnd(l, r) => Expr::BitwiseAnd(b(l), b(r)),
+        Expr::BitwiseOr(l, r) => Expr::BitwiseOr(b(l), b(r)),
+        Expr::BitwiseXor(l, r) => Expr::BitwiseXor(b(l), b(r)),
+        Expr::ShiftLeft(l, r) => Expr::ShiftLeft(b(l), b(r)),
+        Expr::ShiftRight(l, r) => Expr::ShiftRight(b(l), b(r)),
+        Expr::In(l, r) => Expr::In(b(l), r),
+        Expr::Contains(l, r) => Expr::Contains(b(l), b(r)),
+        Expr::StartsWith(l, r) => Expr::StartsWith(b(l), b(r)),
+        Expr::Matches(l, r) => Expr::Matches(b(l), r),
+        Expr::Index(l, r) => Expr::Index(b(l), b(r)),
+        Expr::Slice(v, start, end) => Expr::Slice(b(v), start.map(&b), end.map(&b)),
+        Expr::Call(func, args) => Expr::Call(func, args.into_iter().map(&f).collect()),
+        expr => expr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use function::{Function, Registry};
+    use parser::{parse, parse_with};
+    use unparser::unparse;
+
+    fn opt(filter: &str) -> String {
+        unparse(&optimize(parse(filter).unwrap()))
+    }
+
+    #[test]
+    fn fold() {
+        assert_eq!(opt("tcp.dst == 1 + 2 * 3"), "tcp.dst == 7");
+        assert_eq!(opt("len(\"abc\") + (2 << 2)"), "len(\"abc\") + 8");
+        assert_eq!(opt("1 in {1, 2} && 3 > 2"), "true");
+        assert_eq!(opt("ipv4.src == @10.0.0.0/8"), "ipv4.src == @10.0.0.0/8");
+        assert_eq!(opt("tcp.dst in {80, 443}"), "tcp.dst in {80, 443}");
+        assert_eq!(opt("x\"0102\"[1]"), "2");
+    }
+
+    #[test]
+    fn identity() {
+        assert_eq!(opt("tcp.dst == 80 && true"), "tcp.dst == 80");
+        assert_eq!(opt("tcp && true"), "true && tcp");
+        assert_eq!(opt("tcp && 1 > 2 && udp"), "false");
+        assert_eq!(opt("tcp || 1 < 2"), "true");
+        assert_eq!(opt("false || tcp.dst == 80"), "tcp.dst == 80");
+        assert_eq!(opt("!!(tcp.dst == 80)"), "tcp.dst == 80");
+        assert_eq!(opt("!!tcp"), "!!tcp");
+    }
+
+    #[test]
+    fn order() {
+        assert_eq!(
+            opt("ipv4.src matches \"^a\" && tcp.dst == 80 && udp"),
+            "udp && tcp.dst == 80 && ipv4.src matches \"^a\""
+        );
+        assert_eq!(
+            opt("(len(http.host) > 3 || eth) && tcp"),
+            "tcp && (eth || len(http.host) > 3)"
+        );
+    }
+
+    #[test]
+    fn tokens() {
+        let deps = |filter: &str| {
+            super::tokens(&parse(filter).unwrap()).map(|tokens| {
+                let mut tokens = tokens.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+                tokens.sort();
+                tokens
+            })
+        };
+        assert_eq!(
+            deps("tcp.dst == 80 && ipv4"),
+            Some(vec!["ipv4".into(), "tcp.dst".into()])
+        );
+        assert_eq!(
+            deps("tcp.flags.syn || udp[0].dst == 53"),
+            Some(vec!["tcp.flags.syn".into(), "udp".into()])
+        );
+        assert_eq!(deps("len(http.host) > 3"), Some(vec!["http.host".into()]));
+        assert_eq!(deps("false"), Some(vec![]));
+        assert_eq!(deps("!tcp"), None);
+        assert_eq!(deps("tcp.dst != 80"), None);
+        assert_eq!(deps("true"), None);
+        assert_eq!(deps("frame.index > 10 && tcp"), None);
+
+        let mut functions = Registry::default();
+        functions.register(Function::new("custom", 0, |_, _| Variant::Bool(true)));
+        assert_eq!(
+            super::tokens(&parse_with("custom() && tcp", &functions).unwrap()),
+            None
+        );
+    }
+}
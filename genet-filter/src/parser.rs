@@ -1,62 +1,145 @@
 use ast::Expr;
+use context::Field;
+use error::Error;
+use function::Registry;
 use genet_abi::{token::Token, variant::Variant};
 use hwaddr::HwAddr;
 use num_bigint::BigInt;
 use num_traits::Num;
+use pattern::Pattern;
 use pest::{
-    error::Error,
     iterators::Pair,
     prec_climber::{Assoc, Operator, PrecClimber},
     Parser,
 };
+use prefix::Prefix;
 use serde_json;
-use std::net::{Ipv4Addr, Ipv6Addr};
+use set::{Set, SetElement};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    ops::Range,
+};
+use time;
 use variant::VariantExt;
 
 #[derive(Parser)]
 #[grammar = "syntax.pest"]
 pub struct FilterParser;
 
-pub fn parse(filter: &str) -> Result<Expr, Error<Rule>> {
+pub fn parse(filter: &str) -> Result<Expr, Error> {
+    parse_with(filter, &Registry::default())
+}
+
+/// Parses a filter, resolving function calls against the registry.
+pub fn parse_with(filter: &str, functions: &Registry) -> Result<Expr, Error> {
+    parse_scope(
+        filter,
+        &Scope {
+            functions,
+            macros: Vec::new(),
+        },
+    )
+}
+
+/// The names visible while parsing, and the macros being expanded.
+struct Scope<'a> {
+    functions: &'a Registry,
+    macros: Vec<&'a str>,
+}
+
+fn parse_scope(filter: &str, scope: &Scope) -> Result<Expr, Error> {
     let result = FilterParser::parse(Rule::filter, filter);
     match result {
-        Ok(mut expr) => Ok(consume_expr(
-            expr.next().unwrap().into_inner().next().unwrap(),
-        )),
-        Err(e) => Err(e),
+        Ok(mut expr) => consume_expr(expr.next().unwrap().into_inner().next().unwrap(), scope),
+        Err(e) => Err(Error::from(e)),
     }
 }
 
-fn parse_macro(exp: String) -> Expr {
+fn parse_macro(exp: String, span: Range<usize>) -> Result<Expr, Error> {
+    let mut parts = exp.splitn(2, '/');
+    if let (Some(addr), Some(_)) = (parts.next(), parts.next()) {
+        if addr.parse::<IpAddr>().is_ok() {
+            return exp
+                .parse::<Prefix>()
+                .map(Expr::Prefix)
+                .map_err(|_| Error::new("invalid address prefix", span));
+        }
+    }
     if let Ok(addr) = exp.parse::<Ipv4Addr>() {
-        return Expr::Literal(Variant::Buffer(addr.octets().to_vec().into_boxed_slice()));
+        return Ok(Expr::Literal(Variant::Buffer(
+            addr.octets().to_vec().into_boxed_slice(),
+        )));
     }
     if let Ok(addr) = exp.parse::<Ipv6Addr>() {
-        return Expr::Literal(Variant::Buffer(addr.octets().to_vec().into_boxed_slice()));
+        return Ok(Expr::Literal(Variant::Buffer(
+            addr.octets().to_vec().into_boxed_slice(),
+        )));
     }
     if let Ok(addr) = exp.parse::<HwAddr>() {
-        return Expr::Literal(Variant::Buffer(addr.octets().to_vec().into_boxed_slice()));
+        return Ok(Expr::Literal(Variant::Buffer(
+            addr.octets().to_vec().into_boxed_slice(),
+        )));
     }
-    Expr::Macro(exp)
+    Err(Error::new(&format!("unknown macro `@{}`", exp), span))
 }
 
-fn consume_expr(pair: Pair<Rule>) -> Expr {
+fn consume_expr(pair: Pair<Rule>, scope: &Scope) -> Result<Expr, Error> {
     let cmp = Operator::new(Rule::op_lt, Assoc::Left)
         | Operator::new(Rule::op_lte, Assoc::Left)
         | Operator::new(Rule::op_gt, Assoc::Left)
-        | Operator::new(Rule::op_gte, Assoc::Left);
+        | Operator::new(Rule::op_gte, Assoc::Left)
+        | Operator::new(Rule::op_in, Assoc::Left)
+        | Operator::new(Rule::op_contains, Assoc::Left)
+        | Operator::new(Rule::op_starts_with, Assoc::Left)
+        | Operator::new(Rule::op_matches, Assoc::Left);
+    let additive =
+        Operator::new(Rule::op_add, Assoc::Left) | Operator::new(Rule::op_sub, Assoc::Left);
+    let multiplicative = Operator::new(Rule::op_mul, Assoc::Left)
+        | Operator::new(Rule::op_div, Assoc::Left)
+        | Operator::new(Rule::op_rem, Assoc::Left);
+
+    // Operators are listed in order of increasing precedence.
     let climber = PrecClimber::new(vec![
-        cmp,
-        Operator::new(Rule::op_eq, Assoc::Left) | Operator::new(Rule::op_ne, Assoc::Left),
-        Operator::new(Rule::op_logical_and, Assoc::Left),
         Operator::new(Rule::op_logical_or, Assoc::Left),
+        Operator::new(Rule::op_logical_and, Assoc::Left),
+        Operator::new(Rule::op_eq, Assoc::Left) | Operator::new(Rule::op_ne, Assoc::Left),
+        cmp,
+        Operator::new(Rule::op_bitwise_or, Assoc::Left),
+        Operator::new(Rule::op_bitwise_xor, Assoc::Left),
+        Operator::new(Rule::op_bitwise_and, Assoc::Left),
+        Operator::new(Rule::op_shl, Assoc::Left) | Operator::new(Rule::op_shr, Assoc::Left),
+        additive,
+        multiplicative,
     ]);
-    let primary = |pair: Pair<Rule>| match pair.as_rule() {
-        Rule::primary => consume_primary(pair),
-        _ => Expr::Literal(Variant::Nil),
+    let primary = |pair: Pair<Rule>| {
+        let bytes = hex_bytes(&pair);
+        let expr = match pair.as_rule() {
+            Rule::primary => consume_primary(pair, scope),
+            Rule::set => consume_set(pair, scope),
+            Rule::string => consume_pattern(pair),
+            _ => Ok(Expr::Literal(Variant::Nil)),
+        };
+        expr.map(|expr| (expr, bytes))
     };
-    let infix = |lhs: Expr, op: Pair<Rule>, rhs: Expr| -> Expr {
-        match op.as_rule() {
+    let infix = |lhs: Result<(Expr, Option<Vec<u8>>), Error>,
+                 op: Pair<Rule>,
+                 rhs: Result<(Expr, Option<Vec<u8>>), Error>|
+     -> Result<(Expr, Option<Vec<u8>>), Error> {
+        let ((lhs, _), (rhs, bytes)) = (lhs?, rhs?);
+        let rhs = match (op.as_rule(), bytes) {
+            (Rule::op_contains, Some(bytes)) | (Rule::op_starts_with, Some(bytes)) => {
+                Expr::Literal(Variant::Buffer(bytes.into_boxed_slice()))
+            }
+            _ => rhs,
+        };
+        let (lhs, rhs) = match op.as_rule() {
+            Rule::op_lt | Rule::op_lte | Rule::op_gt | Rule::op_gte | Rule::op_eq | Rule::op_ne => {
+                let span = op.as_span();
+                coerce_datetime(lhs, rhs, span.start()..span.end())?
+            }
+            _ => (lhs, rhs),
+        };
+        let expr = match op.as_rule() {
             Rule::op_lt => Expr::CmpLt(Box::new(lhs), Box::new(rhs)),
             Rule::op_lte => Expr::CmpLte(Box::new(lhs), Box::new(rhs)),
             Rule::op_gt => Expr::CmpGt(Box::new(lhs), Box::new(rhs)),
@@ -65,48 +148,302 @@ fn consume_expr(pair: Pair<Rule>) -> Expr {
             Rule::op_ne => Expr::CmpNotEq(Box::new(lhs), Box::new(rhs)),
             Rule::op_logical_and => Expr::LogicalAnd(Box::new(lhs), Box::new(rhs)),
             Rule::op_logical_or => Expr::LogicalOr(Box::new(lhs), Box::new(rhs)),
+            Rule::op_add => Expr::Add(Box::new(lhs), Box::new(rhs)),
+            Rule::op_sub => Expr::Sub(Box::new(lhs), Box::new(rhs)),
+            Rule::op_mul => Expr::Mul(Box::new(lhs), Box::new(rhs)),
+            Rule::op_div => Expr::Div(Box::new(lhs), Box::new(rhs)),
+            Rule::op_rem => Expr::Rem(Box::new(lhs), Box::new(rhs)),
+            Rule::op_bitwise_and => Expr::BitwiseAnd(Box::new(lhs), Box::new(rhs)),
+            Rule::op_bitwise_or => Expr::BitwiseOr(Box::new(lhs), Box::new(rhs)),
+            Rule::op_bitwise_xor => Expr::BitwiseXor(Box::new(lhs), Box::new(rhs)),
+            Rule::op_shl => Expr::ShiftLeft(Box::new(lhs), Box::new(rhs)),
+            Rule::op_shr => Expr::ShiftRight(Box::new(lhs), Box::new(rhs)),
+            Rule::op_in => Expr::In(Box::new(lhs), Box::new(rhs)),
+            Rule::op_contains => Expr::Contains(Box::new(lhs), Box::new(rhs)),
+            Rule::op_starts_with => Expr::StartsWith(Box::new(lhs), Box::new(rhs)),
+            Rule::op_matches => Expr::Matches(Box::new(lhs), Box::new(rhs)),
             _ => Expr::Literal(Variant::Nil),
+        };
+        Ok((expr, None))
+    };
+    climber
+        .climb(pair.into_inner(), primary, infix)
+        .map(|(expr, _)| expr)
+}
+
+/// Returns the bytes of a bare hex integer literal with its leading zeros,
+/// such as `[0x00, 0x47]` for `0x0047`, to be used as a pattern.
+fn hex_bytes(pair: &Pair<Rule>) -> Option<Vec<u8>> {
+    let mut inner = pair.clone().into_inner();
+    match (inner.next(), inner.next()) {
+        (Some(ref item), None) if item.as_rule() == Rule::hex_integer => {
+            let digits = &item.as_str()[2..];
+            let digits = if digits.len() % 2 == 0 {
+                digits.to_string()
+            } else {
+                format!("0{}", digits)
+            };
+            Some(
+                (0..digits.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+                    .collect(),
+            )
         }
+        _ => None,
+    }
+}
+
+/// Reads a string compared with `frame.time` as an RFC 3339 date-time,
+/// as if written as a `t"..."` literal.
+fn coerce_datetime(lhs: Expr, rhs: Expr, span: Range<usize>) -> Result<(Expr, Expr), Error> {
+    let datetime = |expr: Expr| match expr {
+        Expr::Literal(Variant::String(s)) => match time::parse_datetime(&s) {
+            Some(v) => Ok(Expr::Literal(Variant::Float64(v))),
+            None => Err(Error::new(
+                "expected a date-time such as t\"2024-01-01T10:00:00Z\"",
+                span.clone(),
+            )),
+        },
+        expr => Ok(expr),
     };
-    climber.climb(pair.into_inner(), primary, infix)
+    if let Expr::Frame(Field::Time) = lhs {
+        Ok((lhs, datetime(rhs)?))
+    } else if let Expr::Frame(Field::Time) = rhs {
+        Ok((datetime(lhs)?, rhs))
+    } else {
+        Ok((lhs, rhs))
+    }
+}
+
+fn consume_set(pair: Pair<Rule>, scope: &Scope) -> Result<Expr, Error> {
+    let mut elements = Vec::new();
+    for item in pair.into_inner() {
+        elements.push(match item.as_rule() {
+            Rule::set_range => {
+                let mut inner = item.into_inner();
+                let start = consume_primary(inner.next().unwrap(), scope)?;
+                let end = consume_primary(inner.next().unwrap(), scope)?;
+                SetElement::Range(start, end)
+            }
+            _ => SetElement::Value(consume_primary(item, scope)?),
+        });
+    }
+    Ok(Expr::Set(Set::new(elements)))
+}
+
+fn consume_pattern(pair: Pair<Rule>) -> Result<Expr, Error> {
+    let pattern: String = serde_json::from_str(pair.as_str()).unwrap();
+    match Pattern::new(&pattern) {
+        Ok(pattern) => Ok(Expr::Pattern(pattern)),
+        Err(message) => Err(Error::new(
+            &message,
+            pair.as_span().start()..pair.as_span().end(),
+        )),
+    }
 }
 
-fn consume_primary(pair: Pair<Rule>) -> Expr {
+fn consume_primary(pair: Pair<Rule>, scope: &Scope) -> Result<Expr, Error> {
+    let mut unary = Vec::new();
     let mut result = None;
-    for item in pair.into_inner().rev() {
-        result = Some(match item.as_rule() {
-            Rule::expression => consume_expr(item),
-            Rule::op_unary_plus => Expr::UnaryPlus(Box::new(result.take().unwrap())),
-            Rule::op_unary_negation => Expr::UnaryNegation(Box::new(result.take().unwrap())),
-            Rule::op_logical_negation => Expr::LogicalNegation(Box::new(result.take().unwrap())),
-            Rule::bin_integer => {
-                let v = BigInt::from_str_radix(&item.as_str()[2..], 2).unwrap();
-                Expr::Literal(Variant::BigInt(v.to_signed_bytes_be().into_boxed_slice()).shrink())
+    for item in pair.into_inner() {
+        match item.as_rule() {
+            Rule::op_unary_plus
+            | Rule::op_unary_negation
+            | Rule::op_logical_negation
+            | Rule::op_bitwise_not => unary.push(item.as_rule()),
+            Rule::postfix_index => {
+                let index = consume_expr(item.into_inner().next().unwrap(), scope)?;
+                result = Some(Expr::Index(
+                    Box::new(result.take().unwrap()),
+                    Box::new(index),
+                ));
             }
-            Rule::oct_integer => {
-                let v = BigInt::from_str_radix(&item.as_str()[2..], 8).unwrap();
-                Expr::Literal(Variant::BigInt(v.to_signed_bytes_be().into_boxed_slice()).shrink())
+            Rule::postfix_slice => {
+                let mut start = None;
+                let mut end = None;
+                for bound in item.into_inner() {
+                    let expr = consume_expr(bound.clone().into_inner().next().unwrap(), scope)?;
+                    if bound.as_rule() == Rule::slice_start {
+                        start = Some(Box::new(expr));
+                    } else {
+                        end = Some(Box::new(expr));
+                    }
+                }
+                result = Some(Expr::Slice(Box::new(result.take().unwrap()), start, end));
+            }
+            _ => result = Some(consume_operand(item, scope)?),
+        }
+    }
+    let mut result = result.unwrap();
+    for op in unary.into_iter().rev() {
+        result = match op {
+            Rule::op_unary_plus => Expr::UnaryPlus(Box::new(result)),
+            Rule::op_unary_negation => Expr::UnaryNegation(Box::new(result)),
+            Rule::op_logical_negation => Expr::LogicalNegation(Box::new(result)),
+            _ => Expr::BitwiseNot(Box::new(result)),
+        };
+    }
+    Ok(result)
+}
+
+fn consume_operand(item: Pair<Rule>, scope: &Scope) -> Result<Expr, Error> {
+    Ok(match item.as_rule() {
+        Rule::expression => consume_expr(item, scope)?,
+        Rule::bin_integer => {
+            let v = BigInt::from_str_radix(&item.as_str()[2..], 2).unwrap();
+            Expr::Literal(Variant::BigInt(v.to_signed_bytes_be().into_boxed_slice()).shrink())
+        }
+        Rule::oct_integer => {
+            let v = BigInt::from_str_radix(&item.as_str()[2..], 8).unwrap();
+            Expr::Literal(Variant::BigInt(v.to_signed_bytes_be().into_boxed_slice()).shrink())
+        }
+        Rule::hex_integer => {
+            let v = BigInt::from_str_radix(&item.as_str()[2..], 16).unwrap();
+            Expr::Literal(Variant::BigInt(v.to_signed_bytes_be().into_boxed_slice()).shrink())
+        }
+        Rule::dec_integer => {
+            let v = BigInt::from_str_radix(item.as_str(), 10).unwrap();
+            Expr::Literal(Variant::BigInt(v.to_signed_bytes_be().into_boxed_slice()).shrink())
+        }
+        Rule::string => Expr::Literal(Variant::String(
+            serde_json::from_str(item.as_str()).unwrap(),
+        )),
+        Rule::hex_string => {
+            let hex = &item.as_str()[2..item.as_str().len() - 1];
+            let bytes = (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+                .collect::<Vec<_>>();
+            Expr::Literal(Variant::Buffer(bytes.into_boxed_slice()))
+        }
+        Rule::macro_exp => {
+            let name = &item.as_str()[1..];
+            match scope.functions.get_macro(name) {
+                Some((name, filter)) => expand_macro(name, filter, &item, scope)?,
+                None => {
+                    let span = item.as_span();
+                    parse_macro(name.to_string(), span.start()..span.end())?
+                }
             }
-            Rule::hex_integer => {
-                let v = BigInt::from_str_radix(&item.as_str()[2..], 16).unwrap();
-                Expr::Literal(Variant::BigInt(v.to_signed_bytes_be().into_boxed_slice()).shrink())
+        }
+        Rule::float => Expr::Literal(Variant::Float64(item.as_str().parse().unwrap())),
+        Rule::duration => Expr::Literal(Variant::Float64(
+            time::parse_duration(item.as_str()).unwrap(),
+        )),
+        Rule::datetime => {
+            let s = item.as_str();
+            match time::parse_datetime(&s[2..s.len() - 1]) {
+                Some(v) => Expr::Literal(Variant::Float64(v)),
+                None => {
+                    let span = item.as_span();
+                    return Err(Error::new("invalid date-time", span.start()..span.end()));
+                }
             }
-            Rule::dec_integer => {
-                let v = BigInt::from_str_radix(item.as_str(), 10).unwrap();
-                Expr::Literal(Variant::BigInt(v.to_signed_bytes_be().into_boxed_slice()).shrink())
+        }
+        Rule::nil => Expr::Literal(Variant::Nil),
+        Rule::boolean => Expr::Literal(Variant::Bool(item.as_str() == "true")),
+        Rule::member if item.as_str().starts_with("frame.") => {
+            match Field::from_name(item.as_str()) {
+                Some(field) => Expr::Frame(field),
+                None => {
+                    let span = item.as_span();
+                    return Err(Error::unknown_member(
+                        item.as_str(),
+                        span.start()..span.end(),
+                        Field::names(),
+                    ));
+                }
             }
-            Rule::string => Expr::Literal(Variant::String(
-                serde_json::from_str(item.as_str()).unwrap(),
-            )),
-            Rule::macro_exp => parse_macro(item.as_str()[1..].to_string()),
-            Rule::float => Expr::Literal(Variant::Float64(item.as_str().parse().unwrap())),
-            Rule::nil => Expr::Literal(Variant::Nil),
-            Rule::boolean => Expr::Literal(Variant::Bool(item.as_str() == "true")),
-            Rule::member => Expr::Token(Token::from(item.as_str())),
-            _ => Expr::Literal(Variant::Nil),
-        });
+        }
+        Rule::member => Expr::Token(consume_member(item.as_str(), &item, scope)?),
+        Rule::member_occurrence => {
+            let mut inner = item.clone().into_inner();
+            let layer = inner.next().unwrap().as_str();
+            let index = inner.next().unwrap();
+            let index = index.as_str().parse::<i64>().map_err(|_| {
+                let span = index.as_span();
+                Error::new("invalid layer index", span.start()..span.end())
+            })?;
+            let attr = inner.fold(layer.to_string(), |id, name| id + "." + name.as_str());
+            Expr::Occurrence(
+                consume_member(layer, &item, scope)?,
+                index,
+                consume_member(&attr, &item, scope)?,
+            )
+        }
+        Rule::call => consume_call(item, scope)?,
+        _ => Expr::Literal(Variant::Nil),
+    })
+}
+
+fn consume_member(id: &str, item: &Pair<Rule>, scope: &Scope) -> Result<Token, Error> {
+    if !scope.functions.is_known(id) {
+        let span = item.as_span();
+        return Err(Error::unknown_member(
+            id,
+            span.start()..span.end(),
+            scope.functions.tokens(),
+        ));
+    }
+    Ok(Token::from(id))
+}
+
+/// Parses the body of a named macro into an inlined sub-expression.
+fn expand_macro<'a>(
+    name: &'a str,
+    filter: &'a str,
+    item: &Pair<Rule>,
+    scope: &Scope<'a>,
+) -> Result<Expr, Error> {
+    let span = item.as_span().start()..item.as_span().end();
+    if scope.macros.contains(&name) {
+        let cycle = scope
+            .macros
+            .iter()
+            .skip_while(|m| **m != name)
+            .chain(Some(&name))
+            .map(|m| format!("@{}", m))
+            .collect::<Vec<_>>();
+        return Err(Error::new(
+            &format!("macro cycle: {}", cycle.join(" -> ")),
+            span,
+        ));
+    }
+    let mut macros = scope.macros.clone();
+    macros.push(name);
+    let inner = Scope {
+        functions: scope.functions,
+        macros,
+    };
+    let result = parse_scope(filter, &inner);
+    if scope.macros.is_empty() {
+        // Errors inside macro bodies are reported at the outermost reference.
+        result.map_err(|err| Error::new(&format!("in macro @{}: {}", name, err.message()), span))
+    } else {
+        result
     }
-    result.unwrap()
+}
+
+fn consume_call(pair: Pair<Rule>, scope: &Scope) -> Result<Expr, Error> {
+    let span = pair.as_span();
+    let mut inner = pair.into_inner();
+    let name = inner.next().unwrap().as_str();
+    let args = inner
+        .map(|arg| consume_expr(arg, scope))
+        .collect::<Result<Vec<_>, _>>()?;
+    let message = match scope.functions.get(name) {
+        Some(func) if func.args() == args.len() => return Ok(Expr::Call(func.clone(), args)),
+        Some(func) => format!(
+            "function {} takes {} argument{} but {} given",
+            name,
+            func.args(),
+            if func.args() == 1 { "" } else { "s" },
+            args.len()
+        ),
+        None => format!("unknown function: {}", name),
+    };
+    Err(Error::new(&message, span.start()..span.end()))
 }
 
 #[cfg(test)]
@@ -115,6 +452,7 @@ mod tests {
     use ast::Expr::*;
     use genet_abi::{token::Token, variant::Variant};
     use num_bigint::BigInt;
+    use set::SetElement;
 
     #[test]
     fn literal() {
@@ -272,6 +610,245 @@ mod tests {
         );
     }
 
+    #[test]
+    fn arithmetic() {
+        assert_eq!(
+            parse("1 + 2 * 3"),
+            Ok(Add(
+                Box::new(Literal(Variant::UInt64(1))),
+                Box::new(Mul(
+                    Box::new(Literal(Variant::UInt64(2))),
+                    Box::new(Literal(Variant::UInt64(3)))
+                ))
+            ))
+        );
+
+        assert_eq!(
+            parse("10 - 2 - 3"),
+            Ok(Sub(
+                Box::new(Sub(
+                    Box::new(Literal(Variant::UInt64(10))),
+                    Box::new(Literal(Variant::UInt64(2)))
+                )),
+                Box::new(Literal(Variant::UInt64(3)))
+            ))
+        );
+
+        assert_eq!(
+            parse("~1 << 2"),
+            Ok(ShiftLeft(
+                Box::new(BitwiseNot(Box::new(Literal(Variant::UInt64(1))))),
+                Box::new(Literal(Variant::UInt64(2)))
+            ))
+        );
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(
+            parse("tcp.flags & 0x12 == 0x12"),
+            Ok(CmpEq(
+                Box::new(BitwiseAnd(
+                    Box::new(Token(Token::from("tcp.flags"))),
+                    Box::new(Literal(Variant::UInt64(0x12)))
+                )),
+                Box::new(Literal(Variant::UInt64(0x12)))
+            ))
+        );
+
+        assert_eq!(
+            parse("1 == 2 && 3 < 4 || 5"),
+            Ok(LogicalOr(
+                Box::new(LogicalAnd(
+                    Box::new(CmpEq(
+                        Box::new(Literal(Variant::UInt64(1))),
+                        Box::new(Literal(Variant::UInt64(2)))
+                    )),
+                    Box::new(CmpLt(
+                        Box::new(Literal(Variant::UInt64(3))),
+                        Box::new(Literal(Variant::UInt64(4)))
+                    ))
+                )),
+                Box::new(Literal(Variant::UInt64(5)))
+            ))
+        );
+
+        assert_eq!(
+            parse("1 | 2 ^ 3 & 4"),
+            Ok(BitwiseOr(
+                Box::new(Literal(Variant::UInt64(1))),
+                Box::new(BitwiseXor(
+                    Box::new(Literal(Variant::UInt64(2))),
+                    Box::new(BitwiseAnd(
+                        Box::new(Literal(Variant::UInt64(3))),
+                        Box::new(Literal(Variant::UInt64(4)))
+                    ))
+                ))
+            ))
+        );
+    }
+
+    #[test]
+    fn set() {
+        assert_eq!(
+            parse("tcp.dst in {80, 8000..8100}"),
+            Ok(In(
+                Box::new(Token(Token::from("tcp.dst"))),
+                Box::new(Set(::set::Set::new(vec![
+                    SetElement::Value(Literal(Variant::UInt64(80))),
+                    SetElement::Range(
+                        Literal(Variant::UInt64(8000)),
+                        Literal(Variant::UInt64(8100))
+                    ),
+                ])))
+            ))
+        );
+
+        assert_eq!(
+            parse("1 in {} || index in {nil,}"),
+            Ok(LogicalOr(
+                Box::new(In(
+                    Box::new(Literal(Variant::UInt64(1))),
+                    Box::new(Set(::set::Set::new(vec![])))
+                )),
+                Box::new(In(
+                    Box::new(Token(Token::from("index"))),
+                    Box::new(Set(::set::Set::new(vec![SetElement::Value(Literal(
+                        Variant::Nil
+                    ))])))
+                ))
+            ))
+        );
+
+        assert_eq!(
+            parse("ipv4.src in {@10.0.0.1, @10.0.0.2..@10.0.0.9}"),
+            Ok(In(
+                Box::new(Token(Token::from("ipv4.src"))),
+                Box::new(Set(::set::Set::new(vec![
+                    SetElement::Value(Literal(Variant::Buffer(
+                        vec![10, 0, 0, 1].into_boxed_slice()
+                    ))),
+                    SetElement::Range(
+                        Literal(Variant::Buffer(vec![10, 0, 0, 2].into_boxed_slice())),
+                        Literal(Variant::Buffer(vec![10, 0, 0, 9].into_boxed_slice()))
+                    ),
+                ])))
+            ))
+        );
+    }
+
+    #[test]
+    fn prefix() {
+        assert_eq!(
+            parse("ipv4.src == @10.0.0.0/8"),
+            Ok(CmpEq(
+                Box::new(Token(Token::from("ipv4.src"))),
+                Box::new(Prefix("10.0.0.0/8".parse().unwrap()))
+            ))
+        );
+        assert_eq!(
+            parse("@10.0.0.0/33"),
+            Err(Error::new("invalid address prefix", 0..12))
+        );
+        assert_eq!(
+            parse("ipv6.src == @fe80::/x"),
+            Err(Error::new("invalid address prefix", 12..21))
+        );
+    }
+
+    #[test]
+    fn bytes() {
+        assert_eq!(
+            parse(r#"x"474554""#),
+            Ok(Literal(Variant::Buffer(b"GET".to_vec().into_boxed_slice())))
+        );
+        assert_eq!(
+            parse(r#"x"""#),
+            Ok(Literal(Variant::Buffer(Vec::new().into_boxed_slice())))
+        );
+        assert_eq!(
+            parse(r#"http.host contains "example""#),
+            Ok(Contains(
+                Box::new(Token(Token::from("http.host"))),
+                Box::new(Literal(Variant::String(
+                    "example".to_string().into_boxed_str()
+                )))
+            ))
+        );
+        assert_eq!(
+            parse(r#"tcp.payload startsWith x"474554""#),
+            Ok(StartsWith(
+                Box::new(Token(Token::from("tcp.payload"))),
+                Box::new(Literal(Variant::Buffer(b"GET".to_vec().into_boxed_slice())))
+            ))
+        );
+        assert_eq!(
+            parse("tcp.payload startsWith 0x0047"),
+            Ok(StartsWith(
+                Box::new(Token(Token::from("tcp.payload"))),
+                Box::new(Literal(Variant::Buffer(
+                    vec![0x00, 0x47].into_boxed_slice()
+                )))
+            ))
+        );
+        assert_eq!(
+            parse("tcp.payload contains 0x474"),
+            Ok(Contains(
+                Box::new(Token(Token::from("tcp.payload"))),
+                Box::new(Literal(Variant::Buffer(
+                    vec![0x04, 0x74].into_boxed_slice()
+                )))
+            ))
+        );
+        assert_eq!(
+            parse(r#"dns.name matches "corp$""#),
+            Ok(Matches(
+                Box::new(Token(Token::from("dns.name"))),
+                Box::new(Pattern(::pattern::Pattern::new("corp$").unwrap()))
+            ))
+        );
+    }
+
+    #[test]
+    fn postfix() {
+        assert_eq!(
+            parse("udp.payload[0:4]"),
+            Ok(Slice(
+                Box::new(Token(Token::from("udp.payload"))),
+                Some(Box::new(Literal(Variant::UInt64(0)))),
+                Some(Box::new(Literal(Variant::UInt64(4))))
+            ))
+        );
+        assert_eq!(
+            parse("udp.payload[:-2]"),
+            Ok(Slice(
+                Box::new(Token(Token::from("udp.payload"))),
+                None,
+                Some(Box::new(UnaryNegation(Box::new(Literal(Variant::UInt64(
+                    2
+                ))))))
+            ))
+        );
+        assert_eq!(
+            parse("-ipv4.src[-1]"),
+            Ok(UnaryNegation(Box::new(Index(
+                Box::new(Token(Token::from("ipv4.src"))),
+                Box::new(UnaryNegation(Box::new(Literal(Variant::UInt64(1)))))
+            ))))
+        );
+        assert_eq!(
+            parse("tcp.payload[1:][0]"),
+            Ok(Index(
+                Box::new(Slice(
+                    Box::new(Token(Token::from("tcp.payload"))),
+                    Some(Box::new(Literal(Variant::UInt64(1)))),
+                    None
+                )),
+                Box::new(Literal(Variant::UInt64(0)))
+            ))
+        );
+    }
+
     #[test]
     fn error() {
         assert!(parse("| 12.5").is_err());
@@ -283,9 +860,224 @@ mod tests {
         assert!(parse(">= 12.5").is_err());
         assert!(parse("< 12.5").is_err());
         assert!(parse("> 12.5").is_err());
+        assert!(parse("* 12.5").is_err());
+        assert!(parse("12.5 &").is_err());
+        assert!(parse("12.5 <<< 1").is_err());
+        assert!(parse("1 in 2").is_err());
+        assert!(parse("1 in {a}").is_err());
+        assert!(parse("1 in {1..}").is_err());
+        assert!(parse(r#"x"123""#).is_err());
+        assert!(parse(r#"a matches b"#).is_err());
+        assert!(parse(r#"a matches "(""#).is_err());
+        assert!(parse(r#"a contains"#).is_err());
         assert!(parse(r#" """ "#).is_err());
         assert!(parse(r#" "\x" "#).is_err());
         assert!(parse(r#" "\\"" "#).is_err());
         assert!(parse(r#" "\u000" "#).is_err());
+        assert!(parse("len()").is_err());
+        assert!(parse("len(a, b)").is_err());
+        assert!(parse("unknown(a)").is_err());
+    }
+
+    #[test]
+    fn call() {
+        let functions = Registry::default();
+        assert_eq!(
+            parse("len(udp.payload)"),
+            Ok(Call(
+                functions.get("len").unwrap().clone(),
+                vec![Token(Token::from("udp.payload"))]
+            ))
+        );
+        assert_eq!(
+            parse("count ( ipv4 ) > 1"),
+            Ok(CmpGt(
+                Box::new(Call(
+                    functions.get("count").unwrap().clone(),
+                    vec![Token(Token::from("ipv4"))]
+                )),
+                Box::new(Literal(Variant::UInt64(1)))
+            ))
+        );
+
+        fn zero(_: &::context::Context, _: &[Expr]) -> Variant {
+            Variant::UInt64(0)
+        }
+        let mut functions = Registry::new();
+        functions.register(::function::Function::new("zero", 0, zero));
+        assert_eq!(
+            parse_with("zero()", &functions),
+            Ok(Call(functions.get("zero").unwrap().clone(), vec![]))
+        );
+        assert!(parse_with("len(a)", &functions).is_err());
+    }
+
+    #[test]
+    fn error_detail() {
+        let err = parse("tcp.dst == ").unwrap_err();
+        assert_eq!(err.span(), 11..11);
+        assert_eq!(err.expected(), ["expression"]);
+        assert_eq!(err.message(), "expected expression");
+        assert_eq!(err.suggestion(), None);
+
+        let err = parse("(1").unwrap_err();
+        assert_eq!(err.span(), 2..2);
+        assert_eq!(err.message(), "expected operator or index");
+
+        let err = parse("len(a, b) == 1").unwrap_err();
+        assert_eq!(err.span(), 0..9);
+        assert_eq!(err.message(), "function len takes 1 argument but 2 given");
+
+        let mut functions = Registry::default();
+        functions.add_token("tcp");
+        functions.add_token("tcp.src");
+        functions.add_token("tcp.dst");
+        assert!(parse_with("tcp && tcp.dst == 80", &functions).is_ok());
+        let err = parse_with("tcp && tcp.dts == 80", &functions).unwrap_err();
+        assert_eq!(err.span(), 7..14);
+        assert_eq!(err.message(), "unknown attribute: tcp.dts");
+        assert_eq!(err.suggestion(), Some("tcp.dst"));
+        let err = parse_with("http", &functions).unwrap_err();
+        assert_eq!(err.suggestion(), None);
+    }
+
+    #[test]
+    fn macros() {
+        let mut functions = Registry::default();
+        functions.add_macro("web", "tcp.dst in {80, 443}");
+        functions.add_macro("local", "ipv4.src == @192.168.0.0/16");
+        functions.add_macro("local_web", "@web && @local");
+        assert_eq!(
+            parse_with("@local_web || udp", &functions),
+            parse("(tcp.dst in {80, 443} && ipv4.src == @192.168.0.0/16) || udp",)
+        );
+        let err = parse_with("@web.x", &functions).unwrap_err();
+        assert_eq!(err.span(), 0..6);
+        assert_eq!(err.message(), "unknown macro `@web.x`");
+        let err = parse_with("tcp && @htpp", &functions).unwrap_err();
+        assert_eq!(err.span(), 7..12);
+        assert_eq!(err.message(), "unknown macro `@htpp`");
+        assert_eq!(parse_with("@10.0.0.1", &functions), parse("@10.0.0.1"));
+        assert_eq!(
+            parse_with("@web&&!@local||@local_web", &functions),
+            parse_with("@web && !@local || @local_web", &functions)
+        );
+        assert_eq!(
+            parse("ipv4.src==@10.0.0.1&&eth.src!=@00:11:22:33:44:55"),
+            parse("ipv4.src == @10.0.0.1 && eth.src != @00:11:22:33:44:55")
+        );
+
+        functions.add_macro("a", "@b || tcp");
+        functions.add_macro("b", "udp && @a");
+        functions.add_macro("self", "!@self");
+        let err = parse_with("eth && @a", &functions).unwrap_err();
+        assert_eq!(err.span(), 7..9);
+        assert_eq!(err.message(), "in macro @a: macro cycle: @a -> @b -> @a");
+        let err = parse_with("@self", &functions).unwrap_err();
+        assert_eq!(err.message(), "in macro @self: macro cycle: @self -> @self");
+
+        functions.add_macro("broken", "tcp.dst ==");
+        let err = parse_with("@broken", &functions).unwrap_err();
+        assert_eq!(err.span(), 0..7);
+        assert_eq!(err.message(), "in macro @broken: expected expression");
+    }
+
+    #[test]
+    fn occurrence() {
+        assert_eq!(
+            parse("ipv4[0].src"),
+            Ok(Occurrence(Token::from("ipv4"), 0, Token::from("ipv4.src")))
+        );
+        assert_eq!(
+            parse("tcp[-1].flags.syn"),
+            Ok(Occurrence(
+                Token::from("tcp"),
+                -1,
+                Token::from("tcp.flags.syn")
+            ))
+        );
+        assert_eq!(
+            parse("ipv4[0]"),
+            Ok(Index(
+                Box::new(Token(Token::from("ipv4"))),
+                Box::new(Literal(Variant::UInt64(0)))
+            ))
+        );
+        assert_eq!(
+            parse("ipv4[1].src[0]"),
+            Ok(Index(
+                Box::new(Occurrence(Token::from("ipv4"), 1, Token::from("ipv4.src"))),
+                Box::new(Literal(Variant::UInt64(0)))
+            ))
+        );
+        assert!(parse("ipv4[99999999999999999999].src").is_err());
+
+        let mut functions = Registry::default();
+        functions.add_token("ipv4");
+        functions.add_token("ipv4.src");
+        assert!(parse_with("ipv4[-1].src", &functions).is_ok());
+        assert!(parse_with("ipv4[-1].dst", &functions).is_err());
+    }
+
+    #[test]
+    fn frame() {
+        assert_eq!(parse("frame.index"), Ok(Frame(Field::Index)));
+        assert_eq!(
+            parse("frame.delta > 10ms"),
+            Ok(CmpGt(
+                Box::new(Frame(Field::Delta)),
+                Box::new(Literal(Variant::Float64(0.01)))
+            ))
+        );
+        assert_eq!(
+            parse("frame.time >= t\"2024-01-01T10:00:00Z\""),
+            Ok(CmpGte(
+                Box::new(Frame(Field::Time)),
+                Box::new(Literal(Variant::Float64(1_704_103_200.0)))
+            ))
+        );
+        assert_eq!(
+            parse("frame.timeRelative in {1s..2.5s}"),
+            Ok(In(
+                Box::new(Frame(Field::TimeRelative)),
+                Box::new(Set(::set::Set::new(vec![SetElement::Range(
+                    Literal(Variant::Float64(1.0)),
+                    Literal(Variant::Float64(2.5))
+                )])))
+            ))
+        );
+        assert_eq!(
+            parse("\"2024-01-01\""),
+            Ok(Literal(Variant::String("2024-01-01".into())))
+        );
+        assert_eq!(
+            parse("http.host == \"2018-01-01T00:00:00Z\""),
+            Ok(CmpEq(
+                Box::new(Token(Token::from("http.host"))),
+                Box::new(Literal(Variant::String("2018-01-01T00:00:00Z".into())))
+            ))
+        );
+
+        assert_eq!(
+            parse("frame.time >= \"2024-01-01T10:00:00Z\""),
+            Ok(CmpGte(
+                Box::new(Frame(Field::Time)),
+                Box::new(Literal(Variant::Float64(1_704_103_200.0)))
+            ))
+        );
+
+        let err = parse("frame.time > t\"2024-02-30T00:00:00Z\"").unwrap_err();
+        assert_eq!(err.message(), "invalid date-time");
+        let err = parse("\"yesterday\" < frame.time").unwrap_err();
+        assert_eq!(
+            err.message(),
+            "expected a date-time such as t\"2024-01-01T10:00:00Z\""
+        );
+        let err = parse("frame.indx").unwrap_err();
+        assert_eq!(err.suggestion(), Some("frame.index"));
+
+        let mut functions = Registry::default();
+        functions.add_token("ipv4");
+        assert!(parse_with("frame.length > 60", &functions).is_ok());
     }
 }
@@ -0,0 +1,39 @@
+use genet_abi::variant::Variant;
+use regex::bytes::Regex;
+use std::fmt;
+use variant::VariantExt;
+
+/// A regular expression compiled for the `matches` operator.
+///
+/// Patterns are matched against the UTF-8 bytes of strings,
+/// and against raw bytes of buffers and slices.
+#[derive(Clone)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Pattern, String> {
+        Regex::new(pattern)
+            .map(Pattern)
+            .map_err(|err| err.to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    pub fn is_match(&self, value: &Variant) -> bool {
+        value.as_bytes().map_or(false, |b| self.0.is_match(b))
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Pattern) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Pattern({:?})", self.as_str())
+    }
+}
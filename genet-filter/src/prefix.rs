@@ -0,0 +1,159 @@
+use genet_abi::{token::Token, variant::Variant};
+use std::{
+    fmt,
+    net::{AddrParseError, IpAddr},
+    str::FromStr,
+};
+
+/// An address prefix such as `10.0.0.0/8` or `2001:db8::/32`.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Prefix {
+    addr: IpAddr,
+    len: u8,
+}
+
+impl Prefix {
+    pub fn new(addr: IpAddr, len: u8) -> Option<Prefix> {
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if len <= max {
+            Some(Prefix { addr, len })
+        } else {
+            None
+        }
+    }
+
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    pub fn len(&self) -> u8 {
+        self.len
+    }
+
+    /// Returns the network address with the host bits cleared.
+    pub fn network(&self) -> Vec<u8> {
+        let mut bytes = self.octets();
+        for (i, b) in bytes.iter_mut().enumerate() {
+            *b &= Self::mask(self.len, i);
+        }
+        bytes
+    }
+
+    /// Returns the attribute type of the addresses in the prefix.
+    pub fn typ(&self) -> Token {
+        match self.addr {
+            IpAddr::V4(_) => Token::from("@ipv4:addr"),
+            IpAddr::V6(_) => Token::from("@ipv6:addr"),
+        }
+    }
+
+    /// Returns true if the value of an attribute of type `typ`
+    /// is an address within the prefix.
+    pub fn contains_typed(&self, value: &Variant, typ: Token) -> bool {
+        typ == self.typ() && self.contains(value)
+    }
+
+    /// Returns true if the value is an address of the same family within the prefix.
+    pub fn contains(&self, value: &Variant) -> bool {
+        let bytes: &[u8] = match value {
+            Variant::Buffer(b) => b.as_ref(),
+            Variant::Slice(b) => b.as_ref(),
+            _ => return false,
+        };
+        let octets = self.octets();
+        bytes.len() == octets.len()
+            && bytes
+                .iter()
+                .zip(octets.iter())
+                .enumerate()
+                .all(|(i, (a, b))| a & Self::mask(self.len, i) == b & Self::mask(self.len, i))
+    }
+
+    fn octets(&self) -> Vec<u8> {
+        match self.addr {
+            IpAddr::V4(addr) => addr.octets().to_vec(),
+            IpAddr::V6(addr) => addr.octets().to_vec(),
+        }
+    }
+
+    fn mask(len: u8, index: usize) -> u8 {
+        let bits = (len as usize).saturating_sub(index * 8).min(8);
+        (0xff_u16 << (8 - bits)) as u8
+    }
+}
+
+impl FromStr for Prefix {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Prefix, Error> {
+        let mut parts = s.splitn(2, '/');
+        let addr = parts.next().unwrap_or("").parse::<IpAddr>()?;
+        let len = parts
+            .next()
+            .ok_or(Error)?
+            .parse::<u8>()
+            .map_err(|_| Error)?;
+        Prefix::new(addr, len).ok_or(Error)
+    }
+}
+
+impl fmt::Display for Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.len)
+    }
+}
+
+#[derive(Debug)]
+pub struct Error;
+
+impl From<AddrParseError> for Error {
+    fn from(_: AddrParseError) -> Error {
+        Error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!("10.0.0.0/8".parse::<Prefix>().unwrap().len(), 8);
+        assert_eq!("2001:db8::/32".parse::<Prefix>().unwrap().len(), 32);
+        assert!("10.0.0.0/33".parse::<Prefix>().is_err());
+        assert!("10.0.0.0".parse::<Prefix>().is_err());
+        assert!("10.0.0/8".parse::<Prefix>().is_err());
+        assert!("fe80::1/129".parse::<Prefix>().is_err());
+    }
+
+    #[test]
+    fn contains() {
+        let prefix = "10.0.0.0/8".parse::<Prefix>().unwrap();
+        assert!(prefix.contains(&Variant::Buffer(vec![10, 1, 2, 3].into_boxed_slice())));
+        assert!(!prefix.contains(&Variant::Buffer(vec![11, 1, 2, 3].into_boxed_slice())));
+        assert!(!prefix.contains(&Variant::Buffer(vec![10, 1, 2].into_boxed_slice())));
+        assert!(!prefix.contains(&Variant::UInt64(10)));
+
+        let prefix = "192.168.10.0/23".parse::<Prefix>().unwrap();
+        assert!(prefix.contains(&Variant::Buffer(vec![192, 168, 11, 1].into_boxed_slice())));
+        assert!(!prefix.contains(&Variant::Buffer(vec![192, 168, 12, 1].into_boxed_slice())));
+        assert_eq!(prefix.network(), vec![192, 168, 10, 0]);
+
+        let prefix = "2001:db8::/32".parse::<Prefix>().unwrap();
+        let mut addr = vec![0x20, 0x01, 0x0d, 0xb8];
+        addr.extend_from_slice(&[0xff; 12]);
+        assert!(prefix.contains(&Variant::Buffer(addr.into_boxed_slice())));
+
+        let prefix = "0.0.0.0/0".parse::<Prefix>().unwrap();
+        assert!(prefix.contains(&Variant::Buffer(vec![1, 2, 3, 4].into_boxed_slice())));
+
+        let prefix = "10.0.0.0/8".parse::<Prefix>().unwrap();
+        let addr = Variant::Buffer(vec![10, 1, 2, 3].into_boxed_slice());
+        assert!(prefix.contains_typed(&addr, Token::from("@ipv4:addr")));
+        assert!(!prefix.contains_typed(&addr, Token::from("@ipv6:addr")));
+        assert!(!prefix.contains_typed(&addr, Token::null()));
+    }
+}
@@ -0,0 +1,208 @@
+use ast::Expr;
+use context::Context;
+use genet_abi::{token::Token, variant::Variant};
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use prefix::Prefix;
+use std::collections::HashSet;
+use variant::VariantExt;
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum SetElement {
+    Value(Expr),
+    Range(Expr, Expr),
+}
+
+/// A set of constant values, inclusive ranges and address prefixes
+/// used by the `in` operator.
+///
+/// The elements are evaluated once on construction,
+/// so a membership test costs a hash lookup plus a scan over the ranges.
+#[derive(Clone, Debug)]
+pub struct Set {
+    elements: Vec<SetElement>,
+    values: HashSet<Key>,
+    strings: HashSet<Box<str>>,
+    bytes: HashSet<Box<[u8]>>,
+    ranges: Vec<(Variant, Variant)>,
+    prefixes: Vec<Prefix>,
+}
+
+impl Set {
+    pub fn new(elements: Vec<SetElement>) -> Set {
+        let ctx = Context::new(&[]);
+        let mut values = HashSet::new();
+        let mut strings = HashSet::new();
+        let mut bytes = HashSet::new();
+        let mut ranges = Vec::new();
+        let mut prefixes = Vec::new();
+        for elem in &elements {
+            match elem {
+                SetElement::Value(Expr::Prefix(prefix)) => {
+                    prefixes.push(prefix.clone());
+                }
+                SetElement::Value(expr) => match expr.eval(&ctx) {
+                    Variant::String(s) => {
+                        strings.insert(s);
+                    }
+                    Variant::Buffer(b) => {
+                        bytes.insert(b);
+                    }
+                    Variant::Slice(b) => {
+                        bytes.insert(b.as_ref().to_vec().into_boxed_slice());
+                    }
+                    value => {
+                        if let Some(key) = Key::new(&value) {
+                            values.insert(key);
+                        }
+                    }
+                },
+                SetElement::Range(start, end) => {
+                    ranges.push((start.eval(&ctx), end.eval(&ctx)));
+                }
+            }
+        }
+        Set {
+            elements,
+            values,
+            strings,
+            bytes,
+            ranges,
+            prefixes,
+        }
+    }
+
+    pub fn elements(&self) -> &[SetElement] {
+        &self.elements
+    }
+
+    pub fn contains(&self, value: &Variant) -> bool {
+        self.contains_typed(value, None)
+    }
+
+    /// Tests the membership of a value, which comes from an attribute of type `typ` if any.
+    ///
+    /// Prefixes only match attributes of the address type of their family.
+    pub fn contains_typed(&self, value: &Variant, typ: Option<Token>) -> bool {
+        let found = match value {
+            Variant::String(s) => self.strings.contains(s),
+            Variant::Buffer(b) => self.bytes.contains(b),
+            Variant::Slice(b) => self.bytes.contains(b.as_ref()),
+            _ => Key::new(value).map_or(false, |key| self.values.contains(&key)),
+        };
+        found
+            || self
+                .ranges
+                .iter()
+                .any(|(start, end)| value.op_gte(start) && value.op_lte(end))
+            || self.prefixes.iter().any(|prefix| match typ {
+                Some(typ) => prefix.contains_typed(value, typ),
+                None => prefix.contains(value),
+            })
+    }
+}
+
+impl PartialEq for Set {
+    fn eq(&self, other: &Set) -> bool {
+        self.elements == other.elements
+    }
+}
+
+/// A hashable form of scalar Variant values.
+///
+/// Integers are normalized so that `UInt64(80)`, `Int64(80)` and `Float64(80.0)`
+/// share the same key, matching the semantics of `==`.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+enum Key {
+    Nil,
+    Bool(bool),
+    UInt(u64),
+    Int(i64),
+    BigInt(Box<[u8]>),
+    Float(u64),
+}
+
+impl Key {
+    fn new(value: &Variant) -> Option<Key> {
+        let key = match value {
+            Variant::Nil => Key::Nil,
+            Variant::Bool(v) => Key::Bool(*v),
+            Variant::UInt64(v) => Key::UInt(*v),
+            Variant::Int64(v) => {
+                if *v >= 0 {
+                    Key::UInt(*v as u64)
+                } else {
+                    Key::Int(*v)
+                }
+            }
+            Variant::Float64(v) => {
+                if v.fract() == 0.0 && *v >= 0.0 && *v <= u64::max_value() as f64 {
+                    Key::UInt(*v as u64)
+                } else if v.fract() == 0.0 && *v < 0.0 && *v >= i64::min_value() as f64 {
+                    Key::Int(*v as i64)
+                } else {
+                    Key::Float(v.to_bits())
+                }
+            }
+            Variant::BigInt(v) => {
+                let v = BigInt::from_signed_bytes_be(&v);
+                if let Some(v) = v.to_u64() {
+                    Key::UInt(v)
+                } else if let Some(v) = v.to_i64() {
+                    Key::Int(v)
+                } else {
+                    Key::BigInt(v.to_signed_bytes_be().into_boxed_slice())
+                }
+            }
+            _ => return None,
+        };
+        Some(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contains() {
+        let set = Set::new(vec![
+            SetElement::Value(Expr::Literal(Variant::UInt64(80))),
+            SetElement::Value(Expr::Literal(Variant::Int64(-1))),
+            SetElement::Value(Expr::Literal(Variant::String(
+                "http".to_string().into_boxed_str(),
+            ))),
+            SetElement::Value(Expr::Literal(Variant::Buffer(
+                vec![10, 0, 0, 1].into_boxed_slice(),
+            ))),
+            SetElement::Range(
+                Expr::Literal(Variant::UInt64(8000)),
+                Expr::Literal(Variant::UInt64(8100)),
+            ),
+        ]);
+        assert!(set.contains(&Variant::UInt64(80)));
+        assert!(set.contains(&Variant::Int64(80)));
+        assert!(set.contains(&Variant::Float64(80.0)));
+        assert!(set.contains(&Variant::Int64(-1)));
+        assert!(set.contains(&Variant::UInt64(8000)));
+        assert!(set.contains(&Variant::UInt64(8050)));
+        assert!(set.contains(&Variant::UInt64(8100)));
+        assert!(set.contains(&Variant::String("http".to_string().into_boxed_str())));
+        assert!(set.contains(&Variant::Buffer(vec![10, 0, 0, 1].into_boxed_slice())));
+        assert!(!set.contains(&Variant::UInt64(81)));
+        assert!(!set.contains(&Variant::UInt64(8101)));
+        assert!(!set.contains(&Variant::Float64(80.5)));
+        assert!(!set.contains(&Variant::Nil));
+        assert!(!set.contains(&Variant::String("https".to_string().into_boxed_str())));
+
+        let set = Set::new(vec![SetElement::Value(Expr::Prefix(
+            "192.168.0.0/16".parse().unwrap(),
+        ))]);
+        assert!(set.contains(&Variant::Buffer(vec![192, 168, 3, 4].into_boxed_slice())));
+        assert!(!set.contains(&Variant::Buffer(vec![192, 169, 3, 4].into_boxed_slice())));
+
+        let addr = Variant::Buffer(vec![192, 168, 3, 4].into_boxed_slice());
+        assert!(set.contains_typed(&addr, Some(Token::from("@ipv4:addr"))));
+        assert!(!set.contains_typed(&addr, Some(Token::from("@ipv6:addr"))));
+    }
+}
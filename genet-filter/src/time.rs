@@ -0,0 +1,123 @@
+/// Parses a duration literal such as `10ms` or `1.5s` into seconds.
+pub fn parse_duration(s: &str) -> Option<f64> {
+    let pos = s.find(|c: char| c.is_ascii_alphabetic())?;
+    let value = s[..pos].parse::<f64>().ok()?;
+    let scale = match &s[pos..] {
+        "ns" => 1e-9,
+        "us" => 1e-6,
+        "ms" => 1e-3,
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => return None,
+    };
+    Some(value * scale)
+}
+
+/// Parses an RFC 3339 date-time such as `2024-01-01T10:00:00Z`
+/// into seconds since the Unix epoch.
+///
+/// A missing offset is read as UTC.
+pub fn parse_datetime(s: &str) -> Option<f64> {
+    let num = |r: ::std::ops::Range<usize>| s.get(r).and_then(|v| v.parse::<i64>().ok());
+    let (year, month, day) = (num(0..4)?, num(5..7)?, num(8..10)?);
+    let (hour, min, sec) = (num(11..13)?, num(14..16)?, num(17..19)?);
+    if month < 1 || month > 12 || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    if hour > 23 || min > 59 || sec > 60 {
+        return None;
+    }
+
+    let rest = &s[19..];
+    let frac_len = if rest.starts_with('.') {
+        rest[1..]
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len() - 1)
+            + 1
+    } else {
+        0
+    };
+    let frac = if frac_len > 0 {
+        format!("0{}", &rest[..frac_len]).parse::<f64>().ok()?
+    } else {
+        0.0
+    };
+    let offset = match &rest[frac_len..] {
+        "" | "Z" => 0,
+        zone if zone.len() == 6 => {
+            let sign = if zone.starts_with('-') { -1 } else { 1 };
+            let (h, m) = (
+                zone[1..3].parse::<i64>().ok()?,
+                zone[4..6].parse::<i64>().ok()?,
+            );
+            if h > 23 || m > 59 {
+                return None;
+            }
+            sign * (h * 3600 + m * 60)
+        }
+        _ => return None,
+    };
+
+    let days = days_from_civil(year, month, day);
+    let secs = days * 86400 + hour * 3600 + min * 60 + sec - offset;
+    Some(secs as f64 + frac)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Returns the number of days since 1970-01-01 in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duration() {
+        assert_eq!(parse_duration("10ms"), Some(0.01));
+        assert_eq!(parse_duration("2s"), Some(2.0));
+        assert_eq!(parse_duration("1.5m"), Some(90.0));
+        assert_eq!(parse_duration("1h"), Some(3600.0));
+        assert_eq!(parse_duration("5d"), None);
+    }
+
+    #[test]
+    fn datetime() {
+        assert_eq!(parse_datetime("1970-01-01T00:00:00Z"), Some(0.0));
+        assert_eq!(
+            parse_datetime("2024-01-01T10:00:00Z"),
+            Some(1_704_103_200.0)
+        );
+        assert_eq!(parse_datetime("2024-01-01 10:00:00"), Some(1_704_103_200.0));
+        assert_eq!(
+            parse_datetime("2024-01-01T19:00:00+09:00"),
+            Some(1_704_103_200.0)
+        );
+        assert_eq!(
+            parse_datetime("2024-01-01T10:00:00.25Z"),
+            Some(1_704_103_200.25)
+        );
+        assert_eq!(
+            parse_datetime("2024-02-29T00:00:00Z"),
+            Some(1_709_164_800.0)
+        );
+        assert_eq!(parse_datetime("2023-02-29T00:00:00Z"), None);
+        assert_eq!(parse_datetime("2024-13-01T00:00:00Z"), None);
+    }
+}
@@ -1,6 +1,8 @@
 use ast::Expr;
 use genet_abi::{token::Token, variant::Variant};
 use hwaddr::HwAddr;
+use serde_json;
+use set::SetElement;
 use std::net::{Ipv4Addr, Ipv6Addr};
 use variant::VariantExt;
 
@@ -27,33 +29,198 @@ pub fn unparse_attr(typ: Token, var: &Variant) -> Expr {
     Expr::Literal(var.clone())
 }
 
+const PREC_OR: u8 = 1;
+const PREC_AND: u8 = 2;
+const PREC_EQ: u8 = 3;
+const PREC_CMP: u8 = 4;
+const PREC_BITWISE_OR: u8 = 5;
+const PREC_BITWISE_XOR: u8 = 6;
+const PREC_BITWISE_AND: u8 = 7;
+const PREC_SHIFT: u8 = 8;
+const PREC_ADDITIVE: u8 = 9;
+const PREC_MULTIPLICATIVE: u8 = 10;
+const PREC_UNARY: u8 = 11;
+const PREC_PRIMARY: u8 = 12;
+
 pub fn unparse(expr: &Expr) -> String {
+    unparse_prec(expr).0
+}
+
+fn unparse_bytes(bytes: &[u8]) -> String {
+    let hex = bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    format!("x\"{}\"", hex)
+}
+
+fn unparse_operand(expr: &Expr, prec: u8) -> String {
+    let (s, p) = unparse_prec(expr);
+    if p < prec {
+        format!("({})", s)
+    } else {
+        s
+    }
+}
+
+fn unparse_unary(op: &str, expr: &Expr) -> (String, u8) {
+    (
+        format!("{}{}", op, unparse_operand(expr, PREC_UNARY)),
+        PREC_UNARY,
+    )
+}
+
+fn unparse_binary(op: &str, lhs: &Expr, rhs: &Expr, prec: u8) -> (String, u8) {
+    (
+        format!(
+            "{} {} {}",
+            unparse_operand(lhs, prec),
+            op,
+            unparse_operand(rhs, prec + 1)
+        ),
+        prec,
+    )
+}
+
+fn unparse_prec(expr: &Expr) -> (String, u8) {
     match expr {
-        Expr::Literal(var) => var.to_string(),
-        Expr::Token(t) => t.to_string(),
-        Expr::Macro(expr) => format!("@{}", expr),
+        Expr::Literal(Variant::Buffer(b)) => (unparse_bytes(b), PREC_PRIMARY),
+        Expr::Literal(Variant::Slice(b)) => (unparse_bytes(b), PREC_PRIMARY),
+        Expr::Literal(var) => (var.to_string(), PREC_PRIMARY),
+        Expr::Token(t) => (t.to_string(), PREC_PRIMARY),
+        Expr::Frame(field) => (field.name().to_string(), PREC_PRIMARY),
+        Expr::Macro(expr) => (format!("@{}", expr), PREC_PRIMARY),
+        Expr::Prefix(prefix) => (format!("@{}", prefix), PREC_PRIMARY),
         Expr::CmpEq(lhs, rhs) => match (lhs.as_ref(), rhs.as_ref()) {
-            (lhs, &Expr::Literal(Variant::Bool(true))) => unparse(lhs),
-            (lhs, &Expr::Literal(Variant::Bool(false))) => format!("!{}", unparse(lhs)),
-            (&Expr::Literal(Variant::Bool(true)), rhs) => unparse(rhs),
-            (&Expr::Literal(Variant::Bool(false)), rhs) => format!("!{}", unparse(rhs)),
-            (lhs, rhs) => format!("{} == {}", unparse(lhs), unparse(rhs)),
+            (lhs, &Expr::Literal(Variant::Bool(true))) => unparse_prec(lhs),
+            (lhs, &Expr::Literal(Variant::Bool(false))) => unparse_unary("!", lhs),
+            (&Expr::Literal(Variant::Bool(true)), rhs) => unparse_prec(rhs),
+            (&Expr::Literal(Variant::Bool(false)), rhs) => unparse_unary("!", rhs),
+            (lhs, rhs) => unparse_binary("==", lhs, rhs, PREC_EQ),
         },
         Expr::CmpNotEq(lhs, rhs) => match (lhs.as_ref(), rhs.as_ref()) {
-            (lhs, &Expr::Literal(Variant::Bool(false))) => unparse(lhs),
-            (lhs, &Expr::Literal(Variant::Bool(true))) => format!("!{}", unparse(lhs)),
-            (&Expr::Literal(Variant::Bool(false)), rhs) => unparse(rhs),
-            (&Expr::Literal(Variant::Bool(true)), rhs) => format!("!{}", unparse(rhs)),
-            (lhs, rhs) => format!("{} != {}", unparse(lhs), unparse(rhs)),
+            (lhs, &Expr::Literal(Variant::Bool(false))) => unparse_prec(lhs),
+            (lhs, &Expr::Literal(Variant::Bool(true))) => unparse_unary("!", lhs),
+            (&Expr::Literal(Variant::Bool(false)), rhs) => unparse_prec(rhs),
+            (&Expr::Literal(Variant::Bool(true)), rhs) => unparse_unary("!", rhs),
+            (lhs, rhs) => unparse_binary("!=", lhs, rhs, PREC_EQ),
         },
-        Expr::CmpLt(lhs, rhs) => format!("{} < {}", unparse(lhs), unparse(rhs)),
-        Expr::CmpGt(lhs, rhs) => format!("{} > {}", unparse(lhs), unparse(rhs)),
-        Expr::CmpLte(lhs, rhs) => format!("{} <= {}", unparse(lhs), unparse(rhs)),
-        Expr::CmpGte(lhs, rhs) => format!("{} >= {}", unparse(lhs), unparse(rhs)),
-        Expr::LogicalAnd(lhs, rhs) => format!("{} && {}", unparse(lhs), unparse(rhs)),
-        Expr::LogicalOr(lhs, rhs) => format!("{} || {}", unparse(lhs), unparse(rhs)),
-        Expr::LogicalNegation(expr) => format!("!{}", unparse(expr)),
-        Expr::UnaryPlus(expr) => format!("+{}", unparse(expr)),
-        Expr::UnaryNegation(expr) => format!("-{}", unparse(expr)),
+        Expr::CmpLt(lhs, rhs) => unparse_binary("<", lhs, rhs, PREC_CMP),
+        Expr::CmpGt(lhs, rhs) => unparse_binary(">", lhs, rhs, PREC_CMP),
+        Expr::CmpLte(lhs, rhs) => unparse_binary("<=", lhs, rhs, PREC_CMP),
+        Expr::CmpGte(lhs, rhs) => unparse_binary(">=", lhs, rhs, PREC_CMP),
+        Expr::LogicalAnd(lhs, rhs) => unparse_binary("&&", lhs, rhs, PREC_AND),
+        Expr::LogicalOr(lhs, rhs) => unparse_binary("||", lhs, rhs, PREC_OR),
+        Expr::LogicalNegation(expr) => unparse_unary("!", expr),
+        Expr::UnaryPlus(expr) => unparse_unary("+", expr),
+        Expr::UnaryNegation(expr) => unparse_unary("-", expr),
+        Expr::Add(lhs, rhs) => unparse_binary("+", lhs, rhs, PREC_ADDITIVE),
+        Expr::Sub(lhs, rhs) => unparse_binary("-", lhs, rhs, PREC_ADDITIVE),
+        Expr::Mul(lhs, rhs) => unparse_binary("*", lhs, rhs, PREC_MULTIPLICATIVE),
+        Expr::Div(lhs, rhs) => unparse_binary("/", lhs, rhs, PREC_MULTIPLICATIVE),
+        Expr::Rem(lhs, rhs) => unparse_binary("%", lhs, rhs, PREC_MULTIPLICATIVE),
+        Expr::BitwiseAnd(lhs, rhs) => unparse_binary("&", lhs, rhs, PREC_BITWISE_AND),
+        Expr::BitwiseOr(lhs, rhs) => unparse_binary("|", lhs, rhs, PREC_BITWISE_OR),
+        Expr::BitwiseXor(lhs, rhs) => unparse_binary("^", lhs, rhs, PREC_BITWISE_XOR),
+        Expr::BitwiseNot(expr) => unparse_unary("~", expr),
+        Expr::ShiftLeft(lhs, rhs) => unparse_binary("<<", lhs, rhs, PREC_SHIFT),
+        Expr::ShiftRight(lhs, rhs) => unparse_binary(">>", lhs, rhs, PREC_SHIFT),
+        Expr::Set(set) => {
+            let elements = set
+                .elements()
+                .iter()
+                .map(|elem| match elem {
+                    SetElement::Value(v) => unparse(v),
+                    SetElement::Range(start, end) => {
+                        format!("{}..{}", unparse(start), unparse(end))
+                    }
+                })
+                .collect::<Vec<_>>();
+            (format!("{{{}}}", elements.join(", ")), PREC_PRIMARY)
+        }
+        Expr::In(lhs, rhs) => unparse_binary("in", lhs, rhs, PREC_CMP),
+        Expr::Pattern(pattern) => (
+            serde_json::to_string(pattern.as_str()).unwrap(),
+            PREC_PRIMARY,
+        ),
+        Expr::Contains(lhs, rhs) => unparse_binary("contains", lhs, rhs, PREC_CMP),
+        Expr::StartsWith(lhs, rhs) => unparse_binary("startsWith", lhs, rhs, PREC_CMP),
+        Expr::Matches(lhs, rhs) => unparse_binary("matches", lhs, rhs, PREC_CMP),
+        Expr::Call(func, args) => (
+            format!(
+                "{}({})",
+                func.name(),
+                args.iter().map(unparse).collect::<Vec<_>>().join(", ")
+            ),
+            PREC_PRIMARY,
+        ),
+        Expr::Occurrence(layer, index, attr) => {
+            let layer = layer.to_string();
+            let attr = attr.to_string();
+            (
+                format!(
+                    "{}[{}]{}",
+                    layer,
+                    index,
+                    &attr[layer.len().min(attr.len())..]
+                ),
+                PREC_PRIMARY,
+            )
+        }
+        Expr::Index(expr, index) => (
+            format!(
+                "{}[{}]",
+                unparse_operand(expr, PREC_PRIMARY),
+                unparse(index)
+            ),
+            PREC_PRIMARY,
+        ),
+        Expr::Slice(expr, start, end) => (
+            format!(
+                "{}[{}:{}]",
+                unparse_operand(expr, PREC_PRIMARY),
+                start.as_ref().map_or(String::new(), |s| unparse(s)),
+                end.as_ref().map_or(String::new(), |e| unparse(e))
+            ),
+            PREC_PRIMARY,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parse;
+
+    #[test]
+    fn round_trip() {
+        for filter in &[
+            "1 + 2 * 3",
+            "(1 + 2) * 3",
+            "10 - (2 - 3)",
+            "tcp.flags & 18 == 18",
+            "ipv4.totalLength - ipv4.headerLength * 4 > 1000",
+            "tcp.dst % 1000 == 80",
+            "~(1 << 2) | 3 ^ 4 >> 1",
+            "-(1 + 2)",
+            "(a || b) && c",
+            "tcp.dst in {80, 443, 8000..8100}",
+            "ipv4.src == @10.0.0.0/8 || ipv6.dst in {@2001:db8::/32}",
+            "http.host contains \"example\" && tcp.payload startsWith 4670804",
+            "dns.name matches \"^.*\\\\.corp$\"",
+            "len(udp.payload) > 4 && lower(http.host) == \"example.com\"",
+            "count(ipv4) + abs(-1) * 2",
+            "ipv4[0].src != ipv4[-1].src && any(ipv4.src == 1)",
+            "frame.index > 1000 && frame.input == 1",
+            "udp.payload[0:4] == 1 && ipv4.src[-1] == 1",
+            "(a + b)[:2][1] + -c[1:]",
+            "tcp.dst + 1 in {-1, \"z\", \"a\"..\"f\"} && ok",
+            "tcp.payload startsWith x\"0047\" && udp.payload contains x\"\"",
+            "x\"00\" in {x\"0000\", x\"\"}",
+        ] {
+            let expr = parse(filter).unwrap();
+            assert_eq!(unparse(&expr), *filter);
+            assert_eq!(parse(&unparse(&expr)), Ok(expr));
+        }
     }
 }
@@ -1,8 +1,12 @@
-use genet_abi::variant::Variant;
+use genet_abi::{slice::TryGet, variant::Variant};
 use num_bigint::{BigInt, Sign};
-use num_traits::ToPrimitive;
+use num_traits::{ToPrimitive, Zero};
 use serde_json;
-use std::cmp::Ordering;
+use std::{borrow::Cow, cmp::Ordering};
+
+/// The largest left shift amount; larger shifts evaluate to `Nil`
+/// instead of allocating an arbitrarily large `BigInt`.
+const MAX_SHIFT: usize = 1024;
 
 pub trait VariantExt {
     fn shrink(self) -> Variant;
@@ -15,6 +19,22 @@ pub trait VariantExt {
     fn op_lte(&self, other: &Variant) -> bool;
     fn op_gte(&self, other: &Variant) -> bool;
     fn op_eq(&self, other: &Variant) -> bool;
+    fn op_add(&self, other: &Variant) -> Variant;
+    fn op_sub(&self, other: &Variant) -> Variant;
+    fn op_mul(&self, other: &Variant) -> Variant;
+    fn op_div(&self, other: &Variant) -> Variant;
+    fn op_rem(&self, other: &Variant) -> Variant;
+    fn op_bitwise_and(&self, other: &Variant) -> Variant;
+    fn op_bitwise_or(&self, other: &Variant) -> Variant;
+    fn op_bitwise_xor(&self, other: &Variant) -> Variant;
+    fn op_bitwise_not(&self) -> Variant;
+    fn op_shl(&self, other: &Variant) -> Variant;
+    fn op_shr(&self, other: &Variant) -> Variant;
+    fn op_contains(&self, other: &Variant) -> bool;
+    fn op_starts_with(&self, other: &Variant) -> bool;
+    fn op_index(&self, index: &Variant) -> Variant;
+    fn op_slice(&self, start: Option<&Variant>, end: Option<&Variant>) -> Variant;
+    fn as_bytes(&self) -> Option<&[u8]>;
     fn to_string(&self) -> String;
 }
 
@@ -164,6 +184,203 @@ impl VariantExt for Variant {
         }
     }
 
+    fn op_add(&self, other: &Variant) -> Variant {
+        arithmetic(
+            self,
+            other,
+            u64::checked_add,
+            i64::checked_add,
+            Some(|a, b| a + b),
+            |a, b| Some(a + b),
+        )
+    }
+
+    fn op_sub(&self, other: &Variant) -> Variant {
+        arithmetic(
+            self,
+            other,
+            u64::checked_sub,
+            i64::checked_sub,
+            Some(|a, b| a - b),
+            |a, b| Some(a - b),
+        )
+    }
+
+    fn op_mul(&self, other: &Variant) -> Variant {
+        arithmetic(
+            self,
+            other,
+            u64::checked_mul,
+            i64::checked_mul,
+            Some(|a, b| a * b),
+            |a, b| Some(a * b),
+        )
+    }
+
+    fn op_div(&self, other: &Variant) -> Variant {
+        arithmetic(
+            self,
+            other,
+            u64::checked_div,
+            i64::checked_div,
+            Some(|a, b| a / b),
+            |a, b| if b.is_zero() { None } else { Some(a / b) },
+        )
+    }
+
+    fn op_rem(&self, other: &Variant) -> Variant {
+        arithmetic(
+            self,
+            other,
+            u64::checked_rem,
+            i64::checked_rem,
+            Some(|a, b| a % b),
+            |a, b| if b.is_zero() { None } else { Some(a % b) },
+        )
+    }
+
+    fn op_bitwise_and(&self, other: &Variant) -> Variant {
+        arithmetic(
+            self,
+            other,
+            |a, b| Some(a & b),
+            |a, b| Some(a & b),
+            None,
+            |a, b| Some(a & b),
+        )
+    }
+
+    fn op_bitwise_or(&self, other: &Variant) -> Variant {
+        arithmetic(
+            self,
+            other,
+            |a, b| Some(a | b),
+            |a, b| Some(a | b),
+            None,
+            |a, b| Some(a | b),
+        )
+    }
+
+    fn op_bitwise_xor(&self, other: &Variant) -> Variant {
+        arithmetic(
+            self,
+            other,
+            |a, b| Some(a ^ b),
+            |a, b| Some(a ^ b),
+            None,
+            |a, b| Some(a ^ b),
+        )
+    }
+
+    fn op_bitwise_not(&self) -> Variant {
+        match self {
+            Variant::Int64(v) => Variant::Int64(!v),
+            Variant::UInt64(v) => Variant::UInt64(!v),
+            _ => to_bigint(self).map_or(Variant::Nil, |v| from_bigint(!v)),
+        }
+    }
+
+    fn op_shl(&self, other: &Variant) -> Variant {
+        let shift = match to_bigint(other).and_then(|v| v.to_usize()) {
+            Some(shift) if shift <= MAX_SHIFT => shift,
+            _ => return Variant::Nil,
+        };
+        match self {
+            Variant::UInt64(v) if shift < 64 && v.leading_zeros() as usize >= shift => {
+                Variant::UInt64(v << shift)
+            }
+            _ => to_bigint(self).map_or(Variant::Nil, |v| from_bigint(v << shift)),
+        }
+    }
+
+    fn op_shr(&self, other: &Variant) -> Variant {
+        let shift = match to_bigint(other).and_then(|v| v.to_usize()) {
+            Some(shift) => shift,
+            None => return Variant::Nil,
+        };
+        match self {
+            Variant::UInt64(v) => Variant::UInt64(v.checked_shr(shift as u32).unwrap_or(0)),
+            Variant::Int64(v) => Variant::Int64(v >> shift.min(63)),
+            _ => to_bigint(self).map_or(Variant::Nil, |v| from_bigint(v >> shift)),
+        }
+    }
+
+    fn op_contains(&self, other: &Variant) -> bool {
+        match (self.as_bytes(), to_pattern_bytes(other)) {
+            (Some(a), Some(b)) => b.is_empty() || a.windows(b.len()).any(|w| w == &b[..]),
+            _ => false,
+        }
+    }
+
+    fn op_starts_with(&self, other: &Variant) -> bool {
+        match (self.as_bytes(), to_pattern_bytes(other)) {
+            (Some(a), Some(b)) => a.starts_with(&b),
+            _ => false,
+        }
+    }
+
+    fn op_index(&self, index: &Variant) -> Variant {
+        let index = match to_bigint(index).and_then(|v| v.to_i64()) {
+            Some(index) => index,
+            None => return Variant::Nil,
+        };
+        match self {
+            Variant::String(s) => {
+                let len = s.chars().count();
+                resolve_index(index, len)
+                    .filter(|i| *i < len)
+                    .and_then(|i| s.chars().nth(i))
+                    .map_or(Variant::Nil, |c| {
+                        Variant::String(c.to_string().into_boxed_str())
+                    })
+            }
+            _ => self.as_bytes().map_or(Variant::Nil, |b| {
+                resolve_index(index, b.len())
+                    .and_then(|i| b.get(i))
+                    .map_or(Variant::Nil, |v| Variant::UInt64(u64::from(*v)))
+            }),
+        }
+    }
+
+    fn op_slice(&self, start: Option<&Variant>, end: Option<&Variant>) -> Variant {
+        let bound = |v: Option<&Variant>, default: i64| -> Option<i64> {
+            match v {
+                Some(v) => to_bigint(v).and_then(|v| v.to_i64()),
+                None => Some(default),
+            }
+        };
+        let (start, end) = match (bound(start, 0), bound(end, i64::max_value())) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return Variant::Nil,
+        };
+        let range = |len: usize| {
+            let start = resolve_index(start, len).unwrap_or(0).min(len);
+            let end = resolve_index(end, len).unwrap_or(0).min(len);
+            start..end.max(start)
+        };
+        match self {
+            Variant::String(s) => {
+                let chars = s.chars().collect::<Vec<_>>();
+                let s: String = chars[range(chars.len())].iter().collect();
+                Variant::String(s.into_boxed_str())
+            }
+            Variant::Buffer(b) => Variant::Buffer(b[range(b.len())].to_vec().into_boxed_slice()),
+            Variant::Slice(b) => b
+                .try_get(range(b.len()))
+                .map_or(Variant::Nil, Variant::Slice),
+            _ => Variant::Nil,
+        }
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Variant::String(s) => Some(s.as_bytes()),
+            Variant::Buffer(b) => Some(b),
+            Variant::Slice(b) => Some(b.as_ref()),
+            _ => None,
+        }
+    }
+
     fn to_string(&self) -> String {
         match self {
             Variant::Nil => "nil".to_string(),
@@ -181,3 +398,189 @@ impl VariantExt for Variant {
         }
     }
 }
+
+pub(crate) fn to_bigint(v: &Variant) -> Option<BigInt> {
+    match v {
+        Variant::Int64(v) => Some(BigInt::from(*v)),
+        Variant::UInt64(v) => Some(BigInt::from(*v)),
+        Variant::BigInt(v) => Some(BigInt::from_signed_bytes_be(&v)),
+        Variant::Buffer(v) => Some(BigInt::from_bytes_be(Sign::Plus, &v)),
+        Variant::Slice(v) => Some(BigInt::from_bytes_be(Sign::Plus, &v)),
+        _ => None,
+    }
+}
+
+/// Returns the bytes to search for in `contains` and `startsWith`.
+///
+/// Non-negative integers are converted into minimal big-endian bytes,
+/// so `0x474554` works the same as `x"474554"`. Hex literals written as
+/// patterns are parsed into bytes keeping their leading zeros.
+fn to_pattern_bytes(v: &Variant) -> Option<Cow<[u8]>> {
+    if let Some(b) = v.as_bytes() {
+        return Some(Cow::Borrowed(b));
+    }
+    match to_bigint(v) {
+        Some(ref v) if v.sign() != Sign::Minus => Some(Cow::Owned(v.to_bytes_be().1)),
+        _ => None,
+    }
+}
+
+/// Converts a possibly negative index into an offset from the beginning.
+fn resolve_index(index: i64, len: usize) -> Option<usize> {
+    if index < 0 {
+        (len as i64)
+            .checked_add(index)
+            .filter(|i| *i >= 0)
+            .map(|i| i as usize)
+    } else {
+        Some(index as usize)
+    }
+}
+
+fn to_f64(v: &Variant) -> Option<f64> {
+    match v {
+        Variant::Float64(v) => Some(*v),
+        Variant::Int64(v) => Some(*v as f64),
+        Variant::UInt64(v) => Some(*v as f64),
+        _ => to_bigint(v).and_then(|v| v.to_f64()),
+    }
+}
+
+pub(crate) fn from_bigint(v: BigInt) -> Variant {
+    Variant::BigInt(v.to_signed_bytes_be().into_boxed_slice()).shrink()
+}
+
+/// Applies a binary numeric operator.
+///
+/// `UInt64` and `Int64` pairs use the native operator and fall back to `BigInt`
+/// on overflow. A `Float64` operand promotes the other side to `Float64`,
+/// and `Buffer` or `Slice` values are treated as unsigned big-endian integers.
+fn arithmetic(
+    lhs: &Variant,
+    rhs: &Variant,
+    uint: fn(u64, u64) -> Option<u64>,
+    int: fn(i64, i64) -> Option<i64>,
+    float: Option<fn(f64, f64) -> f64>,
+    bigint: fn(BigInt, BigInt) -> Option<BigInt>,
+) -> Variant {
+    match (lhs, rhs) {
+        (Variant::UInt64(a), Variant::UInt64(b)) => {
+            if let Some(v) = uint(*a, *b) {
+                return Variant::UInt64(v);
+            }
+        }
+        (Variant::Int64(a), Variant::Int64(b)) => {
+            if let Some(v) = int(*a, *b) {
+                return Variant::Int64(v);
+            }
+        }
+        (Variant::Float64(_), _) | (_, Variant::Float64(_)) => {
+            return match (float, to_f64(lhs), to_f64(rhs)) {
+                (Some(f), Some(a), Some(b)) => Variant::Float64(f(a, b)),
+                _ => Variant::Nil,
+            };
+        }
+        _ => {}
+    }
+    match (to_bigint(lhs), to_bigint(rhs)) {
+        (Some(a), Some(b)) => bigint(a, b).map_or(Variant::Nil, from_bigint),
+        _ => Variant::Nil,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(
+            Variant::UInt64(3).op_add(&Variant::UInt64(4)),
+            Variant::UInt64(7)
+        );
+        assert_eq!(
+            Variant::UInt64(3).op_sub(&Variant::UInt64(4)),
+            Variant::Int64(-1)
+        );
+        assert_eq!(
+            Variant::Int64(-3).op_mul(&Variant::UInt64(4)),
+            Variant::Int64(-12)
+        );
+        assert_eq!(
+            Variant::UInt64(7).op_div(&Variant::Float64(2.0)),
+            Variant::Float64(3.5)
+        );
+        assert_eq!(
+            Variant::UInt64(8080).op_rem(&Variant::UInt64(1000)),
+            Variant::UInt64(80)
+        );
+        assert_eq!(Variant::UInt64(1).op_div(&Variant::UInt64(0)), Variant::Nil);
+        assert_eq!(
+            Variant::String("a".to_string().into_boxed_str()).op_add(&Variant::UInt64(1)),
+            Variant::Nil
+        );
+        assert_eq!(
+            Variant::UInt64(u64::max_value()).op_add(&Variant::UInt64(1)),
+            Variant::BigInt(
+                (BigInt::from(u64::max_value()) + BigInt::from(1))
+                    .to_signed_bytes_be()
+                    .into_boxed_slice()
+            )
+        );
+    }
+
+    #[test]
+    fn bytes() {
+        let host = Variant::String("www.example.com".to_string().into_boxed_str());
+        assert!(host.op_contains(&Variant::String("example".to_string().into_boxed_str())));
+        assert!(!host.op_contains(&Variant::String("corp".to_string().into_boxed_str())));
+        assert!(host.op_starts_with(&Variant::String("www.".to_string().into_boxed_str())));
+        assert!(!host.op_starts_with(&Variant::String("example".to_string().into_boxed_str())));
+
+        let payload = Variant::Buffer(b"GET / HTTP/1.1".to_vec().into_boxed_slice());
+        assert!(payload.op_starts_with(&Variant::UInt64(0x474554)));
+        assert!(payload.op_starts_with(&Variant::Buffer(b"GET".to_vec().into_boxed_slice())));
+        assert!(payload.op_contains(&Variant::String("HTTP".to_string().into_boxed_str())));
+        assert!(!payload.op_contains(&Variant::Int64(-1)));
+        assert!(payload.op_contains(&Variant::Buffer(Vec::new().into_boxed_slice())));
+        assert!(!Variant::UInt64(0x474554).op_contains(&Variant::UInt64(0x47)));
+    }
+
+    #[test]
+    fn bitwise() {
+        assert_eq!(
+            Variant::UInt64(0x1f).op_bitwise_and(&Variant::UInt64(0x12)),
+            Variant::UInt64(0x12)
+        );
+        assert_eq!(
+            Variant::UInt64(0x10).op_bitwise_or(&Variant::UInt64(0x02)),
+            Variant::UInt64(0x12)
+        );
+        assert_eq!(
+            Variant::UInt64(0x13).op_bitwise_xor(&Variant::UInt64(0x01)),
+            Variant::UInt64(0x12)
+        );
+        assert_eq!(Variant::Int64(0).op_bitwise_not(), Variant::Int64(-1));
+        assert_eq!(
+            Variant::Buffer(vec![0x12, 0x34].into_boxed_slice())
+                .op_bitwise_and(&Variant::UInt64(0xff)),
+            Variant::UInt64(0x34)
+        );
+        assert_eq!(
+            Variant::UInt64(1).op_shl(&Variant::UInt64(4)),
+            Variant::UInt64(16)
+        );
+        assert_eq!(
+            Variant::UInt64(0x100).op_shr(&Variant::UInt64(4)),
+            Variant::UInt64(0x10)
+        );
+        assert_eq!(
+            Variant::UInt64(1).op_shl(&Variant::UInt64(1_000_000_000_000)),
+            Variant::Nil
+        );
+        assert_eq!(
+            Variant::Float64(1.0).op_bitwise_and(&Variant::UInt64(1)),
+            Variant::Nil
+        );
+    }
+}
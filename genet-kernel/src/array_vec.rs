@@ -1,13 +1,19 @@
-use std::{mem, ptr};
+use std::{collections::VecDeque, mem, ptr};
 
 const BLOCK_SIZE: usize = 1024;
 
+/// An append-only vector whose elements never move.
+///
+/// Elements are addressed by their absolute position, which is kept
+/// after the oldest elements are removed by `evict`.
 #[derive(Debug)]
 pub struct ArrayVec<T>
 where
     T: Sized,
 {
-    buckets: Vec<*mut [T; BLOCK_SIZE]>,
+    buckets: VecDeque<*mut [T; BLOCK_SIZE]>,
+    freed: usize,
+    start: usize,
     len: usize,
 }
 
@@ -17,18 +23,26 @@ unsafe impl<T: Send> Sync for ArrayVec<T> {}
 impl<T> ArrayVec<T> {
     pub fn new() -> ArrayVec<T> {
         Self {
-            buckets: Vec::new(),
+            buckets: VecDeque::new(),
+            freed: 0,
+            start: 0,
             len: 0,
         }
     }
 
+    /// Returns the number of elements ever pushed, including evicted ones.
     pub fn len(&self) -> usize {
         self.len
     }
 
+    /// Returns the position of the oldest element not evicted.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
     pub fn get(&self, index: usize) -> Option<&T> {
-        if index < self.len {
-            let bucket = index / BLOCK_SIZE;
+        if index >= self.start && index < self.len {
+            let bucket = index / BLOCK_SIZE - self.freed;
             let offset = index % BLOCK_SIZE;
             unsafe { Some(&(*self.buckets[bucket])[offset]) }
         } else {
@@ -36,12 +50,22 @@ impl<T> ArrayVec<T> {
         }
     }
 
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.start && index < self.len {
+            let bucket = index / BLOCK_SIZE - self.freed;
+            let offset = index % BLOCK_SIZE;
+            unsafe { Some(&mut (*self.buckets[bucket])[offset]) }
+        } else {
+            None
+        }
+    }
+
     pub fn push(&mut self, val: T) {
-        let bucket = self.len / BLOCK_SIZE;
+        let bucket = self.len / BLOCK_SIZE - self.freed;
         let offset = self.len % BLOCK_SIZE;
         if self.buckets.len() <= bucket {
             let data: [T; BLOCK_SIZE] = unsafe { mem::uninitialized() };
-            self.buckets.push(Box::into_raw(Box::new(data)));
+            self.buckets.push_back(Box::into_raw(Box::new(data)));
         }
         unsafe {
             ptr::write(&mut (*self.buckets[bucket])[offset], val);
@@ -49,11 +73,38 @@ impl<T> ArrayVec<T> {
         self.len += 1;
     }
 
+    /// Drops the oldest elements up to the position `end`,
+    /// and frees the blocks which no longer have any element.
+    pub fn evict(&mut self, end: usize) {
+        let end = end.min(self.len);
+        while self.start < end {
+            let bucket = self.start / BLOCK_SIZE - self.freed;
+            let offset = self.start % BLOCK_SIZE;
+            unsafe {
+                ptr::drop_in_place(&mut (*self.buckets[bucket])[offset]);
+            }
+            self.start += 1;
+            if self.start % BLOCK_SIZE == 0 {
+                if let Some(bucket) = self.buckets.pop_front() {
+                    let bucket = bucket as *mut [mem::ManuallyDrop<T>; BLOCK_SIZE];
+                    mem::drop(unsafe { Box::from_raw(bucket) });
+                }
+                self.freed += 1;
+            }
+        }
+    }
+
+    /// Iterates over the elements not evicted.
+    #[cfg(test)]
     pub fn iter(&self) -> Iter<T> {
-        Iter { v: self, offset: 0 }
+        Iter {
+            v: self,
+            offset: self.start,
+        }
     }
 }
 
+#[cfg(test)]
 pub struct Iter<'a, T>
 where
     T: 'a,
@@ -62,6 +113,7 @@ where
     offset: usize,
 }
 
+#[cfg(test)]
 impl<'a, T> Iterator for Iter<'a, T> {
     type Item = &'a T;
 
@@ -72,6 +124,52 @@ impl<'a, T> Iterator for Iter<'a, T> {
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.v.len(), Some(self.v.len()))
+        let len = self.v.len().saturating_sub(self.offset);
+        (len, Some(len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use array_vec::{ArrayVec, BLOCK_SIZE};
+    use std::{cell::Cell, rc::Rc};
+
+    struct Counter(Rc<Cell<usize>>);
+
+    impl Drop for Counter {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn evict() {
+        let dropped = Rc::new(Cell::new(0));
+        let mut vec = ArrayVec::new();
+        for _ in 0..BLOCK_SIZE * 3 {
+            vec.push(Counter(dropped.clone()));
+        }
+        vec.evict(10);
+        assert_eq!(dropped.get(), 10);
+        assert_eq!(vec.start(), 10);
+        assert_eq!(vec.len(), BLOCK_SIZE * 3);
+        assert!(vec.get(9).is_none());
+        assert!(vec.get(10).is_some());
+        assert_eq!(vec.iter().count(), BLOCK_SIZE * 3 - 10);
+
+        vec.evict(BLOCK_SIZE * 2 + 1);
+        assert_eq!(dropped.get(), BLOCK_SIZE * 2 + 1);
+        assert!(vec.get(BLOCK_SIZE * 2).is_none());
+        assert!(vec.get(BLOCK_SIZE * 2 + 1).is_some());
+
+        vec.push(Counter(dropped.clone()));
+        assert_eq!(vec.len(), BLOCK_SIZE * 3 + 1);
+        assert!(vec.get(BLOCK_SIZE * 3).is_some());
+
+        vec.evict(0);
+        assert_eq!(vec.start(), BLOCK_SIZE * 2 + 1);
+        vec.evict(usize::max_value());
+        assert_eq!(dropped.get(), BLOCK_SIZE * 3 + 1);
+        assert_eq!(vec.iter().count(), 0);
     }
 }
@@ -5,7 +5,7 @@ use genet_napi::napi::{
     CallbackInfo, Env, PropertyAttributes, PropertyDescriptor, Result, Status, Value, ValueRef,
     ValueType,
 };
-use std::rc::Rc;
+use std::{rc::Rc, sync::Arc};
 
 pub fn wrapper(env: &Env) -> Rc<ValueRef> {
     fn ctor<'env>(env: &'env Env, _info: &CallbackInfo) -> Result<&'env Value> {
@@ -13,12 +13,26 @@ pub fn wrapper(env: &Env) -> Rc<ValueRef> {
     }
 
     fn frame_index<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
-        let frame = env.unwrap::<Frame>(info.this())?;
+        let frame = env.unwrap::<Arc<Frame>>(info.this())?;
         env.create_uint32(frame.index())
     }
 
+    fn frame_input<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let frame = env.unwrap::<Arc<Frame>>(info.this())?;
+        if let Some(input) = frame.input() {
+            env.create_uint32(input)
+        } else {
+            env.get_null()
+        }
+    }
+
+    fn frame_removed<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let frame = env.unwrap::<Arc<Frame>>(info.this())?;
+        env.get_boolean(frame.is_removed())
+    }
+
     fn frame_tree_indices<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
-        let frame = env.unwrap::<Frame>(info.this())?;
+        let frame = env.unwrap::<Arc<Frame>>(info.this())?;
         let indices = frame.tree_indices();
         let array = env.create_array(indices.len())?;
         for (i, item) in indices.iter().enumerate() {
@@ -28,7 +42,7 @@ pub fn wrapper(env: &Env) -> Rc<ValueRef> {
     }
 
     fn frame_query<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
-        let frame = env.unwrap::<Frame>(info.this())?;
+        let frame = env.unwrap::<Arc<Frame>>(info.this())?;
         if let Some(id) = info.argv().get(0) {
             let id = match env.type_of(id)? {
                 ValueType::Number => Token::from(env.get_value_uint32(id)?),
@@ -56,7 +70,7 @@ pub fn wrapper(env: &Env) -> Rc<ValueRef> {
     }
 
     fn frame_layers<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
-        let frame = env.unwrap::<Frame>(info.this())?;
+        let frame = env.unwrap::<Arc<Frame>>(info.this())?;
         let layers = frame.layers();
         let layer_class = env.get_constructor(JsClass::Layer as usize).unwrap();
         let array = env.create_array(layers.len())?;
@@ -80,6 +94,20 @@ pub fn wrapper(env: &Env) -> Rc<ValueRef> {
                     frame_index,
                     false,
                 ),
+                PropertyDescriptor::new_property(
+                    env,
+                    "input",
+                    PropertyAttributes::DEFAULT,
+                    frame_input,
+                    false,
+                ),
+                PropertyDescriptor::new_property(
+                    env,
+                    "removed",
+                    PropertyAttributes::DEFAULT,
+                    frame_removed,
+                    false,
+                ),
                 PropertyDescriptor::new_property(
                     env,
                     "layers",
@@ -1,16 +1,18 @@
 use binding::JsClass;
+use genet_abi::token::Token;
 use genet_filter::Filter;
 use genet_napi::{
     napi::{
         CallbackInfo, Env, HandleScope, PropertyAttributes, PropertyDescriptor, Result, Status,
-        Value, ValueRef,
+        Value, ValueRef, ValueType,
     },
     uv,
 };
 use parking_lot::Mutex;
 use profile::Profile;
 use serde_json;
-use session::{Callback, Event, Session};
+use session::{Callback, Event, Order, Selection, Session};
+use stats::Metric;
 use std::{collections::VecDeque, rc::Rc, sync::Arc};
 
 #[derive(Clone)]
@@ -89,6 +91,16 @@ pub fn init(env: &Env, exports: &Value) -> Result<()> {
         }
     }
 
+    fn profile_add_token<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let profile = env.unwrap::<Profile>(info.this())?;
+        if let Some(value) = info.argv().get(0) {
+            profile.add_token(&env.get_value_string(value)?);
+            env.get_null()
+        } else {
+            Err(Status::InvalidArg)
+        }
+    }
+
     fn profile_concurrency<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
         let profile = env.unwrap::<Profile>(info.this())?;
         if let Some(value) = info.argv().get(0) {
@@ -99,6 +111,49 @@ pub fn init(env: &Env, exports: &Value) -> Result<()> {
         }
     }
 
+    fn profile_max_frames<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let profile = env.unwrap::<Profile>(info.this())?;
+        if let Some(value) = info.argv().get(0) {
+            profile.set_max_frames(env.get_value_double(value)? as usize);
+            env.get_null()
+        } else {
+            env.create_double(profile.max_frames().unwrap_or(0) as f64)
+        }
+    }
+
+    fn profile_max_bytes<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let profile = env.unwrap::<Profile>(info.this())?;
+        if let Some(value) = info.argv().get(0) {
+            profile.set_max_bytes(env.get_value_double(value)? as usize);
+            env.get_null()
+        } else {
+            env.create_double(profile.max_bytes().unwrap_or(0) as f64)
+        }
+    }
+
+    fn profile_max_resident_frames<'env>(
+        env: &'env Env,
+        info: &CallbackInfo,
+    ) -> Result<&'env Value> {
+        let profile = env.unwrap::<Profile>(info.this())?;
+        if let Some(value) = info.argv().get(0) {
+            profile.set_max_resident_frames(env.get_value_double(value)? as usize);
+            env.get_null()
+        } else {
+            env.create_double(profile.max_resident_frames().unwrap_or(0) as f64)
+        }
+    }
+
+    fn profile_merge_inputs<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let profile = env.unwrap::<Profile>(info.this())?;
+        if let Some(value) = info.argv().get(0) {
+            profile.set_merge_inputs(env.get_value_bool(value)?);
+            env.get_null()
+        } else {
+            env.get_boolean(profile.merge_inputs())
+        }
+    }
+
     fn session_frames<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
         let session = env.unwrap::<Session>(info.this())?;
         if let Some([start, end]) = info.argv().get(0..2) {
@@ -109,7 +164,7 @@ pub fn init(env: &Env, exports: &Value) -> Result<()> {
             let array = env.create_array(frames.len())?;
             for (i, item) in frames.iter().enumerate() {
                 let instance = env.new_instance(&frame_class, &[])?;
-                env.wrap_ptr(instance, *item)?;
+                env.wrap(instance, item.clone())?;
                 env.set_element(array, i as u32, instance)?;
             }
             Ok(array)
@@ -135,6 +190,60 @@ pub fn init(env: &Env, exports: &Value) -> Result<()> {
         }
     }
 
+    fn session_frames_for_input<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let session = env.unwrap::<Session>(info.this())?;
+        if let Some([id, start, end]) = info.argv().get(0..3) {
+            let id = env.get_value_uint32(id)?;
+            let start = env.get_value_uint32(start)?;
+            let end = env.get_value_uint32(end)?;
+            let frames = session.frames_for_input(id, start as usize..end as usize);
+            let array = env.create_array(frames.len())?;
+            for (i, item) in frames.iter().enumerate() {
+                env.set_element(array, i as u32, env.create_uint32(*item)?)?;
+            }
+            Ok(array)
+        } else {
+            Err(Status::InvalidArg)
+        }
+    }
+
+    fn session_sorted_frames<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let session = env.unwrap::<Session>(info.this())?;
+        if let Some([id, start, end]) = info.argv().get(0..3) {
+            let id = env.get_value_uint32(id)?;
+            let start = env.get_value_uint32(start)?;
+            let end = env.get_value_uint32(end)?;
+            let frames = session.sorted_frames(id, start as usize..end as usize);
+            let array = env.create_array(frames.len())?;
+            for (i, item) in frames.iter().enumerate() {
+                env.set_element(array, i as u32, env.create_uint32(*item)?)?;
+            }
+            Ok(array)
+        } else {
+            Err(Status::InvalidArg)
+        }
+    }
+
+    fn session_set_sort<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let session = env.unwrap::<Session>(info.this())?;
+        if let Some([id, attr, order]) = info.argv().get(0..3) {
+            let id = env.get_value_uint32(id)?;
+            let attr = env.get_value_string(attr)?;
+            if attr.is_empty() {
+                session.unset_sort(id);
+            } else {
+                let order = match env.get_value_string(order)?.as_str() {
+                    "desc" => Order::Descending,
+                    _ => Order::Ascending,
+                };
+                session.set_sort(id, Token::from(attr), order);
+            }
+            env.get_null()
+        } else {
+            Err(Status::InvalidArg)
+        }
+    }
+
     fn session_set_filter<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
         let session = env.unwrap::<Session>(info.this())?;
         if let Some([id, filter]) = info.argv().get(0..2) {
@@ -144,10 +253,10 @@ pub fn init(env: &Env, exports: &Value) -> Result<()> {
                 if filter.is_empty() {
                     None
                 } else {
-                    match Filter::compile(&filter) {
+                    match Filter::compile_with(&filter, session.profile().functions()) {
                         Ok(filter) => Some(filter),
                         Err(err) => {
-                            env.throw_error("load_library", &err.to_string())?;
+                            env.throw_error("filter", &err.to_string())?;
                             None
                         }
                     }
@@ -172,22 +281,44 @@ pub fn init(env: &Env, exports: &Value) -> Result<()> {
 
     fn session_create_writer<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
         let session = env.unwrap::<Session>(info.this())?;
-        if let Some([id, arg, filter]) = info.argv().get(0..3) {
-            let filter = env.get_value_string(filter)?;
+        if let Some([id, arg, selection]) = info.argv().get(0..3) {
+            let selection = match env.type_of(selection)? {
+                ValueType::Number => Selection::View(env.get_value_uint32(selection)?),
+                ValueType::Object if env.is_array(selection)? => {
+                    let mut ranges = Vec::new();
+                    for i in 0..env.get_array_length(selection)? {
+                        let range = env.get_element(selection, i)?;
+                        let start = env.get_value_uint32(env.get_element(range, 0)?)?;
+                        let end = env.get_value_uint32(env.get_element(range, 1)?)?;
+                        ranges.push(start..end);
+                    }
+                    Selection::Ranges(ranges)
+                }
+                _ => {
+                    let filter = env.get_value_string(selection)?;
+                    Selection::Filter(if filter.is_empty() {
+                        None
+                    } else {
+                        match Filter::compile_with(&filter, session.profile().functions()) {
+                            Ok(filter) => Some(filter),
+                            Err(err) => {
+                                env.throw_error("filter", &err.to_string())?;
+                                None
+                            }
+                        }
+                    })
+                }
+            };
+            let follow = if let Some(follow) = info.argv().get(3) {
+                env.get_value_bool(follow)?
+            } else {
+                false
+            };
             let handle = session.create_writer(
                 &env.get_value_string(id)?,
                 &env.get_value_string(arg)?,
-                if filter.is_empty() {
-                    None
-                } else {
-                    match Filter::compile(&filter) {
-                        Ok(filter) => Some(filter),
-                        Err(err) => {
-                            env.throw_error("load_library", &err.to_string())?;
-                            None
-                        }
-                    }
-                },
+                selection,
+                follow,
             );
             env.create_uint32(handle)
         } else {
@@ -195,16 +326,109 @@ pub fn init(env: &Env, exports: &Value) -> Result<()> {
         }
     }
 
+    fn session_close_writer<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let session = env.unwrap::<Session>(info.this())?;
+        if let Some(value) = info.argv().get(0) {
+            session.close_writer(env.get_value_uint32(value)?);
+            env.get_null()
+        } else {
+            Err(Status::InvalidArg)
+        }
+    }
+
     fn session_close_reader<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
         let session = env.unwrap::<Session>(info.this())?;
         if let Some(value) = info.argv().get(0) {
-            session.close_reader(env.get_value_uint32(value)?);
+            let remove_frames = if let Some(remove_frames) = info.argv().get(1) {
+                env.get_value_bool(remove_frames)?
+            } else {
+                false
+            };
+            session.close_reader(env.get_value_uint32(value)?, remove_frames);
             env.get_null()
         } else {
             Err(Status::InvalidArg)
         }
     }
 
+    fn session_hierarchy<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let session = env.unwrap::<Session>(info.this())?;
+        let id = if let Some(id) = info.argv().get(0) {
+            Some(env.get_value_uint32(id)?)
+        } else {
+            None
+        };
+        if let Some(hierarchy) = session.hierarchy(id) {
+            let json = serde_json::to_string(&hierarchy).unwrap();
+            env.create_string(&json)
+        } else {
+            env.get_null()
+        }
+    }
+
+    fn session_conversations<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let session = env.unwrap::<Session>(info.this())?;
+        let id = if let Some(id) = info.argv().get(0) {
+            Some(env.get_value_uint32(id)?)
+        } else {
+            None
+        };
+        if let Some(conversations) = session.conversations(id) {
+            let json = serde_json::to_string(&conversations).unwrap();
+            env.create_string(&json)
+        } else {
+            env.get_null()
+        }
+    }
+
+    /// Parses the view id, the interval and the metric of an I/O graph,
+    /// throwing an error and returning `None` for an invalid metric.
+    fn io_graph_args(env: &Env, info: &CallbackInfo) -> Result<Option<(Option<u32>, f64, Metric)>> {
+        if let Some([id, interval, metric]) = info.argv().get(0..3) {
+            let id = if env.get_value_double(id)? > 0.0 {
+                Some(env.get_value_uint32(id)?)
+            } else {
+                None
+            };
+            let interval = env.get_value_double(interval)?;
+            match env.get_value_string(metric)?.parse::<Metric>() {
+                Ok(metric) => Ok(Some((id, interval, metric))),
+                Err(err) => {
+                    env.throw_error("ioGraph", &err)?;
+                    Ok(None)
+                }
+            }
+        } else {
+            Err(Status::InvalidArg)
+        }
+    }
+
+    fn session_io_graph<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let session = env.unwrap::<Session>(info.this())?;
+        if let Some((id, interval, metric)) = io_graph_args(env, info)? {
+            let json = serde_json::to_string(&session.io_graph(id, interval, metric)).unwrap();
+            env.create_string(&json)
+        } else {
+            env.get_null()
+        }
+    }
+
+    fn session_add_io_graph<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let session = env.unwrap::<Session>(info.this())?;
+        if let Some((id, interval, metric)) = io_graph_args(env, info)? {
+            session.add_io_graph(id, interval, metric);
+        }
+        env.get_null()
+    }
+
+    fn session_remove_io_graph<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
+        let session = env.unwrap::<Session>(info.this())?;
+        if let Some((id, interval, metric)) = io_graph_args(env, info)? {
+            session.remove_io_graph(id, interval, metric);
+        }
+        env.get_null()
+    }
+
     fn session_length<'env>(env: &'env Env, info: &CallbackInfo) -> Result<&'env Value> {
         let session = env.unwrap::<Session>(info.this())?;
         env.create_uint32(session.len() as u32)
@@ -232,12 +456,30 @@ pub fn init(env: &Env, exports: &Value) -> Result<()> {
                 PropertyAttributes::DEFAULT,
                 session_filtered_frames,
             ),
+            PropertyDescriptor::new_method(
+                env,
+                "framesForInput",
+                PropertyAttributes::DEFAULT,
+                session_frames_for_input,
+            ),
+            PropertyDescriptor::new_method(
+                env,
+                "sortedFrames",
+                PropertyAttributes::DEFAULT,
+                session_sorted_frames,
+            ),
             PropertyDescriptor::new_method(
                 env,
                 "setFilter",
                 PropertyAttributes::DEFAULT,
                 session_set_filter,
             ),
+            PropertyDescriptor::new_method(
+                env,
+                "setSort",
+                PropertyAttributes::DEFAULT,
+                session_set_sort,
+            ),
             PropertyDescriptor::new_method(
                 env,
                 "createReader",
@@ -256,6 +498,42 @@ pub fn init(env: &Env, exports: &Value) -> Result<()> {
                 PropertyAttributes::DEFAULT,
                 session_close_reader,
             ),
+            PropertyDescriptor::new_method(
+                env,
+                "closeWriter",
+                PropertyAttributes::DEFAULT,
+                session_close_writer,
+            ),
+            PropertyDescriptor::new_method(
+                env,
+                "hierarchy",
+                PropertyAttributes::DEFAULT,
+                session_hierarchy,
+            ),
+            PropertyDescriptor::new_method(
+                env,
+                "conversations",
+                PropertyAttributes::DEFAULT,
+                session_conversations,
+            ),
+            PropertyDescriptor::new_method(
+                env,
+                "ioGraph",
+                PropertyAttributes::DEFAULT,
+                session_io_graph,
+            ),
+            PropertyDescriptor::new_method(
+                env,
+                "addIoGraph",
+                PropertyAttributes::DEFAULT,
+                session_add_io_graph,
+            ),
+            PropertyDescriptor::new_method(
+                env,
+                "removeIoGraph",
+                PropertyAttributes::DEFAULT,
+                session_remove_io_graph,
+            ),
             PropertyDescriptor::new_property(
                 env,
                 "length",
@@ -289,6 +567,12 @@ pub fn init(env: &Env, exports: &Value) -> Result<()> {
                 PropertyAttributes::DEFAULT,
                 profile_load_library,
             ),
+            PropertyDescriptor::new_method(
+                env,
+                "addToken",
+                PropertyAttributes::DEFAULT,
+                profile_add_token,
+            ),
             PropertyDescriptor::new_property(
                 env,
                 "concurrency",
@@ -296,6 +580,34 @@ pub fn init(env: &Env, exports: &Value) -> Result<()> {
                 profile_concurrency,
                 true,
             ),
+            PropertyDescriptor::new_property(
+                env,
+                "maxFrames",
+                PropertyAttributes::DEFAULT,
+                profile_max_frames,
+                true,
+            ),
+            PropertyDescriptor::new_property(
+                env,
+                "maxBytes",
+                PropertyAttributes::DEFAULT,
+                profile_max_bytes,
+                true,
+            ),
+            PropertyDescriptor::new_property(
+                env,
+                "maxResidentFrames",
+                PropertyAttributes::DEFAULT,
+                profile_max_resident_frames,
+                true,
+            ),
+            PropertyDescriptor::new_property(
+                env,
+                "mergeInputs",
+                PropertyAttributes::DEFAULT,
+                profile_merge_inputs,
+                true,
+            ),
         ],
     )?;
 
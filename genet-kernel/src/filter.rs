@@ -0,0 +1,127 @@
+use array_vec::ArrayVec;
+use crossbeam_channel;
+use frame::Frame;
+use genet_filter::{context::Context, Filter};
+use parking_lot::{Mutex, RwLock};
+use spill::{self, Spill};
+use std::{
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+pub trait Callback: Sync + Send + Clone {
+    fn done(&self, result: Output);
+}
+
+/// A range of frames to test against a filter.
+///
+/// Jobs sharing a `cancelled` flag belong to the same filter,
+/// and are skipped once the flag is set.
+#[derive(Debug)]
+pub struct Job {
+    pub id: u32,
+    pub filter: Arc<Filter>,
+    pub range: Range<usize>,
+    pub cancelled: Arc<AtomicBool>,
+}
+
+/// The indices of the frames matching the filter of a job.
+#[derive(Debug)]
+pub struct Output {
+    pub id: u32,
+    pub range: Range<usize>,
+    pub cancelled: Arc<AtomicBool>,
+    pub indices: Vec<u32>,
+}
+
+pub struct Pool {
+    sender: crossbeam_channel::Sender<Option<Job>>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl Pool {
+    pub fn new<C: 'static + Callback>(
+        concurrency: u32,
+        frames: &Arc<RwLock<ArrayVec<Arc<Frame>>>>,
+        spill: &Option<Arc<Mutex<Spill>>>,
+        callback: &C,
+    ) -> Pool {
+        let (send, recv) = crossbeam_channel::unbounded::<Option<Job>>();
+        let mut handles = Vec::new();
+        for _ in 0..concurrency {
+            handles.push(Self::spawn(
+                frames.clone(),
+                spill.clone(),
+                callback.clone(),
+                recv.clone(),
+            ));
+        }
+        Pool {
+            sender: send,
+            handles,
+        }
+    }
+
+    fn spawn<C: 'static + Callback>(
+        frames: Arc<RwLock<ArrayVec<Arc<Frame>>>>,
+        spill: Option<Arc<Mutex<Spill>>>,
+        callback: C,
+        recv: crossbeam_channel::Receiver<Option<Job>>,
+    ) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            if let Some(Some(job)) = recv.recv() {
+                if job.cancelled.load(Ordering::Relaxed) {
+                    continue;
+                }
+                let chunk = {
+                    let frames = frames.read();
+                    job.range
+                        .clone()
+                        .filter_map(|index| frames.get(index).cloned())
+                        .collect::<Vec<_>>()
+                };
+                let chunk = chunk.iter().map(|frame| &**frame).collect::<Vec<_>>();
+                let restored = spill::restore(&spill, &chunk).unwrap_or_default();
+                let indices = spill::merge(chunk, &restored)
+                    .into_iter()
+                    .take_while(|_| !job.cancelled.load(Ordering::Relaxed))
+                    .filter(|frame| !frame.is_removed())
+                    .filter(|frame| {
+                        let ctx = Context::with_metadata(frame.layers(), frame.metadata());
+                        job.filter.test(&ctx)
+                    })
+                    .map(|frame| frame.index())
+                    .collect::<Vec<_>>();
+                if !job.cancelled.load(Ordering::Relaxed) {
+                    callback.done(Output {
+                        id: job.id,
+                        range: job.range,
+                        cancelled: job.cancelled,
+                        indices,
+                    });
+                }
+            } else {
+                return;
+            }
+        })
+    }
+
+    pub fn process(&mut self, job: Job) {
+        self.sender.send(Some(job));
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        for _ in 0..self.handles.len() {
+            self.sender.send(None);
+        }
+        while let Some(h) = self.handles.pop() {
+            h.join().unwrap();
+        }
+    }
+}
@@ -1,10 +1,46 @@
-use genet_abi::{attr::Attr, fixed::MutFixed, layer::Layer, token::Token};
-use std::{fmt, mem};
+use genet_abi::{
+    attr::Attr,
+    fixed::{Fixed, MutFixed},
+    layer::{Layer, LayerClass, Payload},
+    slice::{ByteSlice, TryGet},
+    token::Token,
+    variant::Variant,
+};
+use genet_filter::context::Metadata;
+use std::{
+    fmt, mem,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 pub struct Frame {
     index: u32,
+    input: Option<u32>,
+    timestamp: f64,
+    relative: f64,
+    delta: f64,
+    length: u64,
+    captured_length: u64,
     layers: Vec<MutFixed<Layer>>,
     tree_indices: Vec<u8>,
+    spilled: Option<Spilled>,
+    removed: AtomicBool,
+}
+
+/// The decoded layers of a frame whose bytes are written to a spill file.
+struct Spilled {
+    offset: u64,
+    len: usize,
+    layers: Vec<SpilledLayer>,
+    tree_indices: Vec<u8>,
+}
+
+/// A layer whose bytes are followed by the bytes of its payloads
+/// in the spill file.
+struct SpilledLayer {
+    class: Fixed<LayerClass>,
+    len: usize,
+    attrs: Vec<Fixed<Attr>>,
+    payloads: Vec<(Token, Token, usize)>,
 }
 
 impl fmt::Debug for Frame {
@@ -14,13 +50,39 @@ impl fmt::Debug for Frame {
 }
 
 unsafe impl Send for Frame {}
+unsafe impl Sync for Frame {}
 
 impl Frame {
-    pub fn new(index: u32, root: MutFixed<Layer>) -> Frame {
+    /// Creates a frame from the root layer read from the input.
+    ///
+    /// The timestamp and the original length are taken from the
+    /// `link.timestamp` and `link.length` attributes of the root layer.
+    pub fn new(index: u32, input: Option<u32>, root: MutFixed<Layer>) -> Frame {
+        let value = |id: &str| {
+            root.attr(Token::from(id))
+                .and_then(|attr| attr.try_get(&root).ok())
+        };
+        let timestamp = match value("link.timestamp") {
+            Some(Variant::Float64(v)) => v,
+            _ => 0.0,
+        };
+        let captured_length = root.data().len() as u64;
+        let length = match value("link.length") {
+            Some(Variant::UInt64(v)) => v,
+            _ => captured_length,
+        };
         Frame {
             index,
+            input,
+            timestamp,
+            relative: 0.0,
+            delta: 0.0,
+            length,
+            captured_length,
             layers: vec![root],
             tree_indices: Vec::new(),
+            spilled: None,
+            removed: AtomicBool::new(false),
         }
     }
 
@@ -28,6 +90,58 @@ impl Frame {
         self.index
     }
 
+    /// Assigns the index of a frame held back to be ordered with other inputs.
+    pub fn set_index(&mut self, index: u32) {
+        self.index = index;
+    }
+
+    /// Returns the id of the input the frame was read from.
+    pub fn input(&self) -> Option<u32> {
+        self.input
+    }
+
+    /// Returns true if the input of the frame is closed with its frames removed.
+    ///
+    /// Removed frames stay in the store until evicted, but are excluded
+    /// from the views and the statistics.
+    pub fn is_removed(&self) -> bool {
+        self.removed.load(Ordering::Relaxed)
+    }
+
+    pub fn set_removed(&self) {
+        self.removed.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns the capture time in seconds since the Unix epoch.
+    pub fn timestamp(&self) -> f64 {
+        self.timestamp
+    }
+
+    /// Returns the number of bytes actually captured.
+    pub fn captured_length(&self) -> u64 {
+        self.captured_length
+    }
+
+    /// Sets the timestamps of the first and the previous frames,
+    /// from which the relative and delta times are computed.
+    pub fn set_reference_time(&mut self, first: f64, previous: f64) {
+        self.relative = self.timestamp - first;
+        self.delta = self.timestamp - previous;
+    }
+
+    /// Returns the metadata exposed to filters as `frame.*` fields.
+    pub fn metadata(&self) -> Metadata {
+        Metadata {
+            index: self.index,
+            timestamp: self.timestamp,
+            relative: self.relative,
+            delta: self.delta,
+            length: self.length,
+            captured_length: self.captured_length,
+            input: self.input,
+        }
+    }
+
     pub fn layers(&self) -> &[MutFixed<Layer>] {
         &self.layers
     }
@@ -64,4 +178,132 @@ impl Frame {
     pub fn set_tree_indices(&mut self, tree_indices: Vec<u8>) {
         self.tree_indices = tree_indices;
     }
+
+    /// Returns true if the layers of the frame are paged out.
+    pub fn is_spilled(&self) -> bool {
+        self.spilled.is_some()
+    }
+
+    /// Returns the offset and the length of the raw bytes in the spill file.
+    pub fn spilled_range(&self) -> Option<(u64, usize)> {
+        self.spilled.as_ref().map(|s| (s.offset, s.len))
+    }
+
+    /// Returns a copy of the frame without the decoded layers, and the bytes
+    /// of the layers and their payloads to be written to the spill file at `offset`.
+    ///
+    /// The frame itself keeps its layers for the bindings still holding it.
+    pub fn spill(&self, offset: u64) -> (Frame, Vec<u8>) {
+        let mut data = Vec::new();
+        let layers = self
+            .layers
+            .iter()
+            .map(|layer| {
+                data.extend_from_slice(&layer.data());
+                let payloads = layer
+                    .payloads()
+                    .iter()
+                    .map(|payload| {
+                        data.extend_from_slice(&payload.data());
+                        (payload.id(), payload.typ(), payload.data().len())
+                    })
+                    .collect();
+                SpilledLayer {
+                    class: layer.class(),
+                    len: layer.data().len(),
+                    attrs: layer.attrs().to_vec(),
+                    payloads,
+                }
+            })
+            .collect();
+        let spilled = Spilled {
+            offset,
+            len: data.len(),
+            layers,
+            tree_indices: self.tree_indices.clone(),
+        };
+        (self.copy(Vec::new(), Some(spilled)), data)
+    }
+
+    /// Creates a copy of a spilled frame with the layers as decoded,
+    /// from the bytes read back from the spill file.
+    pub fn restore(&self, data: Vec<u8>) -> Option<Frame> {
+        self.spilled.as_ref().map(|spilled| {
+            let data = ByteSlice::from(data);
+            let mut offset = 0;
+            let mut take = |len: usize| {
+                let slice = data
+                    .try_get(offset..offset + len)
+                    .unwrap_or_else(|_| ByteSlice::new());
+                offset += len;
+                slice
+            };
+            let layers = spilled
+                .layers
+                .iter()
+                .map(|spilled| {
+                    let mut layer = Layer::new(spilled.class.clone(), take(spilled.len));
+                    for attr in &spilled.attrs {
+                        layer.add_attr(attr.clone());
+                    }
+                    for (id, typ, len) in &spilled.payloads {
+                        layer.add_payload(Payload::with_typ(take(*len), *id, *typ));
+                    }
+                    MutFixed::new(layer)
+                })
+                .collect();
+            let mut frame = self.copy(layers, None);
+            frame.tree_indices = spilled.tree_indices.clone();
+            frame
+        })
+    }
+
+    fn copy(&self, layers: Vec<MutFixed<Layer>>, spilled: Option<Spilled>) -> Frame {
+        Frame {
+            index: self.index,
+            input: self.input,
+            timestamp: self.timestamp,
+            relative: self.relative,
+            delta: self.delta,
+            length: self.length,
+            captured_length: self.captured_length,
+            layers,
+            tree_indices: Vec::new(),
+            spilled,
+            removed: AtomicBool::new(self.is_removed()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frame::Frame;
+    use genet_abi::{
+        attr::{Attr, AttrClass},
+        fixed::{Fixed, MutFixed},
+        layer::{Layer, LayerClass},
+        slice::ByteSlice,
+        token::Token,
+    };
+
+    #[test]
+    fn metadata() {
+        let class = Fixed::new(LayerClass::builder(Token::from("[link-1]")).build());
+        let mut root = Layer::new(class, ByteSlice::from(vec![0u8; 64]));
+        let ts = Fixed::new(AttrClass::builder("link.timestamp").value(10.5).build());
+        let len = Fixed::new(AttrClass::builder("link.length").value(1514u64).build());
+        root.add_attr(Attr::builder(ts).build());
+        root.add_attr(Attr::builder(len).build());
+
+        let mut frame = Frame::new(3, Some(1), MutFixed::new(root));
+        frame.set_reference_time(8.0, 10.0);
+        let metadata = frame.metadata();
+        assert_eq!(metadata.index, 3);
+        assert_eq!(metadata.input, Some(1));
+        assert_eq!(metadata.timestamp, 10.5);
+        assert_eq!(metadata.relative, 2.5);
+        assert_eq!(metadata.delta, 0.5);
+        assert_eq!(metadata.length, 1514);
+        assert_eq!(metadata.captured_length, 64);
+    }
 }
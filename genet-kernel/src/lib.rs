@@ -1,3 +1,4 @@
+#[macro_use]
 extern crate crossbeam_channel;
 extern crate fnv;
 extern crate genet_abi;
@@ -16,10 +17,13 @@ extern crate serde_derive;
 pub mod binding;
 pub mod profile;
 pub mod session;
+pub mod stats;
 
 mod array_vec;
 mod decoder;
+mod filter;
 mod frame;
 mod io;
 mod result;
+mod spill;
 mod store;
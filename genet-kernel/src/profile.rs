@@ -8,17 +8,29 @@ use genet_abi::{
     token::Token,
     writer::WriterBox,
 };
+use genet_filter::function::{Function, Registry};
 use libloading::Library;
 use num_cpus;
+use serde_json;
 use std::{fmt, io, mem};
 
+/// The config key of named filter macros, a JSON object such as
+/// `{"web": "tcp.dst in {80, 443}"}`.
+const FILTER_MACROS_KEY: &str = "_.filter.macros";
+
 #[derive(Serialize, Clone, Default)]
 pub struct Profile {
     concurrency: u32,
+    max_frames: Option<usize>,
+    max_bytes: Option<usize>,
+    max_resident_frames: Option<usize>,
+    merge_inputs: bool,
     decoders: Vec<DecoderBox>,
     readers: Vec<ReaderBox>,
     writers: Vec<WriterBox>,
     config: FnvHashMap<String, String>,
+    #[serde(skip)]
+    functions: Registry,
 }
 
 impl fmt::Debug for Profile {
@@ -31,10 +43,15 @@ impl Profile {
     pub fn new() -> Profile {
         Profile {
             concurrency: 4,
+            max_frames: None,
+            max_bytes: None,
+            max_resident_frames: None,
+            merge_inputs: false,
             decoders: Vec::new(),
             readers: Vec::new(),
             writers: Vec::new(),
             config: FnvHashMap::default(),
+            functions: Registry::default(),
         }
     }
 
@@ -50,14 +67,75 @@ impl Profile {
         self.concurrency
     }
 
+    /// Limits the number of frames kept in a session.
+    ///
+    /// Once exceeded, the oldest frames are evicted and the pointers
+    /// returned by `Session::frames` for them become invalid.
+    /// `0` removes the limit.
+    pub fn set_max_frames(&mut self, frames: usize) {
+        self.max_frames = if frames > 0 { Some(frames) } else { None };
+    }
+
+    pub fn max_frames(&self) -> Option<usize> {
+        self.max_frames
+    }
+
+    /// Limits the total captured bytes of the frames kept in a session,
+    /// in the same way as `set_max_frames`.
+    pub fn set_max_bytes(&mut self, bytes: usize) {
+        self.max_bytes = if bytes > 0 { Some(bytes) } else { None };
+    }
+
+    pub fn max_bytes(&self) -> Option<usize> {
+        self.max_bytes
+    }
+
+    /// Limits the number of decoded frames kept in memory.
+    ///
+    /// The bytes of older frames are written to a temporary file and read back
+    /// with their decoded layers when `Session::frames` asks for them.
+    /// `0` keeps all frames in memory.
+    pub fn set_max_resident_frames(&mut self, frames: usize) {
+        self.max_resident_frames = if frames > 0 { Some(frames) } else { None };
+    }
+
+    pub fn max_resident_frames(&self) -> Option<usize> {
+        self.max_resident_frames
+    }
+
+    /// Orders the frames of multiple inputs by `link.timestamp`.
+    ///
+    /// Frames are held until every open input has read a frame at least
+    /// as recent, so each input must be ordered by itself.
+    pub fn set_merge_inputs(&mut self, merge: bool) {
+        self.merge_inputs = merge;
+    }
+
+    pub fn merge_inputs(&self) -> bool {
+        self.merge_inputs
+    }
+
     pub fn get_config(&self, key: &str) -> Option<String> {
         self.config.get(key).map(|s| s.to_string())
     }
 
     pub fn set_config(&mut self, key: &str, value: &str) {
-        self.config
+        let value = self
+            .config
             .entry(String::from(key))
             .or_insert_with(|| String::from(value));
+        if key == FILTER_MACROS_KEY {
+            if let Ok(macros) = serde_json::from_str::<FnvHashMap<String, String>>(value) {
+                for (name, filter) in macros {
+                    self.functions.add_macro(&name, &filter);
+                }
+            }
+        }
+    }
+
+    /// Registers a decoder in addition to the ones loaded from libraries.
+    pub fn add_decoder(&mut self, decoder: DecoderBox) {
+        self.decoders.push(decoder);
     }
 
     pub fn decoders(&self) -> impl Iterator<Item = &DecoderBox> {
@@ -72,6 +150,23 @@ impl Profile {
         self.writers.iter()
     }
 
+    /// Registers a function callable from filters compiled for this profile.
+    pub fn add_function(&mut self, func: Function) {
+        self.functions.register(func);
+    }
+
+    /// Registers a known layer or attribute token.
+    ///
+    /// Once any token is registered, filters referring to unknown tokens
+    /// fail to compile with a suggestion.
+    pub fn add_token(&mut self, id: &str) {
+        self.functions.add_token(id);
+    }
+
+    pub fn functions(&self) -> &Registry {
+        &self.functions
+    }
+
     pub fn context(&self) -> Context {
         Context::new(self.config.clone())
     }
@@ -1,12 +1,15 @@
 use frame::Frame;
-use genet_abi::{self, fixed::MutFixed, layer::Layer, reader, writer};
+use genet_abi::{self, fixed::MutFixed, layer::Layer, reader, token::Token, writer};
 use genet_filter::Filter;
 use io::{Input, Output};
 use profile::Profile;
 use serde::ser::{Serialize, SerializeMap, Serializer};
-use std::{fmt, ops::Range};
+use stats::{Bucket, Conversations, Hierarchy, Metric};
+use std::{fmt, ops::Range, sync::Arc};
 use store::{self, Store};
 
+pub use store::{Order, Selection};
+
 pub struct Session {
     store: Store,
     callback: Box<Callback>,
@@ -29,7 +32,7 @@ impl Session {
         }
     }
 
-    pub fn frames(&self, range: Range<usize>) -> Vec<*const Frame> {
+    pub fn frames(&self, range: Range<usize>) -> Vec<Arc<Frame>> {
         self.store.frames(range)
     }
 
@@ -41,6 +44,22 @@ impl Session {
         self.store.set_filter(id, filter);
     }
 
+    /// Returns the frame indices of the sorted view in the range.
+    pub fn sorted_frames(&self, id: u32, range: Range<usize>) -> Vec<u32> {
+        self.store.sorted_frames(id, range)
+    }
+
+    /// Sorts the frames by an attribute or a `frame.*` pseudo-field.
+    ///
+    /// If a filter is set with the same id, the view only contains the matching frames.
+    pub fn set_sort(&mut self, id: u32, token: Token, order: Order) {
+        self.store.set_sort(id, token, order);
+    }
+
+    pub fn unset_sort(&mut self, id: u32) {
+        self.store.unset_sort(id);
+    }
+
     pub fn create_reader(&mut self, id: &str, arg: &str) -> u32 {
         if let Some(reader) = self
             .profile
@@ -63,7 +82,17 @@ impl Session {
         0
     }
 
-    pub fn create_writer(&mut self, id: &str, arg: &str, filter: Option<Filter>) -> u32 {
+    /// Writes the selected frames with the writer `id`.
+    ///
+    /// If `follow` is true, the writer keeps receiving new frames until
+    /// `close_writer` is called.
+    pub fn create_writer(
+        &mut self,
+        id: &str,
+        arg: &str,
+        selection: Selection,
+        follow: bool,
+    ) -> u32 {
         if let Some(writer) = self
             .profile
             .writers()
@@ -73,8 +102,12 @@ impl Session {
             let ctx = self.profile.context();
             match writer.new_worker(&ctx, arg) {
                 Ok(output) => {
-                    self.store
-                        .push_output(self.io_cnt, WorkerOutput::new(output), filter);
+                    self.store.push_output(
+                        self.io_cnt,
+                        WorkerOutput::new(output),
+                        selection,
+                        follow,
+                    );
                     return self.io_cnt;
                 }
                 Err(err) => {
@@ -86,14 +119,63 @@ impl Session {
         0
     }
 
-    pub fn close_reader(&mut self, handle: u32) {
-        self.store.unset_input(handle);
+    /// Stops writing, ending the output with the frames written so far.
+    pub fn close_writer(&mut self, handle: u32) {
+        self.store.close_output(handle);
+    }
+
+    /// Stops reading the input.
+    ///
+    /// If `remove_frames` is true, the frames read from the input are removed
+    /// from the session.
+    pub fn close_reader(&mut self, handle: u32, remove_frames: bool) {
+        self.store.unset_input(handle, remove_frames);
+    }
+
+    /// Returns the indices of the frames read from the input in the range.
+    pub fn frames_for_input(&self, handle: u32, range: Range<usize>) -> Vec<u32> {
+        self.store.frames_for_input(handle, range)
+    }
+
+    /// Returns the protocol hierarchy of all frames, or of the frames matching
+    /// the filter `id`.
+    pub fn hierarchy(&self, id: Option<u32>) -> Option<Hierarchy> {
+        self.store.hierarchy(id)
+    }
+
+    /// Returns the conversation and endpoint tables of all frames, or of the frames
+    /// matching the filter `id`.
+    pub fn conversations(&self, id: Option<u32>) -> Option<Conversations> {
+        self.store.conversations(id)
+    }
+
+    /// Returns the frames, the bytes or the values of an attribute bucketed
+    /// by `interval` seconds, for all frames or the frames matching the filter `id`.
+    ///
+    /// Returns no buckets unless the graph is tracked by `add_io_graph`.
+    pub fn io_graph(&self, id: Option<u32>, interval: f64, metric: Metric) -> Vec<Bucket> {
+        self.store.io_graph(id, interval, metric)
+    }
+
+    /// Starts tracking an I/O graph; `Event::IoGraph` is emitted as its buckets
+    /// change until `remove_io_graph` is called.
+    pub fn add_io_graph(&mut self, id: Option<u32>, interval: f64, metric: Metric) {
+        self.store.add_io_graph(id, interval, metric);
+    }
+
+    pub fn remove_io_graph(&mut self, id: Option<u32>, interval: f64, metric: Metric) {
+        self.store.remove_io_graph(id, interval, metric);
     }
 
     pub fn len(&self) -> usize {
         self.store.len()
     }
 
+    /// Returns the index of the oldest frame not evicted.
+    pub fn start(&self) -> usize {
+        self.store.start()
+    }
+
     pub fn profile(&self) -> &Profile {
         &self.profile
     }
@@ -136,6 +218,28 @@ impl store::Callback for StoreCallback {
         self.callback.on_event(Event::FilteredFrames(id, frames));
     }
 
+    fn on_sorted_frames_updated(&self, id: u32, frames: u32) {
+        self.callback.on_event(Event::SortedFrames(id, frames));
+    }
+
+    fn on_filter_progress(&self, id: u32, processed: u32, total: u32) {
+        self.callback
+            .on_event(Event::FilterProgress(id, processed, total));
+    }
+
+    fn on_frames_evicted(&self, range: Range<u32>) {
+        self.callback.on_event(Event::Evicted(range));
+    }
+
+    fn on_io_graph_updated(&self, id: Option<u32>, interval: f64, metric: Metric) {
+        self.callback.on_event(Event::IoGraph(id, interval, metric));
+    }
+
+    fn on_output_progress(&self, id: u32, processed: u32, total: u32) {
+        self.callback
+            .on_event(Event::OutputProgress(id, processed, total));
+    }
+
     fn on_output_done(&self, id: u32, error: Option<Box<::std::error::Error + Send>>) {
         self.callback.on_event(Event::Output(id, error));
     }
@@ -154,7 +258,12 @@ pub enum Event {
     Frames(u32),
     AsyncFrames(u32),
     FilteredFrames(u32, u32),
+    SortedFrames(u32, u32),
+    FilterProgress(u32, u32, u32),
+    Evicted(Range<u32>),
+    IoGraph(Option<u32>, f64, Metric),
     Input(u32, Option<Box<::std::error::Error + Send>>),
+    OutputProgress(u32, u32, u32),
     Output(u32, Option<Box<::std::error::Error + Send>>),
     Error(Box<::std::error::Error + Send>),
 }
@@ -207,6 +316,36 @@ impl Serialize for Event {
                 s.serialize_entry("length", &len)?;
                 s.end()
             }
+            Event::SortedFrames(id, len) => {
+                let mut s = serializer.serialize_map(Some(3))?;
+                s.serialize_entry("type", "sorted_frames")?;
+                s.serialize_entry("id", &id)?;
+                s.serialize_entry("length", &len)?;
+                s.end()
+            }
+            Event::FilterProgress(id, processed, total) => {
+                let mut s = serializer.serialize_map(Some(4))?;
+                s.serialize_entry("type", "filter_progress")?;
+                s.serialize_entry("id", &id)?;
+                s.serialize_entry("processed", &processed)?;
+                s.serialize_entry("total", &total)?;
+                s.end()
+            }
+            Event::Evicted(range) => {
+                let mut s = serializer.serialize_map(Some(3))?;
+                s.serialize_entry("type", "evicted")?;
+                s.serialize_entry("start", &range.start)?;
+                s.serialize_entry("end", &range.end)?;
+                s.end()
+            }
+            Event::IoGraph(id, interval, metric) => {
+                let mut s = serializer.serialize_map(Some(4))?;
+                s.serialize_entry("type", "io_graph")?;
+                s.serialize_entry("id", &id)?;
+                s.serialize_entry("interval", &interval)?;
+                s.serialize_entry("metric", &metric.to_string())?;
+                s.end()
+            }
             Event::Input(id, err) => {
                 let mut s = serializer.serialize_map(Some(3))?;
                 s.serialize_entry("type", "input")?;
@@ -214,6 +353,14 @@ impl Serialize for Event {
                 s.serialize_entry("error", &err.as_ref().map(|e| format!("{}", e)))?;
                 s.end()
             }
+            Event::OutputProgress(id, processed, total) => {
+                let mut s = serializer.serialize_map(Some(4))?;
+                s.serialize_entry("type", "output_progress")?;
+                s.serialize_entry("id", &id)?;
+                s.serialize_entry("processed", &processed)?;
+                s.serialize_entry("total", &total)?;
+                s.end()
+            }
             Event::Output(id, err) => {
                 let mut s = serializer.serialize_map(Some(3))?;
                 s.serialize_entry("type", "output")?;
@@ -0,0 +1,317 @@
+use fnv::FnvHashMap;
+use frame::Frame;
+use parking_lot::Mutex;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    env, fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::PathBuf,
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+static SPILL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// The size at which a new segment file is started.
+const SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Temporary files holding the bytes of frames paged out from memory.
+///
+/// Spilled frames keep their layers without the bytes, and are restored
+/// as decoded on demand. The most recently used ones are kept in memory.
+///
+/// The bytes are appended to segment files, which are deleted once
+/// all of their frames are evicted.
+pub struct Spill {
+    segments: VecDeque<Segment>,
+    segment_size: u64,
+    len: u64,
+    capacity: usize,
+    resident: FnvHashMap<u32, (Arc<Frame>, u64)>,
+    order: BTreeMap<u64, u32>,
+    generation: u64,
+}
+
+/// A spill file holding the bytes from `offset` of all spilled frames,
+/// up to the frame index `end`.
+struct Segment {
+    file: File,
+    path: PathBuf,
+    offset: u64,
+    len: u64,
+    end: u32,
+}
+
+impl Segment {
+    fn new(offset: u64) -> io::Result<Segment> {
+        let path = env::temp_dir().join(format!(
+            "genet-{}-{}.spill",
+            process::id(),
+            SPILL_COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Segment {
+            file,
+            path,
+            offset,
+            len: 0,
+            end: 0,
+        })
+    }
+
+    fn contains(&self, offset: u64) -> bool {
+        self.offset <= offset && offset < self.offset + self.len
+    }
+}
+
+impl Drop for Segment {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl Spill {
+    /// Creates a spill file keeping at most `capacity` restored frames in memory.
+    pub fn new(capacity: usize) -> io::Result<Spill> {
+        let mut segments = VecDeque::new();
+        segments.push_back(Segment::new(0)?);
+        Ok(Spill {
+            segments,
+            segment_size: SEGMENT_SIZE,
+            len: 0,
+            capacity,
+            resident: FnvHashMap::default(),
+            order: BTreeMap::new(),
+            generation: 0,
+        })
+    }
+
+    /// Writes the bytes of the frame, and returns a copy of the frame
+    /// without its decoded layers.
+    ///
+    /// Returns `None` if the frame is already spilled.
+    pub fn page_out(&mut self, frame: &Frame) -> io::Result<Option<Frame>> {
+        if frame.is_spilled() {
+            return Ok(None);
+        }
+        let (spilled, data) = frame.spill(self.len);
+        if self
+            .segments
+            .back()
+            .map_or(true, |segment| segment.len >= self.segment_size)
+        {
+            let segment = Segment::new(self.len)?;
+            self.segments.push_back(segment);
+        }
+        if let Some(segment) = self.segments.back_mut() {
+            segment.file.seek(SeekFrom::Start(segment.len))?;
+            segment.file.write_all(&data)?;
+            segment.len += data.len() as u64;
+            segment.end = frame.index() + 1;
+        }
+        self.len += data.len() as u64;
+        Ok(Some(spilled))
+    }
+
+    /// Reads copies of spilled frames, which are dropped by the caller.
+    pub fn read(&mut self, frames: &[&Frame]) -> io::Result<Vec<Frame>> {
+        let mut restored = Vec::with_capacity(frames.len());
+        for frame in frames {
+            if let Some((offset, len)) = frame.spilled_range() {
+                let mut data = vec![0u8; len];
+                if len > 0 {
+                    let segment = self
+                        .segments
+                        .iter_mut()
+                        .find(|segment| segment.contains(offset))
+                        .ok_or_else(|| {
+                            io::Error::new(io::ErrorKind::NotFound, "spilled frame evicted")
+                        })?;
+                    segment
+                        .file
+                        .seek(SeekFrom::Start(offset - segment.offset))?;
+                    segment.file.read_exact(&mut data)?;
+                }
+                restored.extend(frame.restore(data));
+            }
+        }
+        Ok(restored)
+    }
+
+    /// Replaces the spilled frames with the restored ones kept in memory,
+    /// after keeping the newly `restored` frames.
+    ///
+    /// The most recently used restored frames are kept for later calls.
+    fn cache(&mut self, frames: &[&Arc<Frame>], restored: Vec<Frame>) -> Vec<Arc<Frame>> {
+        for frame in restored {
+            self.resident.insert(frame.index(), (Arc::new(frame), 0));
+        }
+        let frames = frames
+            .iter()
+            .map(|frame| {
+                self.touch(frame.index())
+                    .unwrap_or_else(|| (*frame).clone())
+            })
+            .collect::<Vec<_>>();
+        let capacity = self.capacity.max(frames.len());
+        while self.order.len() > capacity {
+            let oldest = self.order.keys().next().cloned();
+            if let Some(index) = oldest.and_then(|generation| self.order.remove(&generation)) {
+                self.resident.remove(&index);
+            }
+        }
+        frames
+    }
+
+    /// Marks the restored frame as the most recently used one.
+    fn touch(&mut self, index: u32) -> Option<Arc<Frame>> {
+        let (frame, generation) = self.resident.get_mut(&index)?;
+        self.order.remove(generation);
+        self.generation += 1;
+        *generation = self.generation;
+        self.order.insert(self.generation, index);
+        Some(frame.clone())
+    }
+
+    /// Drops the restored frames in the range evicted from the store,
+    /// and deletes the segments holding no frame after it.
+    pub fn forget(&mut self, range: Range<u32>) {
+        let evicted = self
+            .resident
+            .iter()
+            .filter(|(index, _)| **index >= range.start && **index < range.end)
+            .map(|(index, (_, generation))| (*index, *generation))
+            .collect::<Vec<_>>();
+        for (index, generation) in evicted {
+            self.resident.remove(&index);
+            self.order.remove(&generation);
+        }
+        while self
+            .segments
+            .front()
+            .map_or(false, |segment| segment.end <= range.end)
+        {
+            self.segments.pop_front();
+        }
+    }
+}
+
+/// Restores copies of the spilled frames among `frames`.
+pub fn restore(spill: &Option<Arc<Mutex<Spill>>>, frames: &[&Frame]) -> io::Result<Vec<Frame>> {
+    let spilled = frames
+        .iter()
+        .filter(|f| f.is_spilled())
+        .cloned()
+        .collect::<Vec<_>>();
+    match spill {
+        Some(spill) if !spilled.is_empty() => spill.lock().read(&spilled),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Returns the decoded frames, restoring spilled frames into memory.
+///
+/// The most recently used restored frames are kept for later calls.
+pub fn get(spill: &Arc<Mutex<Spill>>, frames: &[&Arc<Frame>]) -> io::Result<Vec<Arc<Frame>>> {
+    let mut spill = spill.lock();
+    let restored = {
+        let missing = frames
+            .iter()
+            .filter(|f| f.is_spilled() && !spill.resident.contains_key(&f.index()))
+            .map(|f| &***f)
+            .collect::<Vec<_>>();
+        spill.read(&missing)?
+    };
+    Ok(spill.cache(frames, restored))
+}
+
+/// Replaces the spilled frames with the restored ones in the same order.
+pub fn merge<'a>(frames: Vec<&'a Frame>, restored: &'a [Frame]) -> Vec<&'a Frame> {
+    let mut restored = restored.iter().peekable();
+    frames
+        .into_iter()
+        .map(|frame| match restored.peek() {
+            Some(r) if r.index() == frame.index() => restored.next().unwrap(),
+            _ => frame,
+        })
+        .collect()
+}
+
+impl fmt::Debug for Spill {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let paths = self
+            .segments
+            .iter()
+            .map(|segment| &segment.path)
+            .collect::<Vec<_>>();
+        write!(f, "Spill {:?}", paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frame::Frame;
+    use genet_abi::{
+        fixed::{Fixed, MutFixed},
+        layer::{Layer, LayerClass},
+        slice::ByteSlice,
+        token::Token,
+    };
+    use spill::Spill;
+
+    #[test]
+    fn segments() {
+        let class = Fixed::new(LayerClass::builder(Token::from("[link-1]")).build());
+        let frames = (0..5)
+            .map(|index| {
+                let data = vec![index as u8; 10];
+                let root = Layer::new(class.clone(), ByteSlice::from(data));
+                Frame::new(index, None, MutFixed::new(root))
+            })
+            .collect::<Vec<_>>();
+
+        let mut spill = Spill::new(10).unwrap();
+        spill.segment_size = 20;
+        let spilled = frames
+            .iter()
+            .map(|frame| spill.page_out(frame).unwrap().unwrap())
+            .collect::<Vec<_>>();
+        assert!(spill.page_out(&spilled[0]).unwrap().is_none());
+        assert_eq!(spill.segments.len(), 3);
+        for (frame, spilled) in frames.iter().zip(spilled.iter()) {
+            assert_eq!(frame.layers().len(), 1);
+            assert!(spilled.is_spilled());
+            assert!(spilled.layers().is_empty());
+        }
+
+        let paths = spill
+            .segments
+            .iter()
+            .map(|segment| segment.path.clone())
+            .collect::<Vec<_>>();
+        spill.forget(0..3);
+        assert_eq!(spill.segments.len(), 2);
+        assert!(!paths[0].exists());
+        assert!(paths[1].exists());
+
+        assert!(spill.read(&[&spilled[1]]).is_err());
+        let restored = spill.read(&[&spilled[2], &spilled[4]]).unwrap();
+        assert_eq!(restored.len(), 2);
+        assert_eq!(restored[0].index(), 2);
+        assert_eq!(restored[0].layers()[0].data().to_vec(), vec![2u8; 10]);
+        assert_eq!(restored[1].layers()[0].data().to_vec(), vec![4u8; 10]);
+
+        spill.forget(3..5);
+        assert!(spill.segments.is_empty());
+        assert!(!paths[2].exists());
+    }
+}
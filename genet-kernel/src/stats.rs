@@ -0,0 +1,1053 @@
+use fnv::FnvHashMap;
+use frame::Frame;
+use genet_abi::{layer::Layer, token::Token, variant::Variant};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+/// Frame and byte counts of a layer id path such as `[link-1]/eth/ipv4/tcp`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    id: Token,
+    frames: u64,
+    bytes: u64,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn new(id: Token) -> Node {
+        Node {
+            id,
+            frames: 0,
+            bytes: 0,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn id(&self) -> Token {
+        self.id
+    }
+
+    /// Returns the number of frames containing the layer at this path.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Returns the total length of the layers at this path, including their payloads.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    pub fn children(&self) -> &[Node] {
+        &self.children
+    }
+
+    fn child(&mut self, id: Token) -> &mut Node {
+        let pos = match self.children.iter().position(|node| node.id == id) {
+            Some(pos) => pos,
+            None => {
+                self.children.push(Node::new(id));
+                self.children.len() - 1
+            }
+        };
+        &mut self.children[pos]
+    }
+}
+
+impl Serialize for Node {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_map(Some(4))?;
+        s.serialize_entry("id", &self.id.to_string())?;
+        s.serialize_entry("frames", &self.frames)?;
+        s.serialize_entry("bytes", &self.bytes)?;
+        s.serialize_entry("children", &self.children)?;
+        s.end()
+    }
+}
+
+/// Protocol hierarchy statistics updated incrementally as frames are added or removed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hierarchy {
+    frames: u64,
+    bytes: u64,
+    children: Vec<Node>,
+}
+
+impl Hierarchy {
+    pub fn new() -> Hierarchy {
+        Hierarchy::default()
+    }
+
+    /// Returns the number of frames counted.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Returns the total captured length of the frames counted.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns the nodes of the root layers.
+    pub fn children(&self) -> &[Node] {
+        &self.children
+    }
+
+    /// Finds the node of a path such as `[link-1]/eth/ipv4`.
+    pub fn get(&self, path: &str) -> Option<&Node> {
+        let mut children = &self.children;
+        let mut node = None;
+        for id in path.split('/') {
+            let id = Token::from(id);
+            let found = children.iter().find(|node| node.id == id)?;
+            children = &found.children;
+            node = Some(found);
+        }
+        node
+    }
+
+    pub fn add(&mut self, frame: &Frame) {
+        self.frames += 1;
+        self.bytes += frame.captured_length();
+        for (path, len) in paths(frame) {
+            let node = self.node(&path);
+            node.frames += 1;
+            node.bytes += len as u64;
+        }
+    }
+
+    /// Subtracts a frame counted by `add`.
+    ///
+    /// The frame must have the same layers as when it was added.
+    pub fn remove(&mut self, frame: &Frame) {
+        self.frames -= 1;
+        self.bytes -= frame.captured_length();
+        for (path, len) in paths(frame) {
+            let node = self.find(&path);
+            debug_assert!(node.is_some(), "layer not counted: {:?}", path);
+            if let Some(node) = node {
+                node.frames -= 1;
+                node.bytes -= len as u64;
+            }
+        }
+        prune(&mut self.children);
+    }
+
+    fn find(&mut self, path: &[Token]) -> Option<&mut Node> {
+        let (first, rest) = path.split_first()?;
+        let mut node = self.children.iter_mut().find(|node| node.id == *first)?;
+        for id in rest {
+            node = node.children.iter_mut().find(|node| node.id == *id)?;
+        }
+        Some(node)
+    }
+
+    fn node(&mut self, path: &[Token]) -> &mut Node {
+        let (first, rest) = path.split_first().unwrap();
+        let pos = match self.children.iter().position(|node| node.id == *first) {
+            Some(pos) => pos,
+            None => {
+                self.children.push(Node::new(*first));
+                self.children.len() - 1
+            }
+        };
+        rest.iter()
+            .fold(&mut self.children[pos], |node, id| node.child(*id))
+    }
+}
+
+impl Serialize for Hierarchy {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_map(Some(3))?;
+        s.serialize_entry("frames", &self.frames)?;
+        s.serialize_entry("bytes", &self.bytes)?;
+        s.serialize_entry("children", &self.children)?;
+        s.end()
+    }
+}
+
+/// Removes the nodes no longer counting any frame.
+fn prune(children: &mut Vec<Node>) {
+    children.retain(|node| node.frames > 0);
+    for node in children.iter_mut() {
+        prune(&mut node.children);
+    }
+}
+
+/// Returns the index of the parent of each layer in the frame.
+///
+/// The parents are taken from the tree indices, which list the number of
+/// children of each layer in breadth-first order. Layers not covered by
+/// the indices are regarded as children of the preceding layer.
+fn parents(frame: &Frame) -> Vec<Option<usize>> {
+    let len = frame.layers().len();
+    let mut parents = vec![None; len];
+    let mut next = 1;
+    for (index, children) in frame.tree_indices().iter().enumerate() {
+        for child in next..(next + *children as usize).min(len) {
+            parents[child] = Some(index);
+        }
+        next += *children as usize;
+    }
+    for (index, parent) in parents.iter_mut().enumerate().skip(next) {
+        *parent = Some(index - 1);
+    }
+    parents
+}
+
+/// Returns the layer id path and the length of each layer in the frame.
+fn paths(frame: &Frame) -> Vec<(Vec<Token>, usize)> {
+    let layers = frame.layers();
+    let parents = parents(frame);
+    let mut paths: Vec<(Vec<Token>, usize)> = Vec::with_capacity(layers.len());
+    for (index, layer) in layers.iter().enumerate() {
+        let mut path = parents[index]
+            .filter(|parent| *parent < index)
+            .map_or_else(Vec::new, |parent| paths[parent].0.clone());
+        path.push(layer.id());
+        paths.push((path, layer.data().len()));
+    }
+    paths
+}
+
+/// A component of an address.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Component {
+    Bytes(Box<[u8]>),
+    Number(u64),
+    String(Box<str>),
+}
+
+impl Component {
+    fn from_variant(value: Variant) -> Option<Component> {
+        match value {
+            Variant::Slice(v) => Some(Component::Bytes(v.to_vec().into_boxed_slice())),
+            Variant::Buffer(v) => Some(Component::Bytes(v)),
+            Variant::UInt64(v) => Some(Component::Number(v)),
+            Variant::Int64(v) => Some(Component::Number(v as u64)),
+            Variant::String(v) => Some(Component::String(v)),
+            _ => None,
+        }
+    }
+
+    fn is_number(&self) -> bool {
+        if let Component::Number(_) = self {
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Serialize for Component {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Component::Bytes(v) => v.serialize(serializer),
+            Component::Number(v) => v.serialize(serializer),
+            Component::String(v) => v.serialize(serializer),
+        }
+    }
+}
+
+/// An address taken from the `_.src` or `_.dst` alias of a layer.
+///
+/// Numeric addresses such as ports are qualified by the address
+/// of the enclosing layer, e.g. `[ipv4.src, tcp.src]`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Address(Vec<Component>);
+
+impl Address {
+    pub fn components(&self) -> &[Component] {
+        &self.0
+    }
+}
+
+impl Serialize for Address {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_seq(Some(self.0.len()))?;
+        for component in &self.0 {
+            s.serialize_element(component)?;
+        }
+        s.end()
+    }
+}
+
+/// Frame and byte counts in one direction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Traffic {
+    pub frames: u64,
+    pub bytes: u64,
+}
+
+impl Traffic {
+    fn add(&mut self, bytes: u64) {
+        self.frames += 1;
+        self.bytes += bytes;
+    }
+
+    fn remove(&mut self, bytes: u64) {
+        self.frames -= 1;
+        self.bytes -= bytes;
+    }
+}
+
+/// Traffic between a pair of addresses at a layer.
+///
+/// `a` is the source address of the first frame seen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conversation {
+    pub layer: Token,
+    pub a: Address,
+    pub b: Address,
+    pub a_to_b: Traffic,
+    pub b_to_a: Traffic,
+    pub first: f64,
+    pub last: f64,
+}
+
+impl Conversation {
+    /// Returns the frame and byte counts in both directions.
+    pub fn total(&self) -> Traffic {
+        Traffic {
+            frames: self.a_to_b.frames + self.b_to_a.frames,
+            bytes: self.a_to_b.bytes + self.b_to_a.bytes,
+        }
+    }
+}
+
+impl Serialize for Conversation {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let total = self.total();
+        let mut s = serializer.serialize_map(Some(9))?;
+        s.serialize_entry("layer", &self.layer.to_string())?;
+        s.serialize_entry("a", &self.a)?;
+        s.serialize_entry("b", &self.b)?;
+        s.serialize_entry("frames", &total.frames)?;
+        s.serialize_entry("bytes", &total.bytes)?;
+        s.serialize_entry("aToB", &self.a_to_b)?;
+        s.serialize_entry("bToA", &self.b_to_a)?;
+        s.serialize_entry("first", &self.first)?;
+        s.serialize_entry("last", &self.last)?;
+        s.end()
+    }
+}
+
+/// Traffic sent and received by an address at a layer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    pub layer: Token,
+    pub address: Address,
+    pub tx: Traffic,
+    pub rx: Traffic,
+    pub first: f64,
+    pub last: f64,
+}
+
+impl Serialize for Endpoint {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_map(Some(6))?;
+        s.serialize_entry("layer", &self.layer.to_string())?;
+        s.serialize_entry("address", &self.address)?;
+        s.serialize_entry("tx", &self.tx)?;
+        s.serialize_entry("rx", &self.rx)?;
+        s.serialize_entry("first", &self.first)?;
+        s.serialize_entry("last", &self.last)?;
+        s.end()
+    }
+}
+
+/// Conversation and endpoint tables of the layers with `_.src` and `_.dst` aliases.
+///
+/// Byte counts are the captured lengths of the frames. Removing frames
+/// updates the counts but not the timestamps.
+#[derive(Debug, Clone, Default)]
+pub struct Conversations {
+    conversations: FnvHashMap<(Token, Address, Address), Conversation>,
+    endpoints: FnvHashMap<(Token, Address), Endpoint>,
+}
+
+impl Conversations {
+    pub fn new() -> Conversations {
+        Conversations::default()
+    }
+
+    pub fn conversations(&self) -> impl Iterator<Item = &Conversation> {
+        self.conversations.values()
+    }
+
+    pub fn endpoints(&self) -> impl Iterator<Item = &Endpoint> {
+        self.endpoints.values()
+    }
+
+    /// Finds the conversation between the addresses in either direction.
+    pub fn conversation(&self, layer: Token, a: &Address, b: &Address) -> Option<&Conversation> {
+        self.conversations
+            .get(&(layer, a.clone(), b.clone()))
+            .or_else(|| self.conversations.get(&(layer, b.clone(), a.clone())))
+    }
+
+    pub fn endpoint(&self, layer: Token, address: &Address) -> Option<&Endpoint> {
+        self.endpoints.get(&(layer, address.clone()))
+    }
+
+    pub fn add(&mut self, frame: &Frame) {
+        let bytes = frame.captured_length();
+        let ts = frame.timestamp();
+        for (layer, src, dst) in addresses(frame) {
+            let forward = (layer, src.clone(), dst.clone());
+            let reverse = (layer, dst.clone(), src.clone());
+            if let Some(conv) = self.conversations.get_mut(&forward) {
+                conv.a_to_b.add(bytes);
+                conv.last = conv.last.max(ts);
+            } else if let Some(conv) = self.conversations.get_mut(&reverse) {
+                conv.b_to_a.add(bytes);
+                conv.last = conv.last.max(ts);
+            } else {
+                let mut a_to_b = Traffic::default();
+                a_to_b.add(bytes);
+                self.conversations.insert(
+                    forward,
+                    Conversation {
+                        layer,
+                        a: src.clone(),
+                        b: dst.clone(),
+                        a_to_b,
+                        b_to_a: Traffic::default(),
+                        first: ts,
+                        last: ts,
+                    },
+                );
+            }
+            self.endpoint_mut(layer, src, ts).tx.add(bytes);
+            self.endpoint_mut(layer, dst, ts).rx.add(bytes);
+        }
+    }
+
+    /// Subtracts a frame counted by `add`.
+    ///
+    /// The frame must have the same addresses as when it was added.
+    pub fn remove(&mut self, frame: &Frame) {
+        let bytes = frame.captured_length();
+        for (layer, src, dst) in addresses(frame) {
+            let forward = (layer, src.clone(), dst.clone());
+            let reverse = (layer, dst.clone(), src.clone());
+            if let Some(conv) = self.conversations.get_mut(&forward) {
+                conv.a_to_b.remove(bytes);
+            } else if let Some(conv) = self.conversations.get_mut(&reverse) {
+                conv.b_to_a.remove(bytes);
+            } else {
+                debug_assert!(false, "conversation not counted: {:?}", forward);
+            }
+            let src = self.endpoints.get_mut(&(layer, src));
+            debug_assert!(src.is_some(), "endpoint not counted");
+            if let Some(endpoint) = src {
+                endpoint.tx.remove(bytes);
+            }
+            let dst = self.endpoints.get_mut(&(layer, dst));
+            debug_assert!(dst.is_some(), "endpoint not counted");
+            if let Some(endpoint) = dst {
+                endpoint.rx.remove(bytes);
+            }
+        }
+        self.conversations.retain(|_, conv| conv.total().frames > 0);
+        self.endpoints
+            .retain(|_, endpoint| endpoint.tx.frames + endpoint.rx.frames > 0);
+    }
+
+    fn endpoint_mut(&mut self, layer: Token, address: Address, ts: f64) -> &mut Endpoint {
+        let endpoint = self
+            .endpoints
+            .entry((layer, address.clone()))
+            .or_insert_with(|| Endpoint {
+                layer,
+                address,
+                tx: Traffic::default(),
+                rx: Traffic::default(),
+                first: ts,
+                last: ts,
+            });
+        endpoint.first = endpoint.first.min(ts);
+        endpoint.last = endpoint.last.max(ts);
+        endpoint
+    }
+}
+
+impl Serialize for Conversations {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let conversations = self.conversations().collect::<Vec<_>>();
+        let endpoints = self.endpoints().collect::<Vec<_>>();
+        let mut s = serializer.serialize_map(Some(2))?;
+        s.serialize_entry("conversations", &conversations)?;
+        s.serialize_entry("endpoints", &endpoints)?;
+        s.end()
+    }
+}
+
+/// Returns the source and destination addresses of each layer in the frame
+/// with the `_.src` and `_.dst` aliases.
+fn addresses(frame: &Frame) -> Vec<(Token, Address, Address)> {
+    let layers = frame.layers();
+    let parents = parents(frame);
+    let mut addresses: Vec<Option<(Address, Address)>> = Vec::with_capacity(layers.len());
+    let mut result = Vec::new();
+    for (index, layer) in layers.iter().enumerate() {
+        let pair = match (component(layer, "_.src"), component(layer, "_.dst")) {
+            (Some(src), Some(dst)) => {
+                let enclosing = if src.is_number() {
+                    let mut parent = parents[index].filter(|parent| *parent < index);
+                    while let Some(p) = parent {
+                        if addresses[p].is_some() {
+                            break;
+                        }
+                        parent = parents[p].filter(|parent| *parent < p);
+                    }
+                    parent.and_then(|p| addresses[p].clone())
+                } else {
+                    None
+                };
+                let (mut src_addr, mut dst_addr) =
+                    enclosing.unwrap_or_else(|| (Address(Vec::new()), Address(Vec::new())));
+                src_addr.0.push(src);
+                dst_addr.0.push(dst);
+                Some((src_addr, dst_addr))
+            }
+            _ => None,
+        };
+        if let Some((src, dst)) = &pair {
+            result.push((layer.id(), src.clone(), dst.clone()));
+        }
+        addresses.push(pair);
+    }
+    result
+}
+
+fn component(layer: &Layer, id: &str) -> Option<Component> {
+    layer
+        .attr(Token::from(id))
+        .and_then(|attr| attr.try_get(layer).ok())
+        .and_then(Component::from_variant)
+}
+
+/// The value plotted by an I/O graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Metric {
+    Frames,
+    Bytes,
+    Sum(Token),
+    Avg(Token),
+    Max(Token),
+}
+
+impl Metric {
+    fn attr(self) -> Option<Token> {
+        match self {
+            Metric::Sum(id) | Metric::Avg(id) | Metric::Max(id) => Some(id),
+            _ => None,
+        }
+    }
+}
+
+/// Formats the metric as `frames`, `bytes`, or `sum:`, `avg:` or `max:`
+/// followed by an attribute id.
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Metric::Frames => write!(f, "frames"),
+            Metric::Bytes => write!(f, "bytes"),
+            Metric::Sum(id) => write!(f, "sum:{}", id),
+            Metric::Avg(id) => write!(f, "avg:{}", id),
+            Metric::Max(id) => write!(f, "max:{}", id),
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Metric, String> {
+        let mut parts = s.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("frames"), None) => Ok(Metric::Frames),
+            (Some("bytes"), None) => Ok(Metric::Bytes),
+            (Some("sum"), Some(id)) if !id.is_empty() => Ok(Metric::Sum(Token::from(id))),
+            (Some("avg"), Some(id)) if !id.is_empty() => Ok(Metric::Avg(Token::from(id))),
+            (Some("max"), Some(id)) if !id.is_empty() => Ok(Metric::Max(Token::from(id))),
+            _ => Err(format!("invalid metric: {}", s)),
+        }
+    }
+}
+
+/// A time slot of an I/O graph.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Bucket {
+    /// The start of the slot in seconds since the Unix epoch.
+    pub time: f64,
+    pub frames: u64,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Accumulator {
+    frames: u64,
+    bytes: u64,
+    count: u64,
+    sum: f64,
+    max: Option<f64>,
+}
+
+/// Frame counts, byte counts or attribute values bucketed by the `link.timestamp`
+/// of the frames.
+///
+/// Empty buckets are omitted. Removing frames updates the buckets
+/// except for the maximum values.
+#[derive(Debug, Clone)]
+pub struct IoGraph {
+    interval: f64,
+    metric: Metric,
+    buckets: BTreeMap<i64, Accumulator>,
+}
+
+impl IoGraph {
+    /// Creates a graph with buckets of `interval` seconds.
+    pub fn new(interval: f64, metric: Metric) -> IoGraph {
+        IoGraph {
+            interval,
+            metric,
+            buckets: BTreeMap::new(),
+        }
+    }
+
+    pub fn interval(&self) -> f64 {
+        self.interval
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    pub fn buckets(&self) -> Vec<Bucket> {
+        self.buckets
+            .iter()
+            .map(|(slot, acc)| Bucket {
+                time: *slot as f64 * self.interval,
+                frames: acc.frames,
+                value: match self.metric {
+                    Metric::Frames => acc.frames as f64,
+                    Metric::Bytes => acc.bytes as f64,
+                    Metric::Sum(_) => acc.sum,
+                    Metric::Avg(_) if acc.count > 0 => acc.sum / acc.count as f64,
+                    Metric::Avg(_) => 0.0,
+                    Metric::Max(_) => acc.max.unwrap_or(0.0),
+                },
+            })
+            .collect()
+    }
+
+    pub fn add(&mut self, frame: &Frame) {
+        let value = self.metric.attr().and_then(|id| number(frame, id));
+        let acc = self.buckets.entry(self.slot(frame)).or_default();
+        acc.frames += 1;
+        acc.bytes += frame.captured_length();
+        if let Some(value) = value {
+            acc.count += 1;
+            acc.sum += value;
+            acc.max = Some(acc.max.map_or(value, |max| max.max(value)));
+        }
+    }
+
+    pub fn remove(&mut self, frame: &Frame) {
+        let value = self.metric.attr().and_then(|id| number(frame, id));
+        let slot = self.slot(frame);
+        let empty = if let Some(acc) = self.buckets.get_mut(&slot) {
+            acc.frames -= 1;
+            acc.bytes -= frame.captured_length();
+            if let Some(value) = value {
+                acc.count -= 1;
+                acc.sum -= value;
+            }
+            acc.frames == 0
+        } else {
+            debug_assert!(false, "frame not counted: {}", frame.index());
+            false
+        };
+        if empty {
+            self.buckets.remove(&slot);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.buckets.clear();
+    }
+
+    fn slot(&self, frame: &Frame) -> i64 {
+        (frame.timestamp() / self.interval).floor() as i64
+    }
+}
+
+/// Returns the numeric value of the attribute in the innermost layer having it.
+fn number(frame: &Frame, id: Token) -> Option<f64> {
+    frame
+        .layers()
+        .iter()
+        .rev()
+        .filter_map(|layer| layer.attr(id).map(|attr| attr.try_get(layer)))
+        .next()
+        .and_then(|value| match value {
+            Ok(Variant::UInt64(v)) => Some(v as f64),
+            Ok(Variant::Int64(v)) => Some(v as f64),
+            Ok(Variant::Float64(v)) => Some(v),
+            Ok(Variant::Bool(v)) => Some(if v { 1.0 } else { 0.0 }),
+            _ => None,
+        })
+}
+
+/// Statistics of a view, updated as frames are added or removed.
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    pub hierarchy: Hierarchy,
+    pub conversations: Conversations,
+    graphs: Vec<IoGraph>,
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats::default()
+    }
+
+    /// Returns the I/O graph with the interval and the metric, if tracked.
+    pub fn io_graph(&self, interval: f64, metric: Metric) -> Option<&IoGraph> {
+        self.graphs
+            .iter()
+            .find(|graph| graph.interval == interval && graph.metric == metric)
+    }
+
+    pub fn io_graphs(&self) -> &[IoGraph] {
+        &self.graphs
+    }
+
+    /// Starts tracking an I/O graph unless the same one is already tracked.
+    pub fn add_io_graph(&mut self, graph: IoGraph) -> bool {
+        if self.io_graph(graph.interval, graph.metric).is_some() {
+            false
+        } else {
+            self.graphs.push(graph);
+            true
+        }
+    }
+
+    /// Stops tracking the I/O graph, returning false if it is not tracked.
+    pub fn remove_io_graph(&mut self, interval: f64, metric: Metric) -> bool {
+        let len = self.graphs.len();
+        self.graphs
+            .retain(|graph| graph.interval != interval || graph.metric != metric);
+        self.graphs.len() < len
+    }
+
+    pub fn add(&mut self, frame: &Frame) {
+        self.hierarchy.add(frame);
+        self.conversations.add(frame);
+        for graph in &mut self.graphs {
+            graph.add(frame);
+        }
+    }
+
+    pub fn remove(&mut self, frame: &Frame) {
+        self.hierarchy.remove(frame);
+        self.conversations.remove(frame);
+        for graph in &mut self.graphs {
+            graph.remove(frame);
+        }
+    }
+
+    /// Clears the counts, keeping the I/O graphs tracked.
+    pub fn reset(&mut self) {
+        self.hierarchy = Hierarchy::new();
+        self.conversations = Conversations::new();
+        for graph in &mut self.graphs {
+            graph.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frame::Frame;
+    use genet_abi::{
+        attr::{Attr, AttrClass},
+        fixed::{Fixed, MutFixed},
+        layer::{Layer, LayerClass},
+        slice::ByteSlice,
+        token::Token,
+        variant::Variant,
+    };
+    use serde_json;
+    use stats::{Address, Bucket, Component, Conversations, Hierarchy, IoGraph, Metric, Traffic};
+
+    fn layer(id: &str, len: usize, attrs: Vec<(&str, Variant)>) -> MutFixed<Layer> {
+        let class = LayerClass::builder(Token::from(id))
+            .alias("_.src", format!("{}.src", id))
+            .alias("_.dst", format!("{}.dst", id))
+            .build();
+        let mut layer = Layer::new(Fixed::new(class), ByteSlice::from(vec![0u8; len]));
+        for (id, value) in attrs {
+            let class = Fixed::new(AttrClass::builder(Token::from(id)).build());
+            layer.add_attr(Attr::builder(class).value(value).build());
+        }
+        MutFixed::new(layer)
+    }
+
+    fn frame(index: u32, path: &[(&str, usize)]) -> Frame {
+        chain(
+            index,
+            path.iter()
+                .map(|(id, len)| layer(id, *len, Vec::new()))
+                .collect(),
+        )
+    }
+
+    fn chain(index: u32, mut layers: Vec<MutFixed<Layer>>) -> Frame {
+        let mut frame = Frame::new(index, None, layers.remove(0));
+        let mut indices = vec![1; layers.len()];
+        indices.push(0);
+        let mut all = frame.fetch_layers();
+        all.append(&mut layers);
+        frame.set_layers(all);
+        frame.set_tree_indices(indices);
+        frame
+    }
+
+    #[test]
+    fn hierarchy() {
+        let tcp = frame(
+            0,
+            &[("[link-1]", 100), ("eth", 100), ("ipv4", 86), ("tcp", 66)],
+        );
+        let udp = frame(
+            1,
+            &[("[link-1]", 60), ("eth", 60), ("ipv4", 46), ("udp", 26)],
+        );
+        let mut stats = Hierarchy::new();
+        stats.add(&tcp);
+        stats.add(&udp);
+        assert_eq!(stats.frames(), 2);
+        assert_eq!(stats.bytes(), 160);
+
+        let ipv4 = stats.get("[link-1]/eth/ipv4").unwrap();
+        assert_eq!((ipv4.frames(), ipv4.bytes()), (2, 132));
+        assert_eq!(ipv4.children().len(), 2);
+        let tcp_node = stats.get("[link-1]/eth/ipv4/tcp").unwrap();
+        assert_eq!((tcp_node.frames(), tcp_node.bytes()), (1, 66));
+        assert!(stats.get("[link-1]/ipv4").is_none());
+
+        stats.remove(&tcp);
+        assert!(stats.get("[link-1]/eth/ipv4/tcp").is_none());
+        assert_eq!(
+            serde_json::to_string(&stats).unwrap(),
+            "{\"frames\":1,\"bytes\":60,\"children\":[\
+             {\"id\":\"[link-1]\",\"frames\":1,\"bytes\":60,\"children\":[\
+             {\"id\":\"eth\",\"frames\":1,\"bytes\":60,\"children\":[\
+             {\"id\":\"ipv4\",\"frames\":1,\"bytes\":46,\"children\":[\
+             {\"id\":\"udp\",\"frames\":1,\"bytes\":26,\"children\":[]}]}]}]}]}"
+        );
+    }
+
+    fn tcp(index: u32, ts: f64, len: usize, src: (u8, u64), dst: (u8, u64)) -> Frame {
+        let ip = |n: u8| Variant::Buffer(vec![10, 0, 0, n].into_boxed_slice());
+        chain(
+            index,
+            vec![
+                layer(
+                    "[link-1]",
+                    len,
+                    vec![("link.timestamp", Variant::Float64(ts))],
+                ),
+                layer(
+                    "ipv4",
+                    len,
+                    vec![("ipv4.src", ip(src.0)), ("ipv4.dst", ip(dst.0))],
+                ),
+                layer(
+                    "tcp",
+                    len - 20,
+                    vec![
+                        ("tcp.src", Variant::UInt64(src.1)),
+                        ("tcp.dst", Variant::UInt64(dst.1)),
+                    ],
+                ),
+            ],
+        )
+    }
+
+    fn address(components: &[Component]) -> Address {
+        Address(components.to_vec())
+    }
+
+    #[test]
+    fn conversations() {
+        let ipv4 = Token::from("ipv4");
+        let ip = |n: u8| Component::Bytes(vec![10, 0, 0, n].into_boxed_slice());
+        let frames = vec![
+            tcp(0, 1.0, 100, (1, 1000), (2, 80)),
+            tcp(1, 2.0, 60, (2, 80), (1, 1000)),
+            tcp(2, 3.0, 40, (1, 1001), (3, 80)),
+        ];
+        let mut stats = Conversations::new();
+        frames.iter().for_each(|f| stats.add(f));
+        assert_eq!(stats.conversations().count(), 4);
+        assert_eq!(stats.endpoints().count(), 7);
+
+        let conv = stats
+            .conversation(ipv4, &address(&[ip(2)]), &address(&[ip(1)]))
+            .unwrap();
+        assert_eq!(conv.a, address(&[ip(1)]));
+        assert_eq!(
+            conv.a_to_b,
+            Traffic {
+                frames: 1,
+                bytes: 100
+            }
+        );
+        assert_eq!(
+            conv.b_to_a,
+            Traffic {
+                frames: 1,
+                bytes: 60
+            }
+        );
+        assert_eq!((conv.first, conv.last), (1.0, 2.0));
+
+        let conv = stats
+            .conversation(
+                Token::from("tcp"),
+                &address(&[ip(1), Component::Number(1001)]),
+                &address(&[ip(3), Component::Number(80)]),
+            )
+            .unwrap();
+        assert_eq!(
+            conv.total(),
+            Traffic {
+                frames: 1,
+                bytes: 40
+            }
+        );
+
+        let endpoint = stats.endpoint(ipv4, &address(&[ip(1)])).unwrap();
+        assert_eq!(
+            endpoint.tx,
+            Traffic {
+                frames: 2,
+                bytes: 140
+            }
+        );
+        assert_eq!(
+            endpoint.rx,
+            Traffic {
+                frames: 1,
+                bytes: 60
+            }
+        );
+        assert_eq!((endpoint.first, endpoint.last), (1.0, 3.0));
+
+        stats.remove(&frames[2]);
+        assert_eq!(stats.conversations().count(), 2);
+        assert!(stats.endpoint(ipv4, &address(&[ip(3)])).is_none());
+        let endpoint = stats.endpoint(ipv4, &address(&[ip(1)])).unwrap();
+        assert_eq!(
+            endpoint.tx,
+            Traffic {
+                frames: 1,
+                bytes: 100
+            }
+        );
+
+        let json = serde_json::to_value(&stats).unwrap();
+        let conv = json["conversations"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|conv| conv["layer"] == "ipv4")
+            .unwrap();
+        assert_eq!(conv["a"], serde_json::json!([[10, 0, 0, 1]]));
+        assert_eq!(conv["frames"], 2);
+        assert_eq!(conv["bToA"], serde_json::json!({"frames": 1, "bytes": 60}));
+    }
+
+    #[test]
+    fn io_graph() {
+        let frames = vec![
+            tcp(0, 1.5, 100, (1, 1000), (2, 80)),
+            tcp(1, 2.0, 60, (2, 80), (1, 1000)),
+            tcp(2, 3.5, 40, (1, 1001), (3, 80)),
+        ];
+        let bucket = |time, frames, value| Bucket {
+            time,
+            frames,
+            value,
+        };
+        let graph = |metric: &str| {
+            let mut graph = IoGraph::new(2.0, metric.parse().unwrap());
+            frames.iter().for_each(|f| graph.add(f));
+            graph
+        };
+        assert_eq!(
+            graph("frames").buckets(),
+            vec![bucket(0.0, 1, 1.0), bucket(2.0, 2, 2.0)]
+        );
+        assert_eq!(
+            graph("bytes").buckets(),
+            vec![bucket(0.0, 1, 100.0), bucket(2.0, 2, 100.0)]
+        );
+        assert_eq!(
+            graph("sum:tcp.src").buckets(),
+            vec![bucket(0.0, 1, 1000.0), bucket(2.0, 2, 1081.0)]
+        );
+        assert_eq!(
+            graph("avg:tcp.src").buckets(),
+            vec![bucket(0.0, 1, 1000.0), bucket(2.0, 2, 540.5)]
+        );
+        assert_eq!(
+            graph("max:tcp.src").buckets(),
+            vec![bucket(0.0, 1, 1000.0), bucket(2.0, 2, 1001.0)]
+        );
+        assert_eq!(
+            graph("avg:udp.src").buckets(),
+            vec![bucket(0.0, 1, 0.0), bucket(2.0, 2, 0.0)]
+        );
+
+        let mut sum = graph("sum:tcp.src");
+        sum.remove(&frames[0]);
+        sum.remove(&frames[2]);
+        assert_eq!(sum.buckets(), vec![bucket(2.0, 1, 80.0)]);
+
+        assert_eq!(
+            "max:tcp.window".parse::<Metric>(),
+            Ok(Metric::Max(Token::from("tcp.window")))
+        );
+        assert_eq!(
+            Metric::Avg(Token::from("ipv4.len")).to_string(),
+            "avg:ipv4.len"
+        );
+        assert!("sum:".parse::<Metric>().is_err());
+        assert!("frames:tcp".parse::<Metric>().is_err());
+    }
+}
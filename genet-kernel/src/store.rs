@@ -1,29 +1,46 @@
 use array_vec::ArrayVec;
 use crossbeam_channel;
 use decoder::{parallel, serial};
-use fnv::FnvHashMap;
+use filter;
+use fnv::{FnvHashMap, FnvHashSet};
 use frame::Frame;
-use genet_abi::{fixed::MutFixed, layer::Layer};
-use genet_filter::{self, Filter};
+use genet_abi::{fixed::MutFixed, layer::Layer, token::Token, variant::Variant};
+use genet_filter::{self, ast::Expr, context::Field, variant::VariantExt, Filter};
 use io::{Input, Output};
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use profile::Profile;
 use result::Result;
+use spill::{self, Spill};
+use stats::{Bucket, Conversations, Hierarchy, IoGraph, Metric, Stats};
 use std::{
-    fmt,
+    cmp::Ordering,
+    collections::VecDeque,
+    fmt, mem,
     ops::Range,
     panic::{self, AssertUnwindSafe},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering as AtomicOrdering},
+        Arc,
+    },
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
 const OUTPUT_BLOCK_SIZE: usize = 65536;
 const MAX_FILTER_SIZE: usize = 16384;
+const MAX_FILTER_JOBS: usize = 8;
+const MERGE_GRACE_PERIOD_MS: u64 = 500;
+const OUTPUT_HOLD_LIMIT_RATIO: usize = 2;
 
 pub trait Callback: Send {
     fn on_frames_updated(&self, _frames: u32) {}
     fn on_async_frames_updated(&self, _frames: u32) {}
     fn on_filtered_frames_updated(&self, _id: u32, _frames: u32) {}
+    fn on_sorted_frames_updated(&self, _id: u32, _frames: u32) {}
+    fn on_filter_progress(&self, _id: u32, _processed: u32, _total: u32) {}
+    fn on_frames_evicted(&self, _range: Range<u32>) {}
+    fn on_io_graph_updated(&self, _id: Option<u32>, _interval: f64, _metric: Metric) {}
+    fn on_output_progress(&self, _id: u32, _processed: u32, _total: u32) {}
     fn on_output_done(&self, _id: u32, _error: Option<Box<::std::error::Error + Send>>) {}
     fn on_input_done(&self, _id: u32, _error: Option<Box<::std::error::Error + Send>>) {}
     fn on_error(&self, _error: Box<::std::error::Error + Send>) {}
@@ -32,13 +49,76 @@ pub trait Callback: Send {
 #[derive(Debug)]
 enum Command {
     PushFrames(Option<u32>, Result<Vec<MutFixed<Layer>>>),
+    OpenInput(u32),
+    CloseInput(u32),
+    RemoveInput(u32),
     PushSerialFrames(Vec<Frame>),
     StoreFrames(Vec<Frame>),
     SetFilter(u32, Option<Filter>),
-    PushOutput(u32, Box<Output>, Option<Filter>),
+    FilterDone(filter::Output),
+    SetSort(u32, Option<(Token, Order)>),
+    AddIoGraph(Option<u32>, IoGraph),
+    RemoveIoGraph(Option<u32>, f64, Metric),
+    PushOutput(u32, Box<Output>, Selection, bool),
+    CloseOutput(u32),
     Close,
 }
 
+/// The frames written to an output.
+#[derive(Debug)]
+pub enum Selection {
+    /// All frames, or the frames matching the filter.
+    Filter(Option<Filter>),
+    /// The frames in the index ranges.
+    Ranges(Vec<Range<u32>>),
+    /// The frames of the filtered view, without evaluating the filter again.
+    View(u32),
+}
+
+impl Selection {
+    /// Returns the indices of the selected frames in the range.
+    ///
+    /// Frames not matching a filter are excluded by `test`.
+    fn indices(&self, range: Range<usize>, filtered: &FnvHashMap<u32, Vec<u32>>) -> Vec<usize> {
+        match self {
+            Selection::Filter(_) => range.collect(),
+            Selection::Ranges(ranges) => range
+                .filter(|index| {
+                    ranges
+                        .iter()
+                        .any(|r| r.start as usize <= *index && *index < r.end as usize)
+                })
+                .collect(),
+            Selection::View(id) => filtered.get(id).map_or_else(Vec::new, |vec| {
+                let pos = |index: usize| match vec.binary_search(&(index as u32)) {
+                    Ok(pos) | Err(pos) => pos,
+                };
+                vec[pos(range.start)..pos(range.end)]
+                    .iter()
+                    .map(|index| *index as usize)
+                    .collect()
+            }),
+        }
+    }
+
+    fn test(&self, frame: &Frame) -> bool {
+        if let Selection::Filter(Some(filter)) = self {
+            let ctx =
+                genet_filter::context::Context::with_metadata(frame.layers(), frame.metadata());
+            filter.test(&ctx)
+        } else {
+            true
+        }
+    }
+}
+
+/// The order of a sorted view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Ascending,
+    Descending,
+}
+
 #[derive(Debug)]
 struct Error(String);
 
@@ -54,8 +134,12 @@ impl fmt::Display for Error {
     }
 }
 
-type FrameStore = Arc<RwLock<ArrayVec<Frame>>>;
+type FrameStore = Arc<RwLock<ArrayVec<Arc<Frame>>>>;
 type FilteredFrameStore = Arc<RwLock<FnvHashMap<u32, Vec<u32>>>>;
+type SortedFrameStore = Arc<RwLock<FnvHashMap<u32, SortedFrames>>>;
+type InputFrameStore = Arc<RwLock<FnvHashMap<u32, Vec<u32>>>>;
+type StatsStore = Arc<RwLock<FnvHashMap<Option<u32>, Stats>>>;
+type SpillStore = Option<Arc<Mutex<Spill>>>;
 
 #[derive(Debug)]
 pub struct Store {
@@ -63,6 +147,10 @@ pub struct Store {
     ev: EventLoop,
     frames: FrameStore,
     filtered: FilteredFrameStore,
+    sorted: SortedFrameStore,
+    input_frames: InputFrameStore,
+    stats: StatsStore,
+    spill: SpillStore,
     inputs: FnvHashMap<u32, InputContext>,
     inputs_trash: Vec<InputContext>,
 }
@@ -71,25 +159,60 @@ impl Store {
     pub fn new<C: 'static + Callback + Clone>(profile: Profile, callback: C) -> Store {
         let frames = Arc::new(RwLock::new(ArrayVec::new()));
         let filtered = Arc::new(RwLock::new(FnvHashMap::default()));
-        let (ev, send) = EventLoop::new(profile, callback, frames.clone(), filtered.clone());
+        let sorted = Arc::new(RwLock::new(FnvHashMap::default()));
+        let input_frames = Arc::new(RwLock::new(FnvHashMap::default()));
+        let mut stats = FnvHashMap::default();
+        stats.insert(None, Stats::new());
+        let stats = Arc::new(RwLock::new(stats));
+        let spill = profile
+            .max_resident_frames()
+            .and_then(|capacity| match Spill::new(capacity) {
+                Ok(spill) => Some(Arc::new(Mutex::new(spill))),
+                Err(err) => {
+                    callback.on_error(Box::new(Error(err.to_string())));
+                    None
+                }
+            });
+        let (ev, send) = EventLoop::new(
+            profile,
+            callback,
+            frames.clone(),
+            filtered.clone(),
+            sorted.clone(),
+            input_frames.clone(),
+            stats.clone(),
+            spill.clone(),
+        );
         Store {
             sender: send,
             ev,
             frames,
             filtered,
+            sorted,
+            input_frames,
+            stats,
+            spill,
             inputs: FnvHashMap::default(),
             inputs_trash: Vec::new(),
         }
     }
 
-    pub fn frames(&self, range: Range<usize>) -> Vec<*const Frame> {
-        self.frames
-            .read()
-            .iter()
-            .skip(range.start)
-            .take(range.end.saturating_sub(range.start))
-            .map(|f| f as *const Frame)
-            .collect::<Vec<_>>()
+    /// Returns the frames in the range, restoring spilled frames.
+    ///
+    /// The frames are shared, and stay valid after they are evicted.
+    /// Removed frames are returned as well, so that each frame keeps
+    /// its position; see `Frame::is_removed`.
+    pub fn frames(&self, range: Range<usize>) -> Vec<Arc<Frame>> {
+        let frames = self.frames.read();
+        let frames = (range.start.max(frames.start())..range.end.min(frames.len()))
+            .filter_map(|index| frames.get(index))
+            .collect::<Vec<_>>();
+        if let Some(spill) = &self.spill {
+            if let Ok(frames) = spill::get(spill, &frames) {
+                return frames;
+            }
+        }
+        frames.into_iter().cloned().collect::<Vec<_>>()
     }
 
     pub fn filtered_frames(&self, id: u32, range: Range<usize>) -> Vec<u32> {
@@ -105,21 +228,136 @@ impl Store {
         }
     }
 
+    /// Returns the indices of the frames read from the input `id` in the range.
+    pub fn frames_for_input(&self, id: u32, range: Range<usize>) -> Vec<u32> {
+        let input_frames = self.input_frames.read();
+        if let Some(vec) = input_frames.get(&id) {
+            vec.iter()
+                .skip(range.start)
+                .take(range.end.saturating_sub(range.start))
+                .cloned()
+                .collect::<Vec<_>>()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Returns the frame indices of the sorted view in the range.
+    pub fn sorted_frames(&self, id: u32, range: Range<usize>) -> Vec<u32> {
+        let mut sorted = self.sorted.write();
+        if let Some(sorted) = sorted.get_mut(&id) {
+            sorted.merge();
+            sorted
+                .keys
+                .iter()
+                .skip(range.start)
+                .take(range.end.saturating_sub(range.start))
+                .map(|(_, index)| *index)
+                .collect::<Vec<_>>()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Returns the protocol hierarchy of all frames, or of the frames matching
+    /// the filter `id`.
+    pub fn hierarchy(&self, id: Option<u32>) -> Option<Hierarchy> {
+        self.stats
+            .read()
+            .get(&id)
+            .map(|stats| stats.hierarchy.clone())
+    }
+
+    /// Returns the conversation and endpoint tables of all frames, or of the frames
+    /// matching the filter `id`.
+    pub fn conversations(&self, id: Option<u32>) -> Option<Conversations> {
+        self.stats
+            .read()
+            .get(&id)
+            .map(|stats| stats.conversations.clone())
+    }
+
+    /// Returns the I/O graph of all frames, or of the frames matching the filter `id`.
+    ///
+    /// Returns no buckets unless the graph is tracked by `add_io_graph`.
+    pub fn io_graph(&self, id: Option<u32>, interval: f64, metric: Metric) -> Vec<Bucket> {
+        self.stats
+            .read()
+            .get(&id)
+            .and_then(|stats| stats.io_graph(interval, metric))
+            .map_or_else(Vec::new, IoGraph::buckets)
+    }
+
+    /// Starts tracking the I/O graph, counting the frames already in the view.
+    ///
+    /// `Callback::on_io_graph_updated` is called as its buckets change,
+    /// until `remove_io_graph` is called.
+    pub fn add_io_graph(&mut self, id: Option<u32>, interval: f64, metric: Metric) {
+        if interval > 0.0 {
+            self.sender
+                .send(Command::AddIoGraph(id, IoGraph::new(interval, metric)));
+        }
+    }
+
+    /// Stops tracking the I/O graph.
+    pub fn remove_io_graph(&mut self, id: Option<u32>, interval: f64, metric: Metric) {
+        self.sender
+            .send(Command::RemoveIoGraph(id, interval, metric));
+    }
+
     pub fn len(&self) -> usize {
         let frames = self.frames.read();
         frames.len()
     }
 
+    /// Returns the index of the oldest frame not evicted.
+    pub fn start(&self) -> usize {
+        let frames = self.frames.read();
+        frames.start()
+    }
+
     pub fn set_filter(&mut self, id: u32, filter: Option<Filter>) {
         self.sender.send(Command::SetFilter(id, filter));
     }
 
-    pub fn push_output<O: 'static + Output>(&mut self, id: u32, output: O, filter: Option<Filter>) {
+    /// Sorts the frames by the attribute or the `frame.*` pseudo-field `token`.
+    ///
+    /// If a filter is set with the same id, only the frames matching the filter
+    /// are sorted.
+    pub fn set_sort(&mut self, id: u32, token: Token, order: Order) {
+        self.sender.send(Command::SetSort(id, Some((token, order))));
+    }
+
+    pub fn unset_sort(&mut self, id: u32) {
+        self.sender.send(Command::SetSort(id, None));
+    }
+
+    /// Writes the selected frames to the output.
+    ///
+    /// If `follow` is false, only the frames stored so far are written.
+    /// Otherwise, the output keeps receiving new frames until `close_output` is called.
+    ///
+    /// The frames not yet written are kept from eviction, but an output
+    /// holding more than twice the limits of the profile fails
+    /// and the frames are evicted.
+    pub fn push_output<O: 'static + Output>(
+        &mut self,
+        id: u32,
+        output: O,
+        selection: Selection,
+        follow: bool,
+    ) {
         self.sender
-            .send(Command::PushOutput(id, Box::new(output), filter));
+            .send(Command::PushOutput(id, Box::new(output), selection, follow));
+    }
+
+    /// Stops writing to the output, ending it with the frames written so far.
+    pub fn close_output(&mut self, id: u32) {
+        self.sender.send(Command::CloseOutput(id));
     }
 
     pub fn set_input<I: 'static + Input>(&mut self, id: u32, input: I) {
+        self.sender.send(Command::OpenInput(id));
         let holder = Arc::new(self.sender.clone());
         let sender = Arc::downgrade(&holder);
         let mut input = input;
@@ -148,10 +386,19 @@ impl Store {
         );
     }
 
-    pub fn unset_input(&mut self, id: u32) {
+    /// Stops reading the input.
+    ///
+    /// If `remove_frames` is true, the frames already read from the input are
+    /// excluded from the views and the statistics, as well as the frames
+    /// still being decoded.
+    pub fn unset_input(&mut self, id: u32, remove_frames: bool) {
         if let Some(mut input) = self.inputs.remove(&id) {
             input.holder = None;
             self.inputs_trash.push(input);
+            self.sender.send(Command::CloseInput(id));
+            if remove_frames {
+                self.sender.send(Command::RemoveInput(id));
+            }
         }
     }
 }
@@ -191,11 +438,330 @@ impl serial::Callback for SerialCallback {
     }
 }
 
+#[derive(Clone)]
+struct FilterCallback {
+    sender: crossbeam_channel::Sender<Command>,
+}
+
+impl filter::Callback for FilterCallback {
+    fn done(&self, result: filter::Output) {
+        self.sender.send(Command::FilterDone(result));
+    }
+}
+
+/// The state of an output.
+///
+/// `end` is the number of frames stored when a snapshot output was pushed,
+/// and `None` for an output following new frames.
+///
+/// `filter` is the cancellation flag of the filter of a filtered view
+/// when the output was pushed.
+struct OutputContext {
+    output: Box<Output>,
+    selection: Selection,
+    offset: usize,
+    end: Option<usize>,
+    filter: Option<Arc<AtomicBool>>,
+}
+
+/// Orders the frames of multiple inputs by timestamp.
+///
+/// The oldest queued frame is released once no open input can precede it:
+/// each open input either has a frame queued, has already read a frame
+/// as new as it, or has been idle for longer than the grace period.
+/// The frames of a closed input are released without waiting for it.
+struct MergeContext {
+    queues: FnvHashMap<u32, MergeQueue>,
+    grace: Duration,
+}
+
+struct MergeQueue {
+    frames: VecDeque<Frame>,
+    open: bool,
+    last: Option<f64>,
+    updated: Instant,
+}
+
+impl MergeQueue {
+    fn new(open: bool) -> MergeQueue {
+        MergeQueue {
+            frames: VecDeque::new(),
+            open,
+            last: None,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Returns true if the input may still read a frame older than `timestamp`.
+    fn precedes(&self, timestamp: f64, grace: Duration) -> bool {
+        self.open
+            && self.frames.is_empty()
+            && self.last.map_or(true, |last| last < timestamp)
+            && self.updated.elapsed() < grace
+    }
+}
+
+impl MergeContext {
+    fn new() -> MergeContext {
+        MergeContext {
+            queues: FnvHashMap::default(),
+            grace: Duration::from_millis(MERGE_GRACE_PERIOD_MS),
+        }
+    }
+
+    fn open(&mut self, id: u32) {
+        self.queues.insert(id, MergeQueue::new(true));
+    }
+
+    fn close(&mut self, id: u32) {
+        if let Some(queue) = self.queues.get_mut(&id) {
+            queue.open = false;
+        }
+    }
+
+    /// Queues the frames of an input.
+    ///
+    /// Frames arriving after the input is closed are queued as closed.
+    fn push(&mut self, id: u32, frames: Vec<Frame>) {
+        let queue = self
+            .queues
+            .entry(id)
+            .or_insert_with(|| MergeQueue::new(false));
+        if let Some(frame) = frames.last() {
+            queue.last = Some(frame.timestamp());
+        }
+        queue.updated = Instant::now();
+        queue.frames.extend(frames);
+    }
+
+    /// Releases the frames which no open input can precede anymore.
+    fn pop(&mut self) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        loop {
+            let next = self
+                .queues
+                .iter()
+                .filter_map(|(id, queue)| queue.frames.front().map(|f| (f.timestamp(), *id)))
+                .min_by(|a, b| {
+                    a.0.partial_cmp(&b.0)
+                        .unwrap_or(Ordering::Equal)
+                        .then(a.1.cmp(&b.1))
+                });
+            let (timestamp, id) = match next {
+                Some(next) => next,
+                None => break,
+            };
+            let grace = self.grace;
+            if self
+                .queues
+                .values()
+                .any(|queue| queue.precedes(timestamp, grace))
+            {
+                break;
+            }
+            if let Some(queue) = self.queues.get_mut(&id) {
+                frames.extend(queue.frames.pop_front());
+            }
+        }
+        self.queues
+            .retain(|_, queue| queue.open || !queue.frames.is_empty());
+        frames
+    }
+
+    /// Returns the time until the frames held back for an idle input
+    /// are released, or `None` if no frame is held back.
+    fn timeout(&self) -> Option<Duration> {
+        if self.queues.values().all(|queue| queue.frames.is_empty()) {
+            return None;
+        }
+        self.queues
+            .values()
+            .filter(|queue| queue.open && queue.frames.is_empty())
+            .map(|queue| {
+                self.grace
+                    .checked_sub(queue.updated.elapsed())
+                    .unwrap_or_else(|| Duration::from_millis(0))
+            })
+            .min()
+    }
+}
+
+/// The state of a filtered view.
+///
+/// `offset` is the end of the frames whose results are committed,
+/// and `dispatched` is the end of the frames sent to the filter pool.
+/// Results are committed in order as the pending jobs complete.
 struct FilterContext {
-    filter: Filter,
+    filter: Arc<Filter>,
+    offset: usize,
+    dispatched: usize,
+    jobs: VecDeque<(Range<usize>, Option<Vec<u32>>)>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl FilterContext {
+    fn new(filter: Filter) -> FilterContext {
+        FilterContext {
+            filter: Arc::new(filter),
+            offset: 0,
+            dispatched: 0,
+            jobs: VecDeque::new(),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl Drop for FilterContext {
+    fn drop(&mut self) {
+        self.cancelled.store(true, AtomicOrdering::Relaxed);
+    }
+}
+
+struct SortContext {
+    key: Expr,
+    order: Order,
     offset: usize,
 }
 
+impl SortContext {
+    fn new(token: Token, order: Order) -> SortContext {
+        let key = Field::from_name(&token.to_string()).map_or(Expr::Token(token), Expr::Frame);
+        SortContext {
+            key,
+            order,
+            offset: 0,
+        }
+    }
+
+    fn key(&self, frame: &Frame) -> Variant {
+        let ctx = genet_filter::context::Context::with_metadata(frame.layers(), frame.metadata());
+        match self.key.eval(&ctx) {
+            Variant::Slice(slice) => Variant::Buffer(slice.to_vec().into_boxed_slice()),
+            val => val,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.offset = 0;
+    }
+}
+
+/// The sort keys of a sorted view.
+///
+/// New keys are kept in `pending` and merged into the sorted keys
+/// only when the view is read.
+#[derive(Debug)]
+struct SortedFrames {
+    order: Order,
+    keys: Vec<(Variant, u32)>,
+    pending: Vec<(Variant, u32)>,
+}
+
+impl SortedFrames {
+    fn new(order: Order) -> SortedFrames {
+        SortedFrames {
+            order,
+            keys: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.keys.len() + self.pending.len()
+    }
+
+    fn push(&mut self, keys: Vec<(Variant, u32)>) {
+        self.pending.extend(keys);
+    }
+
+    fn retain<F: Fn(u32) -> bool>(&mut self, retain: F) {
+        self.keys.retain(|(_, index)| retain(*index));
+        self.pending.retain(|(_, index)| retain(*index));
+    }
+
+    /// Compares the keys, placing frames without the attribute last
+    /// and breaking ties by the frame index.
+    ///
+    /// Numbers are compared by value regardless of their types,
+    /// and keys of other different types are ordered by the type first.
+    fn compare(&self, a: &(Variant, u32), b: &(Variant, u32)) -> Ordering {
+        let rank = |key: &Variant| match key {
+            Variant::Bool(_) => 0,
+            Variant::Int64(_) | Variant::UInt64(_) | Variant::Float64(_) | Variant::BigInt(_) => 1,
+            Variant::Buffer(_) | Variant::Slice(_) => 2,
+            Variant::String(_) => 3,
+            Variant::Nil => 4,
+        };
+        let ord = match (&a.0, &b.0) {
+            (Variant::Nil, Variant::Nil) => Ordering::Equal,
+            (Variant::Nil, _) => Ordering::Greater,
+            (_, Variant::Nil) => Ordering::Less,
+            (lhs, rhs) => {
+                let ord = rank(lhs).cmp(&rank(rhs)).then_with(|| match (lhs, rhs) {
+                    (Variant::Bool(lhs), Variant::Bool(rhs)) => lhs.cmp(rhs),
+                    (Variant::Int64(_), _) | (Variant::UInt64(_), _) | (Variant::Float64(_), _) => {
+                        compare_numbers(lhs, rhs)
+                    }
+                    _ => lhs.ord(rhs).unwrap_or(Ordering::Equal),
+                });
+                if self.order == Order::Descending {
+                    ord.reverse()
+                } else {
+                    ord
+                }
+            }
+        };
+        ord.then(a.1.cmp(&b.1))
+    }
+
+    /// Merges the pending keys into the sorted keys.
+    fn merge(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let mut keys = mem::replace(&mut self.pending, Vec::new());
+        keys.sort_by(|a, b| self.compare(a, b));
+        let old = mem::replace(&mut self.keys, Vec::new());
+        let mut merged = Vec::with_capacity(old.len() + keys.len());
+        {
+            let mut old = old.into_iter().peekable();
+            let mut new = keys.into_iter().peekable();
+            loop {
+                let take_old = match (old.peek(), new.peek()) {
+                    (Some(a), Some(b)) => self.compare(a, b) != Ordering::Greater,
+                    (Some(_), None) => true,
+                    (None, Some(_)) => false,
+                    (None, None) => break,
+                };
+                merged.extend(if take_old { old.next() } else { new.next() });
+            }
+        }
+        self.keys = merged;
+    }
+}
+
+/// Compares two numeric keys by value, placing NaN after the other numbers.
+fn compare_numbers(lhs: &Variant, rhs: &Variant) -> Ordering {
+    let float = |key: &Variant| match key {
+        Variant::Int64(v) => Some(*v as f64),
+        Variant::UInt64(v) => Some(*v as f64),
+        Variant::Float64(v) => Some(*v),
+        _ => None,
+    };
+    match (lhs, rhs) {
+        (Variant::Int64(lhs), Variant::Int64(rhs)) => lhs.cmp(rhs),
+        (Variant::UInt64(lhs), Variant::UInt64(rhs)) => lhs.cmp(rhs),
+        (Variant::Int64(lhs), Variant::UInt64(rhs)) => (*lhs as i128).cmp(&(*rhs as i128)),
+        (Variant::UInt64(lhs), Variant::Int64(rhs)) => (*lhs as i128).cmp(&(*rhs as i128)),
+        _ => match (float(lhs), float(rhs)) {
+            (Some(lhs), Some(rhs)) => lhs
+                .partial_cmp(&rhs)
+                .unwrap_or_else(|| lhs.is_nan().cmp(&rhs.is_nan())),
+            _ => lhs.ord(rhs).unwrap_or(Ordering::Equal),
+        },
+    }
+}
+
 struct EventLoop {
     handle: Option<JoinHandle<()>>,
     sender: crossbeam_channel::Sender<Command>,
@@ -207,6 +773,10 @@ impl EventLoop {
         callback: C,
         frames: FrameStore,
         filtered: FilteredFrameStore,
+        sorted: SortedFrameStore,
+        input_frames: InputFrameStore,
+        stats: StatsStore,
+        spill: SpillStore,
     ) -> (EventLoop, crossbeam_channel::Sender<Command>) {
         let (send, recv) = crossbeam_channel::unbounded();
         let sender = send.clone();
@@ -214,6 +784,7 @@ impl EventLoop {
             let err_callback = callback.clone();
             let result = panic::catch_unwind(AssertUnwindSafe(move || {
                 let mut filter_map = FnvHashMap::default();
+                let mut sort_map = FnvHashMap::default();
                 let mut ppool = parallel::Pool::new(
                     &profile,
                     &ParallelCallback {
@@ -226,43 +797,203 @@ impl EventLoop {
                         sender: sender.clone(),
                     },
                 );
+                let mut fpool = filter::Pool::new(
+                    profile.concurrency(),
+                    &frames,
+                    &spill,
+                    &FilterCallback {
+                        sender: sender.clone(),
+                    },
+                );
                 let mut cnt = 0;
+                let mut clock = None;
+                let mut merge = if profile.merge_inputs() {
+                    Some(MergeContext::new())
+                } else {
+                    None
+                };
+                let mut removed = FnvHashSet::default();
+                let mut bytes = 0;
+                let mut spilled = 0;
+                let mut output_map: FnvHashMap<u32, OutputContext> = FnvHashMap::default();
+                let mut pending = false;
                 callback.on_frames_updated(0);
                 callback.on_async_frames_updated(0);
                 loop {
-                    if let Some(cmd) = recv.recv() {
+                    let timeout = merge.as_ref().and_then(MergeContext::timeout);
+                    let cmd = if pending {
+                        recv.try_recv()
+                    } else if let Some(timeout) = timeout {
+                        select! {
+                            recv(recv, cmd) => cmd,
+                            recv(crossbeam_channel::after(timeout)) => None,
+                        }
+                    } else {
+                        recv.recv()
+                    };
+                    if let Some(cmd) = cmd {
                         match cmd {
-                            Command::PushFrames(id, result) => {
-                                Self::process_input(id, result, &mut cnt, &mut ppool, &callback)
+                            Command::PushFrames(id, result) => Self::process_input(
+                                id, result, &mut merge, &mut cnt, &mut clock, &mut ppool, &callback,
+                            ),
+                            Command::OpenInput(id) => {
+                                if let Some(merge) = merge.as_mut() {
+                                    merge.open(id);
+                                }
+                            }
+                            Command::CloseInput(id) => {
+                                if let Some(merge) = merge.as_mut() {
+                                    merge.close(id);
+                                    let frames = merge.pop();
+                                    Self::process_frames(frames, &mut cnt, &mut clock, &mut ppool);
+                                }
                             }
                             Command::PushSerialFrames(vec) => {
                                 spool.process(vec);
                             }
                             Command::StoreFrames(mut vec) => {
+                                for frame in vec.iter_mut() {
+                                    if frame.input().map_or(false, |id| removed.contains(&id)) {
+                                        frame.set_removed();
+                                    }
+                                }
+                                {
+                                    let stored = vec
+                                        .iter()
+                                        .filter(|frame| !frame.is_removed())
+                                        .collect::<Vec<_>>();
+                                    Self::process_stats(None, &stored, &stats, &callback);
+                                    Self::process_input_frames(&stored, &input_frames);
+                                }
                                 let len = {
                                     let mut frames = frames.write();
                                     for f in vec {
-                                        frames.push(f);
+                                        bytes += f.captured_length() as usize;
+                                        frames.push(Arc::new(f));
                                     }
                                     frames.len()
                                 };
+                                let written = output_map.values().map(|octx| octx.offset).min();
+                                if let Some(range) = Self::process_eviction(
+                                    &profile, &mut bytes, written, &frames, &filtered, &stats,
+                                    &spill, &callback,
+                                ) {
+                                    Self::process_retain_sorts(
+                                        |index| index as usize >= range.end,
+                                        &sorted,
+                                        &sort_map,
+                                        &callback,
+                                    );
+                                    Self::process_evicted_inputs(range, &input_frames);
+                                }
                                 callback.on_frames_updated(len as u32);
                                 callback.on_async_frames_updated(len as u32);
                             }
-                            Command::SetFilter(id, filter) => Self::process_push_filter(
-                                id,
-                                filter,
+                            Command::RemoveInput(id) => {
+                                removed.insert(id);
+                                let indices = Self::process_remove_input(
+                                    id,
+                                    &frames,
+                                    &input_frames,
+                                    &filtered,
+                                    &stats,
+                                    &spill,
+                                    &callback,
+                                );
+                                Self::process_retain_sorts(
+                                    |index| indices.binary_search(&index).is_err(),
+                                    &sorted,
+                                    &sort_map,
+                                    &callback,
+                                );
+                                callback.on_frames_updated(frames.read().len() as u32);
+                            }
+                            Command::SetFilter(id, filter) => {
+                                Self::process_reset_sort(id, &sorted, &mut sort_map, &callback);
+                                Self::process_push_filter(
+                                    id,
+                                    filter,
+                                    &frames,
+                                    &filtered,
+                                    &stats,
+                                    &mut filter_map,
+                                    &callback,
+                                )
+                            }
+                            Command::FilterDone(output) => Self::process_filter_result(
+                                output,
+                                &frames,
                                 &filtered,
+                                &stats,
+                                &spill,
                                 &mut filter_map,
                                 &callback,
                             ),
-                            Command::PushOutput(id, output, filter) => {
-                                Self::process_output(id, output, &filter, &frames, &callback)
+                            Command::SetSort(id, sort) => {
+                                Self::process_push_sort(id, sort, &sorted, &mut sort_map, &callback)
+                            }
+                            Command::AddIoGraph(id, graph) => Self::process_push_io_graph(
+                                id, graph, &frames, &filtered, &stats, &spill, &callback,
+                            ),
+                            Command::RemoveIoGraph(id, interval, metric) => {
+                                if let Some(stats) = stats.write().get_mut(&id) {
+                                    stats.remove_io_graph(interval, metric);
+                                }
+                            }
+                            Command::PushOutput(id, output, selection, follow) => {
+                                Self::process_push_output(
+                                    id,
+                                    output,
+                                    selection,
+                                    follow,
+                                    &frames,
+                                    &filter_map,
+                                    &mut output_map,
+                                )
+                            }
+                            Command::CloseOutput(id) => {
+                                if let Some(octx) = output_map.remove(&id) {
+                                    Self::process_end_output(id, octx, None, &callback);
+                                }
+                            }
+                            Command::Close => {
+                                for (id, octx) in output_map.drain() {
+                                    Self::process_end_output(id, octx, None, &callback);
+                                }
+                                return;
                             }
-                            Command::Close => return,
                         }
                     }
-                    Self::process_filters(&frames, &filtered, &mut filter_map, &callback);
+                    if let Some(merge) = merge.as_mut() {
+                        let released = merge.pop();
+                        Self::process_frames(released, &mut cnt, &mut clock, &mut ppool);
+                    }
+                    Self::process_filters(&frames, &mut fpool, &mut filter_map);
+                    Self::process_sorts(
+                        &frames,
+                        &filtered,
+                        &sorted,
+                        &spill,
+                        &filter_map,
+                        &mut sort_map,
+                        &callback,
+                    );
+                    Self::process_spill(&profile, &frames, &spill, &mut spilled, &callback);
+                    pending = Self::process_outputs(
+                        &frames,
+                        &filtered,
+                        &spill,
+                        &filter_map,
+                        &mut output_map,
+                        &callback,
+                    );
+                    Self::process_lagging_outputs(
+                        &profile,
+                        bytes,
+                        &frames,
+                        &mut output_map,
+                        &callback,
+                    );
                 }
             }));
             if let Err(err) = result {
@@ -289,7 +1020,9 @@ impl EventLoop {
     fn process_input(
         id: Option<u32>,
         result: Result<Vec<MutFixed<Layer>>>,
+        merge: &mut Option<MergeContext>,
         cnt: &mut u32,
+        clock: &mut Option<(f64, f64)>,
         pool: &mut parallel::Pool,
         callback: &Callback,
     ) {
@@ -302,131 +1035,857 @@ impl EventLoop {
                 } else {
                     let frames = layers
                         .into_iter()
-                        .enumerate()
-                        .map(|(i, root)| {
-                            let index = *cnt + i as u32;
-                            Frame::new(index, root)
-                        })
+                        .map(|root| Frame::new(0, id, root))
                         .collect::<Vec<_>>();
-                    *cnt += frames.len() as u32;
-                    pool.process(frames);
+                    let frames = match (merge.as_mut(), id) {
+                        (Some(merge), Some(id)) => {
+                            merge.push(id, frames);
+                            merge.pop()
+                        }
+                        _ => frames,
+                    };
+                    Self::process_frames(frames, cnt, clock, pool);
                 }
             }
             Err(err) => {
                 if let Some(id) = id {
+                    if let Some(merge) = merge.as_mut() {
+                        merge.close(id);
+                        Self::process_frames(merge.pop(), cnt, clock, pool);
+                    }
                     callback.on_input_done(id, Some(err));
                 }
             }
         }
     }
 
-    fn process_output(
+    /// Numbers the frames in order and sends them to the decoders.
+    fn process_frames(
+        frames: Vec<Frame>,
+        cnt: &mut u32,
+        clock: &mut Option<(f64, f64)>,
+        pool: &mut parallel::Pool,
+    ) {
+        if frames.is_empty() {
+            return;
+        }
+        let frames = frames
+            .into_iter()
+            .enumerate()
+            .map(|(i, mut frame)| {
+                frame.set_index(*cnt + i as u32);
+                let ts = frame.timestamp();
+                let (first, prev) = clock.unwrap_or((ts, ts));
+                frame.set_reference_time(first, prev);
+                *clock = Some((first, ts));
+                frame
+            })
+            .collect::<Vec<_>>();
+        *cnt += frames.len() as u32;
+        pool.process(frames);
+    }
+
+    /// Starts writing to the output from the oldest selected frame.
+    ///
+    /// The index ranges of a selection also bound a following output.
+    fn process_push_output(
         id: u32,
         output: Box<Output>,
-        filter: &Option<Filter>,
+        selection: Selection,
+        follow: bool,
         frames: &FrameStore,
-        callback: &Callback,
+        filter_map: &FnvHashMap<u32, FilterContext>,
+        output_map: &mut FnvHashMap<u32, OutputContext>,
     ) {
-        let frames = frames.read();
-        let mut offset = 0;
-        {
-            let mut output = output;
-            while offset < frames.len() {
-                let len = OUTPUT_BLOCK_SIZE.min(frames.len() - offset);
-                let frames = frames
+        let (start, len) = {
+            let frames = frames.read();
+            (frames.start(), frames.len())
+        };
+        let (offset, limit) = if let Selection::Ranges(ranges) = &selection {
+            (
+                ranges
                     .iter()
-                    .skip(offset)
-                    .take(len)
-                    .filter(|frame| {
-                        let ctx = genet_filter::context::Context::new(frame.layers());
-                        filter.as_ref().map_or(true, |f| f.test(&ctx))
-                    })
-                    .collect::<Vec<_>>();
-                if let Err(err) = output.write(frames.as_slice()) {
-                    let err = Error(err.description().to_string());
-                    callback.on_output_done(id, Some(Box::new(err)));
-                    return;
+                    .map(|r| r.start as usize)
+                    .min()
+                    .unwrap_or(start),
+                Some(ranges.iter().map(|r| r.end as usize).max().unwrap_or(0)),
+            )
+        } else {
+            (start, None)
+        };
+        let end = if follow {
+            limit
+        } else {
+            Some(limit.map_or(len, |limit| limit.min(len)))
+        };
+        let filter = if let Selection::View(view) = selection {
+            filter_map.get(&view).map(|fctx| fctx.cancelled.clone())
+        } else {
+            None
+        };
+        output_map.insert(
+            id,
+            OutputContext {
+                output,
+                selection,
+                offset: offset.max(start),
+                end,
+                filter,
+            },
+        );
+    }
+
+    /// Writes the next block of frames to each output, and ends the outputs
+    /// which reached their end or failed.
+    ///
+    /// A filtered view is written as far as its filter has processed the frames,
+    /// and its outputs fail if the filter is replaced.
+    /// Returns true if any output has frames ready to write.
+    fn process_outputs(
+        frames: &FrameStore,
+        filtered: &FilteredFrameStore,
+        spill: &SpillStore,
+        filter_map: &FnvHashMap<u32, FilterContext>,
+        output_map: &mut FnvHashMap<u32, OutputContext>,
+        callback: &Callback,
+    ) -> bool {
+        let mut pending = false;
+        let mut done = Vec::new();
+        for (id, octx) in output_map.iter_mut() {
+            let limit = if let Selection::View(view) = octx.selection {
+                if let Some(fctx) = filter_map.get(&view) {
+                    if !octx
+                        .filter
+                        .as_ref()
+                        .map_or(false, |filter| Arc::ptr_eq(filter, &fctx.cancelled))
+                    {
+                        let err: Box<::std::error::Error + Send> =
+                            Box::new(Error(format!("filtered view changed: {}", view)));
+                        done.push((*id, Some(err)));
+                        continue;
+                    }
+                    Some(fctx.offset)
+                } else {
+                    let err: Box<::std::error::Error + Send> =
+                        Box::new(Error(format!("no filtered view: {}", view)));
+                    done.push((*id, Some(err)));
+                    continue;
+                }
+            } else {
+                None
+            };
+            let (result, available, total) = {
+                let frames = frames.read();
+                let start = octx.offset;
+                let end = octx.end.map_or(frames.len(), |end| end.min(frames.len()));
+                let available = limit.map_or(end, |limit| limit.min(end));
+                let last = available.min(start + OUTPUT_BLOCK_SIZE);
+                if start >= last {
+                    (Ok(false), available, end)
+                } else {
+                    let indices = octx.selection.indices(start..last, &filtered.read());
+                    let chunk = indices
+                        .into_iter()
+                        .filter_map(|index| frames.get(index))
+                        .map(|frame| &**frame)
+                        .filter(|frame| !frame.is_removed())
+                        .collect::<Vec<_>>();
+                    let restored = Self::restore(&chunk, spill, callback);
+                    let chunk = spill::merge(chunk, &restored)
+                        .into_iter()
+                        .filter(|frame| octx.selection.test(frame))
+                        .collect::<Vec<_>>();
+                    octx.offset = last;
+                    (
+                        octx.output.write(chunk.as_slice()).map(|_| true),
+                        available,
+                        end,
+                    )
+                }
+            };
+            match result {
+                Ok(written) => {
+                    if written {
+                        callback.on_output_progress(*id, octx.offset as u32, total as u32);
+                    }
+                    if octx.end.map_or(false, |end| octx.offset >= end) {
+                        done.push((*id, None));
+                    } else if octx.offset < available {
+                        pending = true;
+                    }
+                }
+                Err(err) => {
+                    let err: Box<::std::error::Error + Send> =
+                        Box::new(Error(err.description().to_string()));
+                    done.push((*id, Some(err)));
                 }
-                offset += len;
             }
-            if let Err(err) = output.end() {
-                let err = Error(err.description().to_string());
-                callback.on_output_done(id, Some(Box::new(err)));
-                return;
+        }
+        for (id, error) in done {
+            if let Some(octx) = output_map.remove(&id) {
+                Self::process_end_output(id, octx, error, callback);
             }
         }
-        callback.on_output_done(id, None);
+        pending
+    }
+
+    /// Ends the output unless it failed, and reports the result.
+    fn process_end_output(
+        id: u32,
+        octx: OutputContext,
+        error: Option<Box<::std::error::Error + Send>>,
+        callback: &Callback,
+    ) {
+        let mut octx = octx;
+        let error = error.or_else(|| {
+            octx.output.end().err().map(|err| {
+                let err: Box<::std::error::Error + Send> =
+                    Box::new(Error(err.description().to_string()));
+                err
+            })
+        });
+        callback.on_output_done(id, error);
     }
 
+    /// Replaces the filter of the view, cancelling the jobs of the previous one.
     fn process_push_filter(
         id: u32,
         filter: Option<Filter>,
+        frames: &FrameStore,
         filtered: &FilteredFrameStore,
+        stats: &StatsStore,
         filter_map: &mut FnvHashMap<u32, FilterContext>,
         callback: &Callback,
     ) {
         if let Some(filter) = filter {
-            filter_map.insert(
-                id,
-                FilterContext {
-                    offset: 0,
-                    filter: filter.clone(),
-                },
-            );
+            filter_map.insert(id, FilterContext::new(filter));
             callback.on_filtered_frames_updated(id, 0);
+            callback.on_filter_progress(id, 0, frames.read().len() as u32);
+            let graphs = {
+                let mut stats = stats.write();
+                let stats = stats.entry(Some(id)).or_insert_with(Stats::new);
+                stats.reset();
+                Self::io_graph_keys(stats)
+            };
+            for (interval, metric) in graphs {
+                callback.on_io_graph_updated(Some(id), interval, metric);
+            }
         } else {
             filter_map.remove(&id);
+            stats.write().remove(&Some(id));
         }
         filtered.write().remove(&id);
     }
 
-    fn process_filters(
+    /// Clears the sorted view sharing its id with a filter being replaced.
+    fn process_reset_sort(
+        id: u32,
+        sorted: &SortedFrameStore,
+        sort_map: &mut FnvHashMap<u32, SortContext>,
+        callback: &Callback,
+    ) {
+        if let Some(sctx) = sort_map.get_mut(&id) {
+            sctx.reset();
+            sorted.write().remove(&id);
+            callback.on_sorted_frames_updated(id, 0);
+        }
+    }
+
+    /// Starts tracking an I/O graph, counting the frames already in the view.
+    fn process_push_io_graph(
+        id: Option<u32>,
+        graph: IoGraph,
         frames: &FrameStore,
         filtered: &FilteredFrameStore,
-        filter_map: &mut FnvHashMap<u32, FilterContext>,
+        stats: &StatsStore,
+        spill: &SpillStore,
         callback: &Callback,
     ) {
-        for (id, fctx) in filter_map.iter_mut() {
-            loop {
-                let (mut indices, end) = {
-                    let frames = frames.read();
-                    let mut indices = frames
-                        .iter()
-                        .skip(fctx.offset)
-                        .take(MAX_FILTER_SIZE)
-                        .filter_map(|frame| {
-                            let ctx = genet_filter::context::Context::new(frame.layers());
-                            if fctx.filter.test(&ctx) {
-                                Some(frame.index())
-                            } else {
-                                None
-                            }
-                        })
-                        .collect::<Vec<_>>();
-                    fctx.offset = frames.len().min(fctx.offset + MAX_FILTER_SIZE);
-                    (indices, fctx.offset >= frames.len())
-                };
-                if !indices.is_empty() {
-                    let len = {
-                        let mut filtered = filtered.write();
-                        let mut frames = filtered.entry(*id).or_insert_with(Vec::new);
-                        frames.append(&mut indices);
-                        frames.len()
-                    };
-                    callback.on_filtered_frames_updated(*id, len as u32);
-                }
-                if end {
-                    break;
+        let mut graph = graph;
+        {
+            let frames = frames.read();
+            let indices = if let Some(id) = id {
+                filtered
+                    .read()
+                    .get(&id)
+                    .map_or_else(Vec::new, |vec| vec.iter().map(|i| *i as usize).collect())
+            } else {
+                (frames.start()..frames.len()).collect()
+            };
+            for chunk in indices.chunks(MAX_FILTER_SIZE) {
+                let chunk = chunk
+                    .iter()
+                    .filter_map(|index| frames.get(*index))
+                    .map(|frame| &**frame)
+                    .filter(|frame| !frame.is_removed())
+                    .collect::<Vec<_>>();
+                let restored = Self::restore(&chunk, spill, callback);
+                for frame in spill::merge(chunk, &restored) {
+                    graph.add(frame);
                 }
             }
         }
+        let (interval, metric) = (graph.interval(), graph.metric());
+        let added = stats
+            .write()
+            .get_mut(&id)
+            .map_or(false, |stats| stats.add_io_graph(graph));
+        if added {
+            callback.on_io_graph_updated(id, interval, metric);
+        }
     }
-}
 
-impl Drop for EventLoop {
-    fn drop(&mut self) {
-        self.sender.send(Command::Close);
-        self.handle.take().unwrap().join().expect("failed to join");
+    /// Adds the frames to the statistics of the view.
+    fn process_stats(id: Option<u32>, frames: &[&Frame], stats: &StatsStore, callback: &Callback) {
+        if frames.is_empty() {
+            return;
+        }
+        let graphs = match stats.write().get_mut(&id) {
+            Some(stats) => {
+                frames.iter().for_each(|frame| stats.add(frame));
+                Self::io_graph_keys(stats)
+            }
+            None => return,
+        };
+        for (interval, metric) in graphs {
+            callback.on_io_graph_updated(id, interval, metric);
+        }
+    }
+
+    fn io_graph_keys(stats: &Stats) -> Vec<(f64, Metric)> {
+        stats
+            .io_graphs()
+            .iter()
+            .map(|graph| (graph.interval(), graph.metric()))
+            .collect()
+    }
+
+    fn process_push_sort(
+        id: u32,
+        sort: Option<(Token, Order)>,
+        sorted: &SortedFrameStore,
+        sort_map: &mut FnvHashMap<u32, SortContext>,
+        callback: &Callback,
+    ) {
+        if let Some((token, order)) = sort {
+            sort_map.insert(id, SortContext::new(token, order));
+            callback.on_sorted_frames_updated(id, 0);
+        } else {
+            sort_map.remove(&id);
+        }
+        sorted.write().remove(&id);
+    }
+
+    /// Returns the end of the oldest frames before `len` to evict for the store
+    /// to fit in `ratio` times the limits of the profile,
+    /// and the captured bytes left after evicting them.
+    fn eviction_end(
+        profile: &Profile,
+        bytes: usize,
+        frames: &ArrayVec<Arc<Frame>>,
+        len: usize,
+        ratio: usize,
+    ) -> (usize, usize) {
+        let mut bytes = bytes;
+        let mut end = frames.start();
+        while end < len {
+            let exceeded = profile
+                .max_frames()
+                .map_or(false, |max| frames.len() - end > max * ratio)
+                || profile.max_bytes().map_or(false, |max| bytes > max * ratio);
+            if !exceeded {
+                break;
+            }
+            if let Some(frame) = frames.get(end) {
+                bytes -= frame.captured_length() as usize;
+            }
+            end += 1;
+        }
+        (end, bytes)
+    }
+
+    /// Fails the outputs still holding frames beyond `OUTPUT_HOLD_LIMIT_RATIO`
+    /// times the limits of the profile after being written,
+    /// so that a stalled output does not keep the frames from eviction.
+    fn process_lagging_outputs(
+        profile: &Profile,
+        bytes: usize,
+        frames: &FrameStore,
+        output_map: &mut FnvHashMap<u32, OutputContext>,
+        callback: &Callback,
+    ) {
+        let end = {
+            let frames = frames.read();
+            let len = frames.len();
+            Self::eviction_end(profile, bytes, &frames, len, OUTPUT_HOLD_LIMIT_RATIO).0
+        };
+        let lagging = output_map
+            .iter()
+            .filter(|(_, octx)| octx.offset < end)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in lagging {
+            if let Some(octx) = output_map.remove(&id) {
+                let err: Box<::std::error::Error + Send> =
+                    Box::new(Error(format!("output lagged behind eviction: {}", id)));
+                Self::process_end_output(id, octx, Some(err), callback);
+            }
+        }
+    }
+
+    /// Evicts the oldest frames exceeding the limits of the profile,
+    /// and removes them from the filtered frames.
+    ///
+    /// Frames from `written` are kept until the outputs have written them.
+    fn process_eviction(
+        profile: &Profile,
+        bytes: &mut usize,
+        written: Option<usize>,
+        frames: &FrameStore,
+        filtered: &FilteredFrameStore,
+        stats: &StatsStore,
+        spill: &SpillStore,
+        callback: &Callback,
+    ) -> Option<Range<usize>> {
+        let (start, end) = {
+            let frames = frames.read();
+            let len = written.map_or(frames.len(), |written| written.min(frames.len()));
+            let (end, remaining) = Self::eviction_end(profile, *bytes, &frames, len, 1);
+            *bytes = remaining;
+            (frames.start(), end)
+        };
+        if start == end {
+            return None;
+        }
+        Self::process_evicted_stats(start..end, frames, filtered, stats, spill, callback);
+        frames.write().evict(end);
+        if let Some(spill) = spill {
+            spill.lock().forget(start as u32..end as u32);
+        }
+        let updated = {
+            let mut filtered = filtered.write();
+            filtered
+                .iter_mut()
+                .filter_map(|(id, indices)| {
+                    let len = indices
+                        .iter()
+                        .take_while(|index| (**index as usize) < end)
+                        .count();
+                    if len > 0 {
+                        indices.drain(..len);
+                        Some((*id, indices.len()))
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        callback.on_frames_evicted(start as u32..end as u32);
+        for (id, len) in updated {
+            callback.on_filtered_frames_updated(id, len as u32);
+        }
+        Some(start..end)
+    }
+
+    /// Subtracts the frames about to be evicted from the statistics.
+    fn process_evicted_stats(
+        range: Range<usize>,
+        frames: &FrameStore,
+        filtered: &FilteredFrameStore,
+        stats: &StatsStore,
+        spill: &SpillStore,
+        callback: &Callback,
+    ) {
+        let frames = frames.read();
+        let evicted = range
+            .clone()
+            .filter_map(|index| frames.get(index))
+            .map(|frame| &**frame)
+            .collect::<Vec<_>>();
+        let restored = Self::restore(&evicted, spill, callback);
+        let evicted = spill::merge(evicted, &restored);
+        let filtered = filtered.read();
+        let mut graphs = Vec::new();
+        for (id, stats) in stats.write().iter_mut() {
+            if let Some(filter) = id {
+                if let Some(indices) = filtered.get(filter) {
+                    indices
+                        .iter()
+                        .take_while(|index| (**index as usize) < range.end)
+                        .filter_map(|index| evicted.get(*index as usize - range.start))
+                        .for_each(|frame| stats.remove(frame));
+                }
+            } else {
+                evicted
+                    .iter()
+                    .filter(|frame| !frame.is_removed())
+                    .for_each(|frame| stats.remove(frame));
+            }
+            graphs.extend(
+                Self::io_graph_keys(stats)
+                    .into_iter()
+                    .map(|(interval, metric)| (*id, interval, metric)),
+            );
+        }
+        for (id, interval, metric) in graphs {
+            callback.on_io_graph_updated(id, interval, metric);
+        }
+    }
+
+    /// Records the indices of the stored frames for each input.
+    fn process_input_frames(frames: &[&Frame], input_frames: &InputFrameStore) {
+        let mut input_frames = input_frames.write();
+        for frame in frames {
+            if let Some(id) = frame.input() {
+                input_frames
+                    .entry(id)
+                    .or_insert_with(Vec::new)
+                    .push(frame.index());
+            }
+        }
+    }
+
+    fn process_evicted_inputs(range: Range<usize>, input_frames: &InputFrameStore) {
+        for indices in input_frames.write().values_mut() {
+            let len = indices
+                .iter()
+                .take_while(|index| (**index as usize) < range.end)
+                .count();
+            indices.drain(..len);
+        }
+    }
+
+    /// Marks the frames of the input as removed and subtracts them from
+    /// the filtered views and the statistics.
+    ///
+    /// Returns the indices of the removed frames.
+    fn process_remove_input(
+        id: u32,
+        frames: &FrameStore,
+        input_frames: &InputFrameStore,
+        filtered: &FilteredFrameStore,
+        stats: &StatsStore,
+        spill: &SpillStore,
+        callback: &Callback,
+    ) -> Vec<u32> {
+        let indices = input_frames.write().remove(&id).unwrap_or_default();
+        if indices.is_empty() {
+            return indices;
+        }
+        {
+            let frames = frames.read();
+            for index in &indices {
+                if let Some(frame) = frames.get(*index as usize) {
+                    frame.set_removed();
+                }
+            }
+        }
+        let (graphs, updated) = {
+            let frames = frames.read();
+            let removed = indices
+                .iter()
+                .filter_map(|index| frames.get(*index as usize))
+                .map(|frame| &**frame)
+                .collect::<Vec<_>>();
+            let restored = Self::restore(&removed, spill, callback);
+            let removed = spill::merge(removed, &restored);
+            let mut filtered = filtered.write();
+            let mut graphs = Vec::new();
+            for (view, stats) in stats.write().iter_mut() {
+                if let Some(view) = view {
+                    if let Some(matched) = filtered.get(view) {
+                        removed
+                            .iter()
+                            .filter(|frame| matched.binary_search(&frame.index()).is_ok())
+                            .for_each(|frame| stats.remove(frame));
+                    }
+                } else {
+                    removed.iter().for_each(|frame| stats.remove(frame));
+                }
+                graphs.extend(
+                    Self::io_graph_keys(stats)
+                        .into_iter()
+                        .map(|(interval, metric)| (*view, interval, metric)),
+                );
+            }
+            let updated = filtered
+                .iter_mut()
+                .filter_map(|(view, matched)| {
+                    let len = matched.len();
+                    matched.retain(|index| indices.binary_search(index).is_err());
+                    if matched.len() < len {
+                        Some((*view, matched.len()))
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>();
+            (graphs, updated)
+        };
+        for (view, interval, metric) in graphs {
+            callback.on_io_graph_updated(view, interval, metric);
+        }
+        for (view, len) in updated {
+            callback.on_filtered_frames_updated(view, len as u32);
+        }
+        indices
+    }
+
+    /// Removes the frames no longer retained from the sorted views.
+    fn process_retain_sorts<F: Fn(u32) -> bool>(
+        retain: F,
+        sorted: &SortedFrameStore,
+        sort_map: &FnvHashMap<u32, SortContext>,
+        callback: &Callback,
+    ) {
+        let sorts = {
+            let mut sorted = sorted.write();
+            sort_map
+                .keys()
+                .filter_map(|id| {
+                    sorted.get_mut(id).map(|sorted| {
+                        sorted.retain(&retain);
+                        (*id, sorted.len())
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+        for (id, len) in sorts {
+            callback.on_sorted_frames_updated(id, len as u32);
+        }
+    }
+
+    /// Writes the oldest frames exceeding the resident limit to the spill file,
+    /// replacing them with copies without the decoded layers.
+    ///
+    /// The bytes are written without holding the frames lock, which is only
+    /// taken to swap in the copies. Spilling stops after the first failure.
+    fn process_spill(
+        profile: &Profile,
+        frames: &FrameStore,
+        spill: &SpillStore,
+        spilled: &mut usize,
+        callback: &Callback,
+    ) {
+        if let (Some(spill), Some(max)) = (spill, profile.max_resident_frames()) {
+            let (pending, end) = {
+                let frames = frames.read();
+                let end = frames.len().saturating_sub(max);
+                let pending = ((*spilled).max(frames.start())..end)
+                    .filter_map(|index| frames.get(index).map(|frame| (index, frame.clone())))
+                    .collect::<Vec<_>>();
+                (pending, end)
+            };
+            if pending.is_empty() {
+                return;
+            }
+            let mut paged = Vec::with_capacity(pending.len());
+            let mut result = Ok(());
+            for (index, frame) in pending {
+                match spill.lock().page_out(&frame) {
+                    Ok(Some(frame)) => paged.push((index, Arc::new(frame))),
+                    Ok(None) => {}
+                    Err(err) => {
+                        result = Err(err);
+                        break;
+                    }
+                }
+            }
+            {
+                let mut frames = frames.write();
+                for (index, frame) in paged {
+                    if let Some(slot) = frames.get_mut(index) {
+                        *slot = frame;
+                    }
+                }
+            }
+            match result {
+                Ok(()) => *spilled = end,
+                Err(err) => {
+                    callback.on_error(Box::new(Error(err.to_string())));
+                    *spilled = usize::max_value();
+                }
+            }
+        }
+    }
+
+    /// Restores copies of the spilled frames.
+    fn restore(frames: &[&Frame], spill: &SpillStore, callback: &Callback) -> Vec<Frame> {
+        spill::restore(spill, frames).unwrap_or_else(|err| {
+            callback.on_error(Box::new(Error(err.to_string())));
+            Vec::new()
+        })
+    }
+
+    /// Sorts the frames added since the last call into each sorted view.
+    ///
+    /// A view sharing its id with a filter only takes the frames the filter matched.
+    fn process_sorts(
+        frames: &FrameStore,
+        filtered: &FilteredFrameStore,
+        sorted: &SortedFrameStore,
+        spill: &SpillStore,
+        filter_map: &FnvHashMap<u32, FilterContext>,
+        sort_map: &mut FnvHashMap<u32, SortContext>,
+        callback: &Callback,
+    ) {
+        for (id, sctx) in sort_map.iter_mut() {
+            let (keys, end) = {
+                let frames = frames.read();
+                let start = sctx.offset.max(frames.start());
+                let (indices, end) = if let Some(fctx) = filter_map.get(id) {
+                    let filtered = filtered.read();
+                    let indices = filtered.get(id).map_or_else(Vec::new, |vec| {
+                        let pos = match vec.binary_search(&(start as u32)) {
+                            Ok(pos) | Err(pos) => pos,
+                        };
+                        vec[pos..]
+                            .iter()
+                            .take_while(|index| (**index as usize) < fctx.offset)
+                            .map(|index| *index as usize)
+                            .collect()
+                    });
+                    (indices, fctx.offset)
+                } else {
+                    ((start..frames.len()).collect(), frames.len())
+                };
+                if end <= sctx.offset {
+                    continue;
+                }
+                let keys = indices
+                    .chunks(MAX_FILTER_SIZE)
+                    .flat_map(|chunk| {
+                        let chunk = chunk
+                            .iter()
+                            .filter_map(|index| frames.get(*index))
+                            .map(|frame| &**frame)
+                            .filter(|frame| !frame.is_removed())
+                            .collect::<Vec<_>>();
+                        let restored = Self::restore(&chunk, spill, callback);
+                        spill::merge(chunk, &restored)
+                            .into_iter()
+                            .map(|frame| (sctx.key(frame), frame.index()))
+                            .collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>();
+                (keys, end)
+            };
+            sctx.offset = end;
+            if keys.is_empty() {
+                continue;
+            }
+            let len = {
+                let mut sorted = sorted.write();
+                let sorted = sorted
+                    .entry(*id)
+                    .or_insert_with(|| SortedFrames::new(sctx.order));
+                sorted.push(keys);
+                sorted.len()
+            };
+            callback.on_sorted_frames_updated(*id, len as u32);
+        }
+    }
+
+    /// Sends the frames not yet dispatched to the filter pool,
+    /// keeping at most `MAX_FILTER_JOBS` jobs in flight for each view.
+    fn process_filters(
+        frames: &FrameStore,
+        fpool: &mut filter::Pool,
+        filter_map: &mut FnvHashMap<u32, FilterContext>,
+    ) {
+        let (start, len) = {
+            let frames = frames.read();
+            (frames.start(), frames.len())
+        };
+        for (id, fctx) in filter_map.iter_mut() {
+            if fctx.jobs.is_empty() && fctx.offset < start {
+                fctx.offset = start;
+                fctx.dispatched = start;
+            }
+            while fctx.jobs.len() < MAX_FILTER_JOBS && fctx.dispatched < len {
+                let begin = fctx.dispatched.max(start);
+                let range = begin..len.min(begin + MAX_FILTER_SIZE);
+                fctx.dispatched = range.end;
+                fctx.jobs.push_back((range.clone(), None));
+                fpool.process(filter::Job {
+                    id: *id,
+                    filter: fctx.filter.clone(),
+                    range,
+                    cancelled: fctx.cancelled.clone(),
+                });
+            }
+        }
+    }
+
+    /// Commits the results of the completed jobs at the front of the queue.
+    ///
+    /// Results of a cancelled filter are ignored.
+    fn process_filter_result(
+        output: filter::Output,
+        frames: &FrameStore,
+        filtered: &FilteredFrameStore,
+        stats: &StatsStore,
+        spill: &SpillStore,
+        filter_map: &mut FnvHashMap<u32, FilterContext>,
+        callback: &Callback,
+    ) {
+        let id = output.id;
+        let fctx = match filter_map.get_mut(&id) {
+            Some(fctx) if Arc::ptr_eq(&fctx.cancelled, &output.cancelled) => fctx,
+            _ => return,
+        };
+        if let Some(job) = fctx
+            .jobs
+            .iter_mut()
+            .find(|(range, _)| range.start == output.range.start)
+        {
+            job.1 = Some(output.indices);
+        }
+        let mut committed = Vec::new();
+        while fctx
+            .jobs
+            .front()
+            .map_or(false, |(_, result)| result.is_some())
+        {
+            if let Some((range, Some(mut indices))) = fctx.jobs.pop_front() {
+                committed.append(&mut indices);
+                fctx.offset = range.end;
+            }
+        }
+        let (mut indices, total) = {
+            let frames = frames.read();
+            let matched = committed
+                .iter()
+                .filter_map(|index| frames.get(*index as usize))
+                .map(|frame| &**frame)
+                .filter(|frame| !frame.is_removed())
+                .collect::<Vec<_>>();
+            let restored = Self::restore(&matched, spill, callback);
+            let matched = spill::merge(matched, &restored);
+            Self::process_stats(Some(id), &matched, stats, callback);
+            let indices = matched
+                .iter()
+                .map(|frame| frame.index())
+                .collect::<Vec<_>>();
+            (indices, frames.len())
+        };
+        if !indices.is_empty() {
+            let len = {
+                let mut filtered = filtered.write();
+                let frames = filtered.entry(id).or_insert_with(Vec::new);
+                frames.append(&mut indices);
+                frames.len()
+            };
+            callback.on_filtered_frames_updated(id, len as u32);
+        }
+        callback.on_filter_progress(id, fctx.offset as u32, total as u32);
+    }
+}
+
+impl Drop for EventLoop {
+    fn drop(&mut self) {
+        self.sender.send(Command::Close);
+        self.handle.take().unwrap().join().expect("failed to join");
     }
 }
 
@@ -438,9 +1897,34 @@ impl fmt::Debug for EventLoop {
 
 #[cfg(test)]
 mod tests {
-    use genet_filter::Filter;
+    use frame::Frame;
+    use genet_abi::{
+        attr::{Attr, AttrClass},
+        context::Context,
+        decoder::{Decoder, DecoderBox, ExecType, Metadata, Status, Worker},
+        error::Error,
+        fixed::{Fixed, MutFixed},
+        layer::{Layer, LayerClass, LayerStack, Parent, Payload},
+        result::Result,
+        slice::ByteSlice,
+        token::Token,
+        variant::Variant,
+    };
+    use genet_filter::{ast::Expr, context, function::Function, Filter};
+    use io::{Input, Output};
     use profile::Profile;
-    use store::{Callback, Store};
+    use stats::{Hierarchy, Metric};
+    use std::{
+        ops::Range,
+        sync::{
+            atomic::{self, AtomicBool},
+            mpsc::{self, Receiver, Sender},
+            Arc, Mutex,
+        },
+        thread,
+        time::Duration,
+    };
+    use store::{Callback, Order, Selection, SortedFrames, Store};
 
     #[derive(Clone)]
     struct TestCallback {}
@@ -460,4 +1944,717 @@ mod tests {
         assert_eq!(store.frames(100..0).len(), 0);
         assert_eq!(store.filtered_frames(0, 100..0).len(), 0);
     }
+
+    /// An input reading batches of frames, given as pairs of
+    /// the captured length and the timestamp.
+    ///
+    /// After the last batch, the input stays idle, or fails if `end` is set.
+    #[derive(Debug)]
+    struct BatchInput {
+        batches: Vec<Vec<(usize, Option<f64>)>>,
+        end: bool,
+    }
+
+    impl BatchInput {
+        /// `count` batches of 60 frames of 10 bytes.
+        fn frames(count: usize) -> BatchInput {
+            BatchInput {
+                batches: vec![vec![(10, None); 60]; count],
+                end: false,
+            }
+        }
+
+        /// Batches of 60 frames of `length(index)` bytes.
+        fn lengths(range: Range<usize>) -> BatchInput {
+            let frames = range.map(|index| (length(index), None)).collect::<Vec<_>>();
+            BatchInput {
+                batches: frames.chunks(60).map(|batch| batch.to_vec()).collect(),
+                end: false,
+            }
+        }
+
+        /// Batches of frames of 10 bytes with the timestamps, followed by an error.
+        fn timestamps(batches: Vec<Vec<f64>>) -> BatchInput {
+            BatchInput {
+                batches: batches
+                    .into_iter()
+                    .map(|batch| batch.into_iter().map(|ts| (10, Some(ts))).collect())
+                    .collect(),
+                end: true,
+            }
+        }
+    }
+
+    impl Input for BatchInput {
+        fn read(&mut self) -> Result<Vec<MutFixed<Layer>>> {
+            if self.batches.is_empty() {
+                if self.end {
+                    return Err(Box::new(Error::new("end of input")));
+                }
+                thread::sleep(Duration::from_millis(10));
+                return Ok(Vec::new());
+            }
+            let class = Fixed::new(LayerClass::builder(Token::from("[link-1]")).build());
+            let ts = Fixed::new(AttrClass::builder(Token::from("link.timestamp")).build());
+            Ok(self
+                .batches
+                .remove(0)
+                .into_iter()
+                .map(|(len, timestamp)| {
+                    let mut layer = Layer::new(class.clone(), ByteSlice::from(vec![0u8; len]));
+                    if let Some(value) = timestamp {
+                        layer.add_attr(
+                            Attr::builder(ts.clone())
+                                .value(Variant::Float64(value))
+                                .build(),
+                        );
+                    }
+                    MutFixed::new(layer)
+                })
+                .collect())
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Evicted(Range<u32>),
+        FilterProgress(u32, u32, u32),
+        OutputProgress(u32, u32, u32),
+        OutputDone(u32),
+        IoGraph(Option<u32>, f64, Metric),
+    }
+
+    /// A callback sending the events to a channel.
+    #[derive(Clone)]
+    struct EventCallback {
+        sender: Sender<Event>,
+    }
+
+    impl EventCallback {
+        fn new() -> (EventCallback, Receiver<Event>) {
+            let (sender, receiver) = mpsc::channel();
+            (EventCallback { sender }, receiver)
+        }
+
+        fn send(&self, event: Event) {
+            let _ = self.sender.send(event);
+        }
+    }
+
+    impl Callback for EventCallback {
+        fn on_frames_evicted(&self, range: Range<u32>) {
+            self.send(Event::Evicted(range));
+        }
+
+        fn on_filter_progress(&self, id: u32, processed: u32, total: u32) {
+            self.send(Event::FilterProgress(id, processed, total));
+        }
+
+        fn on_output_progress(&self, id: u32, processed: u32, total: u32) {
+            self.send(Event::OutputProgress(id, processed, total));
+        }
+
+        fn on_output_done(&self, id: u32, _error: Option<Box<::std::error::Error + Send>>) {
+            self.send(Event::OutputDone(id));
+        }
+
+        fn on_io_graph_updated(&self, id: Option<u32>, interval: f64, metric: Metric) {
+            self.send(Event::IoGraph(id, interval, metric));
+        }
+    }
+
+    /// Receives the events until `f` returns a value.
+    fn next_event<T, F: FnMut(Event) -> Option<T>>(receiver: &Receiver<Event>, mut f: F) -> T {
+        loop {
+            if let Some(value) = f(receiver.recv_timeout(Duration::from_secs(5)).unwrap()) {
+                return value;
+            }
+        }
+    }
+
+    /// Polls `cond` for up to 5 seconds.
+    ///
+    /// A timeout is left to the assertions that follow.
+    fn wait_until<F: FnMut() -> bool>(mut cond: F) {
+        for _ in 0..500 {
+            if cond() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn evict(profile: Profile, retained: Range<u32>) {
+        let (callback, receiver) = EventCallback::new();
+        let mut store = Store::new(profile, callback);
+        store.set_filter(1, Filter::compile("true").ok());
+        store.set_input(1, BatchInput::frames(5));
+
+        let mut evicted = 0..0;
+        while evicted.end < retained.start {
+            let range = next_event(&receiver, |event| match event {
+                Event::Evicted(range) => Some(range),
+                _ => None,
+            });
+            assert_eq!(range.start, evicted.end);
+            evicted = evicted.start..range.end;
+        }
+        wait_until(|| store.len() == 300 && store.filtered_frames(1, 0..300).len() == 100);
+        assert_eq!(evicted, 0..retained.start);
+        assert_eq!(store.start(), retained.start as usize);
+        assert_eq!(store.len(), retained.end as usize);
+
+        let frames = store.frames(0..400);
+        let indices = frames.iter().map(|f| f.index()).collect::<Vec<_>>();
+        assert_eq!(indices, retained.clone().collect::<Vec<_>>());
+        assert_eq!(store.frames(0..retained.start as usize).len(), 0);
+        assert_eq!(
+            store.filtered_frames(1, 0..400),
+            retained.clone().collect::<Vec<_>>()
+        );
+
+        let len = u64::from(retained.end - retained.start);
+        for id in &[None, Some(1)] {
+            let stats = store.hierarchy(*id).unwrap();
+            assert_eq!((stats.frames(), stats.bytes()), (len, len * 10));
+            assert_eq!(stats.get("[link-1]").unwrap().frames(), len);
+        }
+        assert!(store.hierarchy(Some(2)).is_none());
+    }
+
+    #[test]
+    fn max_frames() {
+        let mut profile = Profile::new();
+        profile.set_max_frames(100);
+        evict(profile, 200..300);
+    }
+
+    #[test]
+    fn max_bytes() {
+        let mut profile = Profile::new();
+        profile.set_max_bytes(10 * 100);
+        evict(profile, 200..300);
+    }
+
+    #[test]
+    fn filter_progress() {
+        let (callback, receiver) = EventCallback::new();
+        let mut store = Store::new(Profile::new(), callback);
+        store.set_input(1, BatchInput::frames(5));
+        wait_until(|| store.len() == 300);
+        assert_eq!(store.len(), 300);
+
+        store.set_filter(1, Filter::compile("frame.index < 100").ok());
+        store.set_filter(1, Filter::compile("true").ok());
+        loop {
+            let (id, processed, total) = next_event(&receiver, |event| match event {
+                Event::FilterProgress(id, processed, total) => Some((id, processed, total)),
+                _ => None,
+            });
+            assert_eq!((id, total), (1, 300));
+            if processed == total && store.filtered_frames(1, 0..300).len() == 300 {
+                break;
+            }
+        }
+        assert_eq!(
+            store.filtered_frames(1, 0..300),
+            (0..300).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn merge_inputs() {
+        let mut profile = Profile::new();
+        profile.set_merge_inputs(true);
+        let mut store = Store::new(profile, TestCallback {});
+        store.set_input(
+            1,
+            BatchInput::timestamps(vec![vec![0.0, 2.0, 4.0], vec![6.0, 8.0]]),
+        );
+        store.set_input(
+            2,
+            BatchInput::timestamps(vec![vec![1.0, 3.0], vec![5.0, 7.0, 9.0]]),
+        );
+        wait_until(|| store.len() == 10);
+        let frames = store
+            .frames(0..10)
+            .iter()
+            .map(|f| (f.index(), f.timestamp(), f.input()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            frames,
+            (0..10)
+                .map(|i| (i, f64::from(i), Some(i % 2 + 1)))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn merge_idle_input() {
+        let mut profile = Profile::new();
+        profile.set_merge_inputs(true);
+        let mut store = Store::new(profile, TestCallback {});
+        store.set_input(1, BatchInput::frames(0));
+        store.set_input(2, BatchInput::timestamps(vec![vec![0.0, 1.0, 2.0]]));
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(store.len(), 0);
+        wait_until(|| store.len() == 3);
+        let frames = store
+            .frames(0..10)
+            .iter()
+            .map(|f| (f.index(), f.timestamp()))
+            .collect::<Vec<_>>();
+        assert_eq!(frames, vec![(0, 0.0), (1, 1.0), (2, 2.0)]);
+    }
+
+    #[test]
+    fn remove_input() {
+        let mut store = Store::new(Profile::new(), TestCallback {});
+        store.set_filter(1, Filter::compile("true").ok());
+        store.set_sort(2, Token::from("frame.time"), Order::Descending);
+        store.set_input(1, BatchInput::timestamps(vec![vec![0.0, 1.0, 2.0]]));
+        wait_until(|| store.len() == 3);
+        store.set_input(2, BatchInput::timestamps(vec![vec![3.0, 4.0]]));
+        wait_until(|| {
+            store.filtered_frames(1, 0..10).len() == 5 && store.sorted_frames(2, 0..10).len() == 5
+        });
+        assert_eq!(store.frames_for_input(1, 0..10), vec![0, 1, 2]);
+        assert_eq!(store.frames_for_input(2, 0..10), vec![3, 4]);
+        assert_eq!(store.frames_for_input(2, 1..10), vec![4]);
+
+        store.unset_input(1, true);
+        wait_until(|| store.filtered_frames(1, 0..10).len() == 2);
+        assert!(store.frames_for_input(1, 0..10).is_empty());
+        assert_eq!(store.filtered_frames(1, 0..10), vec![3, 4]);
+        assert_eq!(store.sorted_frames(2, 0..10), vec![4, 3]);
+        let frames = store
+            .frames(0..10)
+            .iter()
+            .map(|f| (f.index(), f.is_removed()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            frames,
+            vec![(0, true), (1, true), (2, true), (3, false), (4, false)]
+        );
+        for id in &[None, Some(1)] {
+            assert_eq!(store.hierarchy(*id).unwrap().frames(), 2);
+        }
+    }
+
+    /// An output recording the indices of the written frames.
+    #[derive(Debug)]
+    struct TestOutput {
+        frames: Arc<Mutex<Vec<u32>>>,
+    }
+
+    impl Output for TestOutput {
+        fn write(&mut self, frames: &[&Frame]) -> Result<()> {
+            let mut written = self.frames.lock().unwrap();
+            written.extend(frames.iter().map(|frame| frame.index()));
+            Ok(())
+        }
+
+        fn end(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Pushes a `TestOutput`, and returns the indices it writes.
+    fn push_output(
+        store: &mut Store,
+        id: u32,
+        selection: Selection,
+        follow: bool,
+    ) -> Arc<Mutex<Vec<u32>>> {
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        store.push_output(
+            id,
+            TestOutput {
+                frames: frames.clone(),
+            },
+            selection,
+            follow,
+        );
+        frames
+    }
+
+    #[test]
+    fn output() {
+        let (callback, receiver) = EventCallback::new();
+        let mut store = Store::new(Profile::new(), callback);
+        let followed = push_output(&mut store, 1, Selection::Filter(None), true);
+        store.set_input(1, BatchInput::frames(5));
+        wait_until(|| followed.lock().unwrap().len() == 300);
+        assert_eq!(*followed.lock().unwrap(), (0..300).collect::<Vec<_>>());
+
+        let written = push_output(
+            &mut store,
+            2,
+            Selection::Filter(Filter::compile("frame.index < 100").ok()),
+            false,
+        );
+        let mut progress = None;
+        loop {
+            match receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+                Event::OutputDone(2) => break,
+                Event::OutputProgress(2, processed, total) => progress = Some((processed, total)),
+                _ => {}
+            }
+        }
+        assert_eq!(progress, Some((300, 300)));
+        assert_eq!(*written.lock().unwrap(), (0..100).collect::<Vec<_>>());
+
+        store.close_output(1);
+        while receiver.recv_timeout(Duration::from_secs(5)).unwrap() != Event::OutputDone(1) {}
+    }
+
+    #[test]
+    fn output_eviction() {
+        let mut profile = Profile::new();
+        profile.set_max_frames(10);
+        let mut store = Store::new(profile, TestCallback {});
+        let followed = push_output(&mut store, 1, Selection::Filter(None), true);
+        store.set_input(1, BatchInput::frames(5));
+        wait_until(|| followed.lock().unwrap().len() == 300);
+        assert_eq!(*followed.lock().unwrap(), (0..300).collect::<Vec<_>>());
+    }
+
+    static STALLED: AtomicBool = AtomicBool::new(true);
+
+    #[test]
+    fn output_stalled() {
+        fn stall(_: &context::Context, _: &[Expr]) -> Variant {
+            while STALLED.load(atomic::Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            Variant::Bool(true)
+        }
+
+        let mut profile = Profile::new();
+        profile.set_max_frames(10);
+        profile.add_function(Function::new("stall", 0, stall));
+        let filter = Filter::compile_with("stall()", profile.functions()).ok();
+        let (callback, receiver) = EventCallback::new();
+        let mut store = Store::new(profile, callback);
+        store.set_filter(1, filter);
+        let stalled = push_output(&mut store, 1, Selection::View(1), true);
+        store.set_input(1, BatchInput::frames(5));
+        while receiver.recv_timeout(Duration::from_secs(5)).unwrap() != Event::OutputDone(1) {}
+        wait_until(|| store.len() == 300 && store.start() == 290);
+        STALLED.store(false, atomic::Ordering::Relaxed);
+        assert_eq!(store.start(), 290);
+        assert!(stalled.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn output_selection() {
+        let (callback, receiver) = EventCallback::new();
+        let mut store = Store::new(Profile::new(), callback);
+        store.set_filter(1, Filter::compile("frame.index < 50").ok());
+        store.set_input(1, BatchInput::frames(5));
+        wait_until(|| store.len() == 300 && store.filtered_frames(1, 0..300).len() == 50);
+
+        let ranges = push_output(
+            &mut store,
+            1,
+            Selection::Ranges(vec![250..260, 10..20, 400..500]),
+            false,
+        );
+        let view = push_output(&mut store, 2, Selection::View(1), false);
+        let missing = push_output(&mut store, 3, Selection::View(2), false);
+        let mut done = Vec::new();
+        while done.len() < 3 {
+            if let Event::OutputDone(id) = receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+                done.push(id);
+            }
+        }
+        assert_eq!(
+            *ranges.lock().unwrap(),
+            (10..20).chain(250..260).collect::<Vec<_>>()
+        );
+        assert_eq!(*view.lock().unwrap(), (0..50).collect::<Vec<_>>());
+        assert!(missing.lock().unwrap().is_empty());
+
+        push_output(&mut store, 4, Selection::View(1), true);
+        store.set_filter(1, Filter::compile("frame.index < 10").ok());
+        while receiver.recv_timeout(Duration::from_secs(5)).unwrap() != Event::OutputDone(4) {}
+    }
+
+    #[test]
+    fn spill() {
+        let mut profile = Profile::new();
+        profile.set_max_resident_frames(50);
+        let mut store = Store::new(profile, TestCallback {});
+        store.set_input(1, BatchInput::frames(5));
+        wait_until(|| store.len() == 300);
+        assert_eq!(store.len(), 300);
+
+        store.set_filter(1, Filter::compile("frame.capturedLength == 10").ok());
+        wait_until(|| store.filtered_frames(1, 0..300).len() == 300);
+        assert_eq!(
+            store.filtered_frames(1, 0..300),
+            (0..300).collect::<Vec<_>>()
+        );
+        {
+            let frames = store.frames.read();
+            assert!(frames.get(0).unwrap().is_spilled());
+            assert!(frames.get(0).unwrap().layers().is_empty());
+            assert!(!frames.get(299).unwrap().is_spilled());
+        }
+
+        let frames = store.frames(0..300);
+        assert_eq!(frames.len(), 300);
+        for (index, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.index(), index as u32);
+            assert_eq!(frame.layers().len(), 1);
+            assert_eq!(frame.layers()[0].data().len(), 10);
+        }
+    }
+
+    struct SequenceWorker {
+        count: usize,
+    }
+
+    impl Worker for SequenceWorker {
+        fn decode(
+            &mut self,
+            _ctx: &mut Context,
+            _stack: &LayerStack,
+            parent: &mut Parent,
+        ) -> Result<Status> {
+            if parent.id() != Token::from("[link-1]") {
+                return Ok(Status::Skip);
+            }
+            let id = if self.count == 0 {
+                "[seq-first]"
+            } else {
+                "[seq-next]"
+            };
+            self.count += 1;
+            let class = Fixed::new(LayerClass::builder(Token::from(id)).build());
+            let count = Fixed::new(AttrClass::builder(Token::from("seq.count")).build());
+            let data = (self.count as u32).to_be_bytes().to_vec();
+            let mut layer = Layer::new(class, ByteSlice::from(data.clone()));
+            layer.add_attr(
+                Attr::builder(count)
+                    .value(Variant::UInt64(self.count as u64))
+                    .build(),
+            );
+            layer.add_payload(Payload::new(ByteSlice::from(data), "@data:seq"));
+            parent.add_child(layer);
+            Ok(Status::Done)
+        }
+    }
+
+    #[derive(Clone)]
+    struct SequenceDecoder {}
+
+    impl Decoder for SequenceDecoder {
+        fn new_worker(&self, _ctx: &Context) -> Box<Worker> {
+            Box::new(SequenceWorker { count: 0 })
+        }
+
+        fn metadata(&self) -> Metadata {
+            Metadata {
+                exec_type: ExecType::SerialSync,
+                ..Metadata::default()
+            }
+        }
+    }
+
+    #[test]
+    fn spill_restore() {
+        let capture = |max: Option<usize>| {
+            let mut profile = Profile::new();
+            if let Some(max) = max {
+                profile.set_max_resident_frames(max);
+            }
+            profile.add_decoder(DecoderBox::new(SequenceDecoder {}));
+            let mut store = Store::new(profile, TestCallback {});
+            store.set_input(1, BatchInput::frames(5));
+            wait_until(|| {
+                let frames = store.frames.read();
+                frames.len() == 300
+                    && (max.is_none() || frames.get(249).map_or(false, |frame| frame.is_spilled()))
+            });
+            store
+        };
+        let decoded = |frame: &Frame| {
+            let layers = frame
+                .layers()
+                .iter()
+                .map(|layer| {
+                    let attrs = layer
+                        .attrs()
+                        .iter()
+                        .map(|attr| (attr.id(), attr.try_get(layer).ok()))
+                        .collect::<Vec<_>>();
+                    let payloads = layer
+                        .payloads()
+                        .iter()
+                        .map(|payload| (payload.id(), payload.data().to_vec()))
+                        .collect::<Vec<_>>();
+                    (layer.id(), layer.data().to_vec(), attrs, payloads)
+                })
+                .collect::<Vec<_>>();
+            (frame.index(), layers, frame.tree_indices().to_vec())
+        };
+
+        let resident = capture(None);
+        let expected = resident
+            .frames(0..300)
+            .iter()
+            .map(|frame| decoded(frame))
+            .collect::<Vec<_>>();
+        assert_eq!(expected[1].1[1].0, Token::from("[seq-next]"));
+
+        let spilled = capture(Some(50));
+        assert!(spilled.frames.read().get(10).unwrap().is_spilled());
+        let frames = spilled.frames(10..11);
+        assert_eq!(decoded(&frames[0]), expected[10]);
+        let frames = spilled
+            .frames(0..300)
+            .iter()
+            .map(|frame| decoded(frame))
+            .collect::<Vec<_>>();
+        assert_eq!(frames, expected);
+    }
+
+    #[test]
+    fn spill_stats() {
+        let mut profile = Profile::new();
+        profile.set_max_frames(100);
+        profile.set_max_resident_frames(10);
+        profile.add_decoder(DecoderBox::new(SequenceDecoder {}));
+        let mut store = Store::new(profile, TestCallback {});
+        store.set_filter(1, Filter::compile("true").ok());
+        store.add_io_graph(None, 1.0, Metric::Frames);
+        store.set_input(1, BatchInput::frames(5));
+        wait_until(|| store.start() == 200 && store.filtered_frames(1, 0..300).len() == 100);
+        for id in &[None, Some(1)] {
+            let stats = store.hierarchy(*id).unwrap();
+            assert_eq!(stats.frames(), 100);
+            assert_eq!(stats.get("[link-1]").unwrap().frames(), 100);
+            assert_eq!(stats.get("[seq-next]").unwrap().frames(), 100);
+            assert!(stats.get("[seq-first]").is_none());
+        }
+
+        store.unset_input(1, true);
+        wait_until(|| {
+            store
+                .hierarchy(None)
+                .map_or(false, |stats| stats.frames() == 0)
+        });
+        for id in &[None, Some(1)] {
+            assert_eq!(store.hierarchy(*id).unwrap(), Hierarchy::new());
+        }
+        assert!(store.io_graph(None, 1.0, Metric::Frames).is_empty());
+    }
+
+    fn length(index: usize) -> usize {
+        index * 7 % 13 + 1
+    }
+
+    #[test]
+    fn sort() {
+        let mut store = Store::new(Profile::new(), TestCallback {});
+        store.set_sort(1, Token::from("frame.capturedLength"), Order::Ascending);
+        store.set_filter(2, Filter::compile("frame.capturedLength > 5").ok());
+        store.set_sort(2, Token::from("frame.capturedLength"), Order::Descending);
+        store.set_input(1, BatchInput::lengths(0..120));
+
+        let mut ascending = (0..120).collect::<Vec<usize>>();
+        ascending.sort_by_key(|index| (length(*index), *index));
+        let mut descending = (0..120)
+            .filter(|index| length(*index) > 5)
+            .collect::<Vec<usize>>();
+        descending.sort_by_key(|index| (13 - length(*index), *index));
+        let ascending = ascending.into_iter().map(|i| i as u32).collect::<Vec<_>>();
+        let descending = descending.into_iter().map(|i| i as u32).collect::<Vec<_>>();
+
+        wait_until(|| {
+            store.sorted_frames(1, 0..200).len() == ascending.len()
+                && store.sorted_frames(2, 0..200).len() == descending.len()
+        });
+        assert_eq!(store.sorted_frames(1, 0..200), ascending);
+        assert_eq!(store.sorted_frames(2, 0..200), descending);
+        assert_eq!(store.sorted_frames(1, 10..20), &ascending[10..20]);
+
+        store.unset_sort(1);
+        wait_until(|| store.sorted_frames(1, 0..200).is_empty());
+        assert!(store.sorted_frames(1, 0..200).is_empty());
+    }
+
+    #[test]
+    fn sort_mixed_types() {
+        let mut sorted = SortedFrames::new(Order::Ascending);
+        sorted.push(vec![
+            (Variant::String("a".into()), 0),
+            (Variant::Int64(10), 1),
+            (Variant::Nil, 2),
+            (Variant::UInt64(5), 3),
+        ]);
+        sorted.push(vec![
+            (Variant::Float64(0.5), 4),
+            (Variant::UInt64(100), 5),
+            (Variant::Float64(::std::f64::NAN), 6),
+            (Variant::Int64(-1), 7),
+            (Variant::Float64(1.5), 8),
+        ]);
+        assert_eq!(sorted.len(), 9);
+        sorted.merge();
+        let indices = sorted
+            .keys
+            .iter()
+            .map(|(_, index)| *index)
+            .collect::<Vec<_>>();
+        assert_eq!(indices, vec![7, 4, 8, 3, 1, 5, 6, 0, 2]);
+    }
+
+    #[test]
+    fn io_graph() {
+        let (callback, receiver) = EventCallback::new();
+        let mut store = Store::new(Profile::new(), callback);
+        store.set_filter(1, Filter::compile("frame.capturedLength > 5").ok());
+        store.add_io_graph(None, 1.0, Metric::Bytes);
+        store.add_io_graph(Some(1), 1.0, Metric::Frames);
+        store.add_io_graph(Some(2), 1.0, Metric::Frames);
+        store.set_input(1, BatchInput::lengths(0..120));
+
+        let bytes = (0..120).map(|index| length(index) as f64).sum::<f64>();
+        let frames = (0..120).filter(|index| length(*index) > 5).count() as u64;
+        wait_until(|| {
+            let all = store.io_graph(None, 1.0, Metric::Bytes);
+            let filtered = store.io_graph(Some(1), 1.0, Metric::Frames);
+            all.get(0).map(|b| b.value) == Some(bytes)
+                && filtered.get(0).map(|b| b.frames) == Some(frames)
+        });
+        let updated = receiver.try_iter().collect::<Vec<_>>();
+        let all = store.io_graph(None, 1.0, Metric::Bytes);
+        assert_eq!(all.len(), 1);
+        assert_eq!(
+            (all[0].time, all[0].frames, all[0].value),
+            (0.0, 120, bytes)
+        );
+        let filtered = store.io_graph(Some(1), 1.0, Metric::Frames);
+        assert_eq!(filtered[0].value, frames as f64);
+
+        assert!(updated.contains(&Event::IoGraph(None, 1.0, Metric::Bytes)));
+        assert!(updated.contains(&Event::IoGraph(Some(1), 1.0, Metric::Frames)));
+        assert!(!updated.contains(&Event::IoGraph(Some(2), 1.0, Metric::Frames)));
+        assert!(store.io_graph(Some(2), 1.0, Metric::Frames).is_empty());
+        assert!(store.io_graph(None, 2.0, Metric::Bytes).is_empty());
+
+        store.remove_io_graph(None, 1.0, Metric::Bytes);
+        wait_until(|| store.io_graph(None, 1.0, Metric::Bytes).is_empty());
+        assert!(store.io_graph(None, 1.0, Metric::Bytes).is_empty());
+        receiver.try_iter().count();
+        store.set_input(2, BatchInput::lengths(60..120));
+        wait_until(|| store.len() == 180);
+        assert_eq!(store.len(), 180);
+        assert!(!receiver.try_iter().any(|event| match event {
+            Event::IoGraph(None, _, _) => true,
+            _ => false,
+        }));
+    }
 }
@@ -37,6 +37,14 @@ class Frame {
     return this._frame.index
   }
 
+  get input () {
+    return this._frame.input
+  }
+
+  get removed () {
+    return this._frame.removed
+  }
+
   get root () {
     if (!this._root) {
       [this._root] = treefy(this._frame.layers, this._frame.treeIndices)
@@ -75,8 +83,22 @@ class Session extends EventEmitter {
         case 'async_frames':
           this._status.asyncFrames = event.length
           break
+        case 'evicted':
+          this._status.start = event.end
+          break
         case 'filtered_frames':
-          this._status.filters[Token.string(event.id)] =
+          this._status.filters[Token.string(event.id)] = Object.assign({},
+            this._status.filters[Token.string(event.id)],
+            { frames: event.length })
+          break
+        case 'filter_progress':
+          this._status.filters[Token.string(event.id)] = Object.assign(
+            { frames: 0 },
+            this._status.filters[Token.string(event.id)],
+            { processed: event.processed, total: event.total })
+          break
+        case 'sorted_frames':
+          this._status.sorts[Token.string(event.id)] =
             { frames: event.length }
           break
         case 'error':
@@ -90,7 +112,9 @@ class Session extends EventEmitter {
     this._streamReaders = new Set()
     this._status = {
       filters: {},
+      sorts: {},
       frames: 0,
+      start: 0,
       asyncFrames: 0,
       stream: false,
     }
@@ -110,6 +134,43 @@ class Session extends EventEmitter {
     return this._sess.filteredFrames(Token.get(id), start, end)
   }
 
+  framesForInput (handle, start, end) {
+    return this._sess.framesForInput(handle, start, end)
+  }
+
+  sortedFrames (id, start, end) {
+    return this._sess.sortedFrames(Token.get(id), start, end)
+  }
+
+  hierarchy (id) {
+    const json = (typeof id === 'undefined')
+      ? this._sess.hierarchy()
+      : this._sess.hierarchy(Token.get(id))
+    return json === null ? null : JSON.parse(json)
+  }
+
+  conversations (id) {
+    const json = (typeof id === 'undefined')
+      ? this._sess.conversations()
+      : this._sess.conversations(Token.get(id))
+    return json === null ? null : JSON.parse(json)
+  }
+
+  ioGraph (id, interval, metric = 'frames') {
+    const token = (typeof id === 'string') ? Token.get(id) : 0
+    return JSON.parse(this._sess.ioGraph(token, interval, metric))
+  }
+
+  addIoGraph (id, interval, metric = 'frames') {
+    const token = (typeof id === 'string') ? Token.get(id) : 0
+    this._sess.addIoGraph(token, interval, metric)
+  }
+
+  removeIoGraph (id, interval, metric = 'frames') {
+    const token = (typeof id === 'string') ? Token.get(id) : 0
+    this._sess.removeIoGraph(token, interval, metric)
+  }
+
   get status () {
     return this._status
   }
@@ -121,6 +182,13 @@ class Session extends EventEmitter {
     }
   }
 
+  setSort (id, attr = '', order = 'asc') {
+    this._sess.setSort(Token.get(id), attr, order)
+    if (attr === '') {
+      Reflect.deleteProperty(this._status.sorts, id)
+    }
+  }
+
   createReader (id, arg = {}) {
     const handle = this._sess.createReader(id, JSON.stringify(arg))
     if (handle === 0) {
@@ -129,6 +197,7 @@ class Session extends EventEmitter {
     const disposable = new Disposable(() => {
       this._sess.closeReader(handle)
     })
+    disposable.handle = handle
     disposable.promise = new Promise((res, rej) => {
       this.on('update', (event) => {
         if (event.id === handle && event.type === 'output') {
@@ -143,18 +212,23 @@ class Session extends EventEmitter {
     return disposable
   }
 
-  async createWriter (id, arg = {}, filter = '') {
+  async createWriter (id, arg = {}, selection = '', follow = false) {
+    let sel = selection
+    if (typeof selection === 'object' && !Array.isArray(selection)) {
+      sel = Token.get(selection.view)
+    }
     const handle = this._sess.createWriter(
-      id, JSON.stringify(arg), filter)
+      id, JSON.stringify(arg), sel, follow)
     if (handle === 0) {
       throw new Error(`failed to invoke writer: ${id}`)
     }
     const disposable = new Disposable(() => {
-      this._sess.closeReader(handle)
+      this._sess.closeWriter(handle)
     })
+    disposable.handle = handle
     disposable.promise = new Promise((res, rej) => {
       this.on('update', (event) => {
-        if (event.id === handle && event.type === 'input') {
+        if (event.id === handle && event.type === 'output') {
           if (event.error === null) {
             res()
           } else {
@@ -166,6 +240,10 @@ class Session extends EventEmitter {
     return disposable
   }
 
+  closeReader (handle, removeFrames = false) {
+    this._sess.closeReader(handle, removeFrames)
+  }
+
   regiterStreamReader (id, arg = {}) {
     const reader = {
       id,
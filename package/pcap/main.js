@@ -29,6 +29,12 @@ class PcapView {
     if (Number.isInteger(snaplen)) {
       args.push('-l', `${snaplen}`)
     }
+    const filter = genet.config.get('@genet/pcap.captureFilter')
+    if (typeof filter === 'string' && filter.trim() !== '') {
+      args.push('-f', filter)
+      const macros = genet.config.get('_.filter.macros') || {}
+      args.push('--macros', JSON.stringify(macros))
+    }
     const stream = {
       cmd: cli,
       args,
@@ -1,12 +1,15 @@
 #![windows_subsystem = "windows"]
 
 extern crate clap;
+extern crate genet_filter;
 extern crate pcap;
 extern crate serde_json;
 
 use clap::{App, Arg, SubCommand};
-use pcap::Pcap;
+use genet_filter::{function::Registry, Filter};
+use pcap::{Instruction, Pcap};
 use std::{
+    collections::HashMap,
     io::{stdout, Write},
     sync::mpsc::RecvTimeoutError,
     time::Duration,
@@ -25,6 +28,19 @@ fn main() {
                 .short("l")
                 .help("Sets the snapshot length")
                 .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("filter")
+                .short("f")
+                .long("filter")
+                .help("Sets the capture filter")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("macros")
+                .long("macros")
+                .help("Sets the filter macros as a JSON object")
+                .takes_value(true),
         );
 
     let status = SubCommand::with_name("devices");
@@ -62,7 +78,46 @@ fn main() {
             .value_of("snaplen")
             .and_then(|v| v.parse().ok())
             .unwrap_or(2048);
-        let recv = match pcap.start(&matches.value_of("DEVICE").unwrap(), snaplen) {
+        let device = matches.value_of("DEVICE").unwrap();
+        let filter = match matches.value_of("filter") {
+            Some(filter) => {
+                let link = match pcap
+                    .devices()
+                    .unwrap_or_else(|| vec![])
+                    .iter()
+                    .find(|dev| dev.id() == device)
+                {
+                    Some(dev) => dev.link() as u32,
+                    None => {
+                        eprintln!("error: device not found: {}", device);
+                        std::process::exit(1)
+                    }
+                };
+                let mut functions = Registry::default();
+                if let Some(macros) = matches.value_of("macros") {
+                    match serde_json::from_str::<HashMap<String, String>>(macros) {
+                        Ok(macros) => {
+                            for (name, filter) in macros {
+                                functions.add_macro(&name, &filter);
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("error: invalid macros: {}", e);
+                            std::process::exit(1)
+                        }
+                    }
+                }
+                match compile_filter(filter, link, &functions) {
+                    Ok(prog) => Some(prog),
+                    Err(e) => {
+                        eprintln!("error: {}", e);
+                        std::process::exit(1)
+                    }
+                }
+            }
+            None => None,
+        };
+        let recv = match pcap.start(device, snaplen, filter.as_ref().map(|f| f.as_slice())) {
             Ok(r) => r,
             Err(e) => {
                 eprintln!("error: {:?}", e);
@@ -83,3 +138,21 @@ fn main() {
         }
     }
 }
+
+fn compile_filter(
+    filter: &str,
+    link: u32,
+    functions: &Registry,
+) -> Result<Vec<Instruction>, String> {
+    let filter = Filter::compile_with(filter, functions).map_err(|e| e.to_string())?;
+    let prog = filter.to_bpf(link).map_err(|e| e.to_string())?;
+    Ok(prog
+        .into_iter()
+        .map(|insn| Instruction {
+            code: insn.code,
+            jt: insn.jt,
+            jf: insn.jf,
+            k: insn.k,
+        })
+        .collect())
+}
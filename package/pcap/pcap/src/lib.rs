@@ -26,12 +26,23 @@ pub struct Header {
     pub ts_usec: u32,
 }
 
+/// A classic BPF instruction, laid out as `struct bpf_insn`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
 #[derive(Debug)]
 pub enum Error {
     NoPermission,
     DLLNotFound,
     DLLFuncNotFound,
     OpenFailed(String),
+    FilterFailed(String),
 }
 
 pub type FrameReceiver = Receiver<(Header, Box<[u8]>)>;
@@ -71,7 +82,15 @@ impl Pcap {
         true
     }
 
-    pub fn start(&mut self, ifs: &str, snaplen: u32) -> Result<FrameReceiver, Error> {
+    /// Starts capturing on the interface.
+    ///
+    /// If `filter` is given, packets are filtered in the kernel by the BPF program.
+    pub fn start(
+        &mut self,
+        ifs: &str,
+        snaplen: u32,
+        filter: Option<&[Instruction]>,
+    ) -> Result<FrameReceiver, Error> {
         use std::{ffi::CString, slice};
         let (send, recv) = channel();
         let ifs = CString::new(ifs).unwrap();
@@ -104,6 +123,19 @@ impl Pcap {
                 return Err(Error::OpenFailed(msg));
             }
 
+            if let Some(filter) = filter {
+                let mut insns = filter.to_vec();
+                let mut prog = ffi::BpfProgram {
+                    bf_len: insns.len() as u32,
+                    bf_insns: insns.as_mut_ptr(),
+                };
+                if (self.syms.pcap_setfilter)(pcap, &mut prog) < 0 {
+                    let msg = ffi::getstr((self.syms.pcap_geterr)(pcap));
+                    (self.syms.pcap_close)(pcap);
+                    return Err(Error::FilterFailed(msg));
+                }
+            }
+
             self.handles.push(pcap);
 
             extern "C" fn handler(
@@ -515,6 +547,8 @@ mod ffi {
         ) -> c_int,
         pub pcap_breakloop: unsafe extern "C" fn(pcap: *mut Pcap),
         pub pcap_close: unsafe extern "C" fn(pcap: *mut Pcap),
+        pub pcap_setfilter: unsafe extern "C" fn(pcap: *mut Pcap, fp: *mut BpfProgram) -> c_int,
+        pub pcap_geterr: unsafe extern "C" fn(pcap: *mut Pcap) -> *mut c_char,
     }
 
     impl Symbols {
@@ -529,6 +563,8 @@ mod ffi {
                 pcap_loop,
                 pcap_breakloop,
                 pcap_close,
+                pcap_setfilter,
+                pcap_geterr,
             })
         }
 
@@ -546,6 +582,8 @@ mod ffi {
             let pcap_loop;
             let pcap_breakloop;
             let pcap_close;
+            let pcap_setfilter;
+            let pcap_geterr;
 
             {
                 let pcap_findalldevs_: libloading::Symbol<
@@ -580,6 +618,12 @@ mod ffi {
                 let pcap_close_: libloading::Symbol<
                     unsafe extern "C" fn(pcap: *mut Pcap),
                 >;
+                let pcap_setfilter_: libloading::Symbol<
+                    unsafe extern "C" fn(pcap: *mut Pcap, fp: *mut BpfProgram) -> c_int,
+                >;
+                let pcap_geterr_: libloading::Symbol<
+                    unsafe extern "C" fn(pcap: *mut Pcap) -> *mut c_char,
+                >;
 
                 unsafe {
                     pcap_findalldevs_ = lib
@@ -603,6 +647,12 @@ mod ffi {
                     pcap_breakloop_ = lib
                         .get(b"pcap_breakloop")
                         .map_err(|_| super::Error::DLLFuncNotFound)?;
+                    pcap_setfilter_ = lib
+                        .get(b"pcap_setfilter")
+                        .map_err(|_| super::Error::DLLFuncNotFound)?;
+                    pcap_geterr_ = lib
+                        .get(b"pcap_geterr")
+                        .map_err(|_| super::Error::DLLFuncNotFound)?;
                 }
 
                 pcap_findalldevs = *pcap_findalldevs_.deref();
@@ -612,6 +662,8 @@ mod ffi {
                 pcap_loop = *pcap_loop_.deref();
                 pcap_breakloop = *pcap_breakloop_.deref();
                 pcap_close = *pcap_close_.deref();
+                pcap_setfilter = *pcap_setfilter_.deref();
+                pcap_geterr = *pcap_geterr_.deref();
             }
 
             Ok(Symbols {
@@ -623,6 +675,8 @@ mod ffi {
                 pcap_loop,
                 pcap_breakloop,
                 pcap_close,
+                pcap_setfilter,
+                pcap_geterr,
             })
         }
     }
@@ -658,6 +712,12 @@ mod ffi {
         pub comment: *mut c_char,
     }
 
+    #[repr(C)]
+    pub(crate) struct BpfProgram {
+        pub bf_len: u32,
+        pub bf_insns: *mut super::Instruction,
+    }
+
     pub(crate) type PcapHandler = extern "C" fn(*mut c_uchar, *const PcapPkthdr, *const c_uchar);
 
     #[cfg(not(target_os = "windows"))]
@@ -681,6 +741,8 @@ mod ffi {
         ) -> c_int;
         fn pcap_breakloop(pcap: *mut Pcap);
         fn pcap_close(pcap: *mut Pcap);
+        fn pcap_setfilter(pcap: *mut Pcap, fp: *mut BpfProgram) -> c_int;
+        fn pcap_geterr(pcap: *mut Pcap) -> *mut c_char;
     }
 }
 
@@ -35,6 +35,37 @@ export default {
       maximum: 8,
       default: 0,
     },
+    '_.session.maxFrames': {
+      description: 'Evict the oldest frames beyond this number (0 for unlimited)',
+      type: 'integer',
+      minimum: 0,
+      default: 0,
+    },
+    '_.session.maxBytes': {
+      description: 'Evict the oldest frames beyond this number of captured bytes (0 for unlimited)',
+      type: 'integer',
+      minimum: 0,
+      default: 0,
+    },
+    '_.session.maxResidentFrames': {
+      description: 'Spill older decoded frames to a temporary file beyond this number (0 to keep all in memory)',
+      type: 'integer',
+      minimum: 0,
+      default: 0,
+    },
+    '_.session.mergeInputs': {
+      description: 'Order the frames of multiple inputs by timestamp',
+      type: 'boolean',
+      default: false,
+    },
+    '_.filter.macros': {
+      description: 'Named filter snippets referred to as @name',
+      type: 'object',
+      additionalProperties: {
+        type: 'string',
+      },
+      default: {},
+    },
     '_.dev.tabReloading': {
       description: 'Touch $HOME/.genet/.reload to reload all tabs',
       type: 'boolean',
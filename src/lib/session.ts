@@ -96,9 +96,16 @@ export default class Session extends EventEmitter {
   async create() {
     const profile = new native.Session.Profile()
     profile.concurrency = genet.config.get('_.decoder.concurrency')
+    profile.maxFrames = genet.config.get('_.session.maxFrames')
+    profile.maxBytes = genet.config.get('_.session.maxBytes')
+    profile.maxResidentFrames = genet.config.get('_.session.maxResidentFrames')
+    profile.mergeInputs = genet.config.get('_.session.mergeInputs')
     for (const [key, value] of Object.entries(this._config.toJSON())) {
       profile.setConfig(key, JSON.stringify(value))
     }
+    for (const id of this._tokens.keys()) {
+      profile.addToken(id)
+    }
     for (const file of this._libs) {
       try {
         profile.loadLibrary(file)
@@ -118,7 +118,11 @@ export default class FrameListView {
             [index] = sess.filteredFrames('main', index, index + 1)
           }
           const [frame] = sess.frames(index, index + 1)
-          this.dummyItem.setAttribute('data-layer', frame.primary.id)
+          if (frame && !frame.removed) {
+            this.dummyItem.setAttribute('data-layer', frame.primary.id)
+          } else {
+            this.dummyItem.removeAttribute('data-layer')
+          }
           const [red, green, blue] =
             parseColor(getComputedStyle(this.dummyItem)
               .getPropertyValue('background-color')).rgb
@@ -151,12 +155,14 @@ export default class FrameListView {
         'main', startIndex, startIndex + visibleItems)
     const indices = status.filters.main
       ? filteredFrames
-      : new Array(visibleItems).fill(0)
-        .map((_val, index) => startIndex + index)
+      : vnode.attrs.sess.frames(startIndex, startIndex + visibleItems)
+        .filter((frame) => !frame.removed)
+        .map((frame) => frame.index)
     const items = indices.map((seq, index) => {
+      const position = status.filters.main ? startIndex + index : seq
       const itemStyle = {
         height: `${this.itemHeight}px`,
-        top: `${(index + startIndex) * this.itemHeight}px`,
+        top: `${position * this.itemHeight}px`,
       }
       return m(FrameView, {
         style: itemStyle,